@@ -0,0 +1,95 @@
+use std::hint::black_box;
+
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, Criterion, Throughput,
+};
+use pis::x86::{X86CpuMode, X86Ctx, X86SegmentDefaultOperandSize};
+
+/// a mix of common instructions, spread over the one byte and two byte opcode maps.
+const CODE: &[&[u8]] = &[
+    // push rbp
+    &[0x55],
+    // mov rbp, rsp
+    &[0x48, 0x89, 0xe5],
+    // sub rsp, 0x20
+    &[0x48, 0x83, 0xec, 0x20],
+    // mov dword [rbp-4], edi
+    &[0x89, 0x7d, 0xfc],
+    // mov eax, dword [rbp-4]
+    &[0x8b, 0x45, 0xfc],
+    // lea rcx, [rax+rax*2+8]
+    &[0x48, 0x8d, 0x4c, 0x40, 0x08],
+    // movzx edx, byte [rcx]
+    &[0x0f, 0xb6, 0x11],
+    // imul eax, edx
+    &[0x0f, 0xaf, 0xc2],
+    // test eax, eax
+    &[0x85, 0xc0],
+    // sete cl
+    &[0x0f, 0x94, 0xc1],
+    // cmovne eax, edx
+    &[0x0f, 0x45, 0xc2],
+    // shl rax, 3
+    &[0x48, 0xc1, 0xe0, 0x03],
+    // xor eax, 0x1234
+    &[0x35, 0x34, 0x12, 0x00, 0x00],
+    // jz .+0x10
+    &[0x74, 0x10],
+    // call .+0x100
+    &[0xe8, 0x00, 0x01, 0x00, 0x00],
+    // mov eax, 1
+    &[0xb8, 0x01, 0x00, 0x00, 0x00],
+    // add rsp, 0x20
+    &[0x48, 0x83, 0xc4, 0x20],
+    // pop rbp
+    &[0x5d],
+    // ret
+    &[0xc3],
+];
+
+/// short instructions with small translations, so that the time spent dispatching the opcode is a larger part of the
+/// decoding time.
+const SHORT_CODE: &[&[u8]] = &[
+    // nop
+    &[0x90],
+    // clc
+    &[0xf8],
+    // cld
+    &[0xfc],
+    // ret
+    &[0xc3],
+    // push rbx
+    &[0x53],
+    // pop rbx
+    &[0x5b],
+    // syscall
+    &[0x0f, 0x05],
+    // nop dword [rax]
+    &[0x0f, 0x1f, 0x00],
+];
+
+fn bench_code(group: &mut BenchmarkGroup<WallTime>, ctx: &X86Ctx, name: &str, code: &[&[u8]]) {
+    group.throughput(Throughput::Elements(code.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for insn in code {
+                black_box(ctx.decode(black_box(insn)).unwrap());
+            }
+        })
+    });
+}
+
+fn decode(c: &mut Criterion) {
+    let ctx = X86Ctx {
+        cpu_mode: X86CpuMode::LongMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    };
+    let mut group = c.benchmark_group("decode");
+    bench_code(&mut group, &ctx, "mixed", CODE);
+    bench_code(&mut group, &ctx, "short", SHORT_CODE);
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -5,9 +5,12 @@ use pis::{
 
 fn main() {
     let ctx = X86Ctx {
-        cpu_mode: X86CpuMode::ProtectedMode,
+        cpu_mode: X86CpuMode::LongMode,
         code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
     };
-    let res = ctx.translate(&[0x41, 0x51]);
-    println!("{}", res);
+    match ctx.translate(&[0x41, 0x51]) {
+        Ok(result) => println!("{}", result.translation),
+        Err(err) => println!("failed to translate: {:?}", err),
+    }
 }
@@ -0,0 +1,214 @@
+use crate::{
+    ArchCtx, Opcode, Operand, OperandAddr, OperandSize, RegFile, RegGroup, TranslateError,
+    TranslationBuilder, TranslationResult,
+};
+
+/// the distance between the offsets of consecutive registers in the register space. the 31 general purpose registers are
+/// stored one after the other starting at offset 0, in the order of their encoding, followed by the stack pointer.
+pub const XREG_STEP: u64 = 8;
+
+define_reg_operands! {
+    XREG_STEP, B8,
+    X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, X11, X12, X13, X14, X15,
+    X16, X17, X18, X19, X20, X21, X22, X23, X24, X25, X26, X27, X28, X29, X30, SP
+}
+
+/// the registers of aarch64, laid out as described in [`XREG_STEP`]. the 32 bit registers are the low halves of the 64 bit
+/// registers.
+pub const REG_FILE: RegFile = RegFile {
+    groups: &[
+        RegGroup {
+            names: &[
+                "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12",
+                "x13", "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24",
+                "x25", "x26", "x27", "x28", "x29", "x30", "sp",
+            ],
+            first_offset: 0,
+            step: XREG_STEP,
+            size: OperandSize::B8,
+        },
+        RegGroup {
+            names: &[
+                "w0", "w1", "w2", "w3", "w4", "w5", "w6", "w7", "w8", "w9", "w10", "w11", "w12",
+                "w13", "w14", "w15", "w16", "w17", "w18", "w19", "w20", "w21", "w22", "w23", "w24",
+                "w25", "w26", "w27", "w28", "w29", "w30", "wsp",
+            ],
+            first_offset: 0,
+            step: XREG_STEP,
+            size: OperandSize::B4,
+        },
+    ],
+};
+
+/// the length of an instruction in bytes.
+pub const INSN_LEN: usize = 4;
+
+/// the register index which encodes either the stack pointer or the zero register, depending on the instruction.
+const SP_OR_ZR_INDEX: u32 = 31;
+
+/// returns the operand of the register with the given 5 bit index, with the given size. an index of 31 is the stack
+/// pointer.
+fn xreg_operand(index: u32, size: OperandSize) -> Operand {
+    Operand::reg(index as u64 * XREG_STEP, size)
+}
+
+/// the fields of an encoded instruction which are shared by most of the instruction encodings.
+struct EncodedInsn(u32);
+impl EncodedInsn {
+    fn rd(&self) -> u32 {
+        self.0 & 0x1f
+    }
+    fn rn(&self) -> u32 {
+        (self.0 >> 5) & 0x1f
+    }
+    fn rm(&self) -> u32 {
+        (self.0 >> 16) & 0x1f
+    }
+    /// the 12 bit unsigned immediate of the instructions which have one.
+    fn imm12(&self) -> u64 {
+        ((self.0 >> 10) & 0xfff) as u64
+    }
+    /// the operand size selected by the `sf` bit.
+    fn sf_size(&self) -> OperandSize {
+        if self.0 >> 31 != 0 {
+            OperandSize::B8
+        } else {
+            OperandSize::B4
+        }
+    }
+}
+
+pub struct Aarch64Ctx;
+impl Aarch64Ctx {
+    /// returns the operand of the given source register, where an index of 31 encodes the zero register.
+    fn src_reg_or_zr(&self, index: u32, size: OperandSize) -> Operand {
+        if index == SP_OR_ZR_INDEX {
+            Operand::zero(size)
+        } else {
+            xreg_operand(index, size)
+        }
+    }
+    /// emits instructions which write the given value to the given destination register, where an index of 31 encodes
+    /// either the stack pointer or the zero register, to which writes are discarded.
+    ///
+    /// 32 bit writes zero the upper half of the register.
+    fn write_reg(&self, index: u32, is_sp: bool, value: Operand, builder: &mut TranslationBuilder) {
+        if index == SP_OR_ZR_INDEX && !is_sp {
+            return;
+        }
+        let dst = xreg_operand(index, value.size);
+        if dst != value {
+            builder.mov(dst.clone(), value);
+        }
+        if dst.size == OperandSize::B4 {
+            builder.mov(
+                Operand {
+                    addr: OperandAddr {
+                        offset: dst.addr.offset + 4,
+                        ..dst.addr
+                    },
+                    ..dst
+                },
+                Operand::zero(OperandSize::B4),
+            );
+        }
+    }
+    /// translates an `add` with an immediate, which may be shifted left by 12 bits.
+    fn translate_add_imm(&self, insn: &EncodedInsn, builder: &mut TranslationBuilder) {
+        let size = insn.sf_size();
+        let shift = if (insn.0 >> 22) & 1 != 0 { 12 } else { 0 };
+        let result = builder.alloc_tmp(size);
+        builder.mov(result.clone(), xreg_operand(insn.rn(), size));
+        builder.add(
+            result.clone(),
+            Operand::constant(insn.imm12() << shift, size),
+        );
+        self.write_reg(insn.rd(), true, result, builder);
+    }
+    /// translates a `ldr` or `str` with an unsigned offset, which is scaled by the access size.
+    fn translate_ldr_str(
+        &self,
+        insn: &EncodedInsn,
+        size: OperandSize,
+        is_load: bool,
+        builder: &mut TranslationBuilder,
+    ) {
+        let addr = builder.alloc_tmp(OperandSize::B8);
+        // an index of 31 encodes the stack pointer as the base register.
+        builder.mov(addr.clone(), xreg_operand(insn.rn(), OperandSize::B8));
+        let offset = insn.imm12() * size.bytes() as u64;
+        if offset != 0 {
+            builder.add(addr.clone(), Operand::constant(offset, OperandSize::B8));
+        }
+        if is_load {
+            let value = builder.alloc_tmp(size);
+            builder.load(value.clone(), addr);
+            self.write_reg(insn.rd(), false, value, builder);
+        } else {
+            builder.store(addr, self.src_reg_or_zr(insn.rd(), size));
+        }
+    }
+}
+impl ArchCtx for Aarch64Ctx {
+    fn reg_file(&self) -> &'static RegFile {
+        &REG_FILE
+    }
+
+    fn translate_at(&self, code: &[u8], addr: u64) -> Result<TranslationResult, TranslateError> {
+        if code.is_empty() {
+            return Err(TranslateError::UnexpectedEof);
+        }
+        let Some(encoded) = code.first_chunk::<INSN_LEN>() else {
+            return Err(TranslateError::TruncatedInstruction);
+        };
+        let insn = EncodedInsn(u32::from_le_bytes(*encoded));
+
+        let mut builder = TranslationBuilder::new();
+        match insn.0 {
+            // add (immediate)
+            _ if insn.0 & 0x7f80_0000 == 0x1100_0000 => self.translate_add_imm(&insn, &mut builder),
+            // mov (register), which is an alias of `orr` with the zero register
+            _ if insn.0 & 0x7fe0_ffe0 == 0x2a00_03e0 => {
+                let size = insn.sf_size();
+                self.write_reg(
+                    insn.rd(),
+                    false,
+                    self.src_reg_or_zr(insn.rm(), size),
+                    &mut builder,
+                );
+            }
+            // ldr (immediate, unsigned offset)
+            _ if insn.0 & 0xffc0_0000 == 0xf940_0000 => {
+                self.translate_ldr_str(&insn, OperandSize::B8, true, &mut builder)
+            }
+            _ if insn.0 & 0xffc0_0000 == 0xb940_0000 => {
+                self.translate_ldr_str(&insn, OperandSize::B4, true, &mut builder)
+            }
+            // str (immediate, unsigned offset)
+            _ if insn.0 & 0xffc0_0000 == 0xf900_0000 => {
+                self.translate_ldr_str(&insn, OperandSize::B8, false, &mut builder)
+            }
+            _ if insn.0 & 0xffc0_0000 == 0xb900_0000 => {
+                self.translate_ldr_str(&insn, OperandSize::B4, false, &mut builder)
+            }
+            // b
+            _ if insn.0 & 0xfc00_0000 == 0x1400_0000 => {
+                // sign extend the 26 bit word offset, and scale it to bytes.
+                let offset = (((insn.0 << 6) as i32) >> 4) as i64;
+                builder.insn(
+                    Opcode::Jump,
+                    Operand::constant(addr.wrapping_add(offset as u64), OperandSize::B8),
+                    Operand::zero(OperandSize::B1),
+                );
+            }
+            // the most significant byte contains the bits which select the instruction class.
+            _ => return Err(TranslateError::UnknownOpcode((insn.0 >> 24) as u8)),
+        }
+        let translation = builder.build();
+        debug_assert_eq!(translation.validate(), Ok(()));
+        Ok(TranslationResult {
+            translation,
+            bytes_consumed: INSN_LEN,
+        })
+    }
+}
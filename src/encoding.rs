@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+
+use crate::{
+    Cond, Insn, Intrinsic, Opcode, Operand, OperandAddr, OperandSize, OperandSpace, Translation,
+    TRANSLATION_MAX_INSNS,
+};
+
+const ENCODED_OPCODE_LEN: usize = 3;
+const ENCODED_OPERAND_LEN: usize = 10;
+const ENCODED_FLAGS_LEN: usize = 1;
+
+/// the length of a single encoded instruction.
+pub const ENCODED_INSN_LEN: usize =
+    ENCODED_OPCODE_LEN + 2 * ENCODED_OPERAND_LEN + ENCODED_FLAGS_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodeError {
+    /// the length of the encoded data is not a multiple of the length of an encoded instruction.
+    InvalidLength,
+    /// the encoded data contains more than [`TRANSLATION_MAX_INSNS`] instructions.
+    TooManyInsns,
+    /// the encoded opcode, along with its parameters, is invalid.
+    InvalidOpcode([u8; ENCODED_OPCODE_LEN]),
+    /// the encoded operand space tag is invalid.
+    InvalidOperandSpace(u8),
+    /// the encoded operand size is invalid.
+    InvalidOperandSize(u8),
+    /// the encoded instruction flags byte is invalid.
+    InvalidFlags(u8),
+}
+
+impl Translation {
+    /// encodes this translation using a compact binary encoding.
+    ///
+    /// each instruction is encoded using a fixed layout of [`ENCODED_INSN_LEN`] bytes. the first 3 bytes encode the opcode,
+    /// as an opcode tag followed by 2 bytes for the parameters of opcodes which have them, like the kind of an intrinsic.
+    /// they are followed by the 2 operands, each encoded as a space tag byte, an 8 byte little endian offset, and a byte
+    /// holding the size of the operand in bytes. the last byte is 1 if the instruction is atomic, and 0 otherwise.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.insns.len() * ENCODED_INSN_LEN);
+        for insn in &self.insns {
+            encoded.extend_from_slice(&encode_opcode(insn.opcode));
+            for operand in &insn.operands {
+                encoded.push(encode_space(operand.addr.space));
+                encoded.extend_from_slice(&operand.addr.offset.to_le_bytes());
+                encoded.push(operand.size.bytes() as u8);
+            }
+            encoded.push(insn.is_atomic as u8);
+        }
+        encoded
+    }
+
+    /// decodes a translation which was encoded using [`Translation::encode`].
+    pub fn decode(bytes: &[u8]) -> Result<Translation, DecodeError> {
+        if !bytes.len().is_multiple_of(ENCODED_INSN_LEN) {
+            return Err(DecodeError::InvalidLength);
+        }
+        if bytes.len() / ENCODED_INSN_LEN > TRANSLATION_MAX_INSNS {
+            return Err(DecodeError::TooManyInsns);
+        }
+        let mut translation = Translation::new();
+        for encoded_insn in bytes.chunks_exact(ENCODED_INSN_LEN) {
+            let (encoded_opcode, encoded_operands) = encoded_insn.split_at(ENCODED_OPCODE_LEN);
+            let (first_operand, rest) = encoded_operands.split_at(ENCODED_OPERAND_LEN);
+            let (second_operand, encoded_flags) = rest.split_at(ENCODED_OPERAND_LEN);
+            let mut insn = Insn::new(
+                decode_opcode(encoded_opcode.try_into().unwrap())?,
+                decode_operand(first_operand)?,
+                decode_operand(second_operand)?,
+            );
+            insn.is_atomic = match encoded_flags[0] {
+                0 => false,
+                1 => true,
+                flags => return Err(DecodeError::InvalidFlags(flags)),
+            };
+            translation.insns.push(insn);
+        }
+        Ok(translation)
+    }
+}
+
+fn encode_opcode(opcode: Opcode) -> [u8; ENCODED_OPCODE_LEN] {
+    match opcode {
+        Opcode::Move => [0, 0, 0],
+        Opcode::Add => [1, 0, 0],
+        Opcode::Sub => [2, 0, 0],
+        Opcode::And => [3, 0, 0],
+        Opcode::Or => [4, 0, 0],
+        Opcode::Xor => [5, 0, 0],
+        Opcode::Shl => [6, 0, 0],
+        Opcode::Shr => [7, 0, 0],
+        Opcode::Sar => [8, 0, 0],
+        Opcode::Store => [9, 0, 0],
+        Opcode::Load => [10, 0, 0],
+        Opcode::SetCond(cond) => [11, encode_cond(cond), 0],
+        Opcode::Branch => [12, 0, 0],
+        Opcode::Jump => [13, 0, 0],
+        Opcode::Call => [14, 0, 0],
+        Opcode::Return => [15, 0, 0],
+        Opcode::Intrinsic(intrinsic) => {
+            let [kind, param] = encode_intrinsic(intrinsic);
+            [16, kind, param]
+        }
+        Opcode::Nop => [17, 0, 0],
+        Opcode::ZeroExtend => [18, 0, 0],
+        Opcode::SignExtend => [19, 0, 0],
+        Opcode::Not => [20, 0, 0],
+        Opcode::Neg => [21, 0, 0],
+        Opcode::UMul => [22, 0, 0],
+        Opcode::SMul => [23, 0, 0],
+        Opcode::UDiv => [24, 0, 0],
+        Opcode::SDiv => [25, 0, 0],
+        Opcode::Rol => [26, 0, 0],
+        Opcode::Ror => [27, 0, 0],
+    }
+}
+
+fn decode_opcode(encoded: [u8; ENCODED_OPCODE_LEN]) -> Result<Opcode, DecodeError> {
+    let invalid = DecodeError::InvalidOpcode(encoded);
+    let [tag, first_param, second_param] = encoded;
+    let opcode = match tag {
+        0 => Opcode::Move,
+        1 => Opcode::Add,
+        2 => Opcode::Sub,
+        3 => Opcode::And,
+        4 => Opcode::Or,
+        5 => Opcode::Xor,
+        6 => Opcode::Shl,
+        7 => Opcode::Shr,
+        8 => Opcode::Sar,
+        9 => Opcode::Store,
+        10 => Opcode::Load,
+        11 => Opcode::SetCond(decode_cond(first_param).ok_or(invalid)?),
+        12 => Opcode::Branch,
+        13 => Opcode::Jump,
+        14 => Opcode::Call,
+        15 => Opcode::Return,
+        16 => Opcode::Intrinsic(decode_intrinsic([first_param, second_param]).ok_or(invalid)?),
+        17 => Opcode::Nop,
+        18 => Opcode::ZeroExtend,
+        19 => Opcode::SignExtend,
+        20 => Opcode::Not,
+        21 => Opcode::Neg,
+        22 => Opcode::UMul,
+        23 => Opcode::SMul,
+        24 => Opcode::UDiv,
+        25 => Opcode::SDiv,
+        26 => Opcode::Rol,
+        27 => Opcode::Ror,
+        _ => return Err(invalid),
+    };
+    // make sure that the unused parameter bytes are zero, so that each opcode has a single encoding.
+    if encode_opcode(opcode) != encoded {
+        return Err(invalid);
+    }
+    Ok(opcode)
+}
+
+fn encode_cond(cond: Cond) -> u8 {
+    match cond {
+        Cond::Zero => 0,
+        Cond::Negative => 1,
+        Cond::EvenParity => 2,
+    }
+}
+
+fn decode_cond(encoded: u8) -> Option<Cond> {
+    match encoded {
+        0 => Some(Cond::Zero),
+        1 => Some(Cond::Negative),
+        2 => Some(Cond::EvenParity),
+        _ => None,
+    }
+}
+
+fn encode_intrinsic(intrinsic: Intrinsic) -> [u8; 2] {
+    match intrinsic {
+        Intrinsic::Syscall => [0, 0],
+        Intrinsic::Sysret => [1, 0],
+        Intrinsic::Sysenter => [2, 0],
+        Intrinsic::Sysexit => [3, 0],
+        Intrinsic::Pause => [4, 0],
+        Intrinsic::Endbr64 => [5, 0],
+        Intrinsic::Endbr32 => [6, 0],
+        Intrinsic::WriteControlReg(index) => [7, index],
+        Intrinsic::ReadControlReg(index) => [8, index],
+        Intrinsic::WriteDebugReg(index) => [9, index],
+        Intrinsic::ReadDebugReg(index) => [10, index],
+    }
+}
+
+fn decode_intrinsic(encoded: [u8; 2]) -> Option<Intrinsic> {
+    let [kind, param] = encoded;
+    Some(match kind {
+        0 => Intrinsic::Syscall,
+        1 => Intrinsic::Sysret,
+        2 => Intrinsic::Sysenter,
+        3 => Intrinsic::Sysexit,
+        4 => Intrinsic::Pause,
+        5 => Intrinsic::Endbr64,
+        6 => Intrinsic::Endbr32,
+        7 => Intrinsic::WriteControlReg(param),
+        8 => Intrinsic::ReadControlReg(param),
+        9 => Intrinsic::WriteDebugReg(param),
+        10 => Intrinsic::ReadDebugReg(param),
+        _ => return None,
+    })
+}
+
+fn encode_space(space: OperandSpace) -> u8 {
+    match space {
+        OperandSpace::Ram => 0,
+        OperandSpace::Const => 1,
+        OperandSpace::Regs => 2,
+        OperandSpace::Tmp => 3,
+    }
+}
+
+fn decode_operand(encoded: &[u8]) -> Result<Operand, DecodeError> {
+    let space = match encoded[0] {
+        0 => OperandSpace::Ram,
+        1 => OperandSpace::Const,
+        2 => OperandSpace::Regs,
+        3 => OperandSpace::Tmp,
+        tag => return Err(DecodeError::InvalidOperandSpace(tag)),
+    };
+    let offset = u64::from_le_bytes(encoded[1..9].try_into().unwrap());
+    let size = OperandSize::from_bytes(encoded[9] as usize)
+        .ok_or(DecodeError::InvalidOperandSize(encoded[9]))?;
+    Ok(Operand {
+        addr: OperandAddr { space, offset },
+        size,
+    })
+}
@@ -0,0 +1,378 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{Cond, Insn, Intrinsic, Opcode, Operand, OperandSize, OperandSpace, Translation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterpError {
+    /// a memory access to the given address is not entirely inside of the ram.
+    RamOutOfBounds(u64),
+    /// an instruction tried to write to a constant operand.
+    WriteToConst,
+    /// the interpreter doesn't know how to execute the given intrinsic.
+    UnsupportedIntrinsic(Intrinsic),
+    /// a division by zero, or a division whose quotient doesn't fit in its destination.
+    DivideError,
+}
+
+/// the way in which control leaves a translation after it was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flow {
+    /// execution reached the end of the translation, and should continue at the next instruction.
+    Next,
+    /// control was transferred to the given address.
+    Jump(u64),
+}
+
+/// the memory accessed by the `Load` and `Store` instructions executed by a [`Machine`]. implementing this allows intercepting
+/// memory accesses, for example to emulate devices or to lazily map pages of a sparse address space.
+pub trait MemoryBackend {
+    /// reads the bytes at the given address into the given buffer.
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), InterpError>;
+
+    /// writes the given bytes to the given address.
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), InterpError>;
+}
+
+/// a memory backend which stores a contiguous ram in a vector, which is mapped at a base address. this allows executing
+/// lifted code against an image which is loaded at its real virtual address.
+///
+/// accesses which are not entirely inside of the mapped range fail with [`InterpError::RamOutOfBounds`]. indexing the
+/// vector through `Deref` uses offsets relative to the base address.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VecMemory {
+    /// the address at which the first byte of the vector is mapped.
+    pub base: u64,
+    /// the content of the ram.
+    pub bytes: Vec<u8>,
+}
+impl VecMemory {
+    /// creates a zeroed ram of the given size, mapped at address 0.
+    pub fn new(size: usize) -> Self {
+        Self::with_base(0, size)
+    }
+
+    /// creates a zeroed ram of the given size, mapped at the given base address.
+    pub fn with_base(base: u64, size: usize) -> Self {
+        Self {
+            base,
+            bytes: vec![0; size],
+        }
+    }
+
+    /// returns the range of the vector which holds the `len` bytes at the given address, or an error if they are not
+    /// entirely inside of the mapped range.
+    fn range_of(&self, addr: u64, len: usize) -> Result<core::ops::Range<usize>, InterpError> {
+        let out_of_bounds = InterpError::RamOutOfBounds(addr);
+        let start = addr
+            .checked_sub(self.base)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .ok_or(out_of_bounds)?;
+        let end = start
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(out_of_bounds)?;
+        Ok(start..end)
+    }
+}
+impl MemoryBackend for VecMemory {
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), InterpError> {
+        let range = self.range_of(addr, buf.len())?;
+        buf.copy_from_slice(&self.bytes[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), InterpError> {
+        let range = self.range_of(addr, data.len())?;
+        self.bytes[range].copy_from_slice(data);
+        Ok(())
+    }
+}
+impl core::ops::Deref for VecMemory {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+impl core::ops::DerefMut for VecMemory {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.bytes
+    }
+}
+
+/// the state of a machine executing ir instructions.
+///
+/// the ram is accessed through the given memory backend. the register and tmp spaces are little endian byte arrays which
+/// grow on demand, and bytes which were never written read as zero.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Machine<M = VecMemory> {
+    pub ram: M,
+    pub regs: Vec<u8>,
+    pub tmps: Vec<u8>,
+}
+impl Machine {
+    /// creates a machine with a zeroed ram of the given size, and zeroed registers.
+    pub fn new(ram_size: usize) -> Self {
+        Self::with_memory(VecMemory::new(ram_size))
+    }
+}
+impl<M: MemoryBackend> Machine<M> {
+    /// creates a machine which uses the given memory backend as its ram, with zeroed registers.
+    pub fn with_memory(ram: M) -> Self {
+        Self {
+            ram,
+            regs: Vec::new(),
+            tmps: Vec::new(),
+        }
+    }
+
+    /// reads the value of the given operand. the value of a 16 byte operand is truncated to its low 8 bytes.
+    pub fn read(&self, operand: &Operand) -> Result<u64, InterpError> {
+        Ok(self.read_wide(operand)? as u64)
+    }
+
+    /// reads the value of the given operand, which may be up to 16 bytes wide.
+    pub fn read_wide(&self, operand: &Operand) -> Result<u128, InterpError> {
+        let offset = operand.addr.offset;
+        let len = operand.size.bytes();
+        let mut value = [0u8; 16];
+        match operand.addr.space {
+            OperandSpace::Const => return Ok((offset & operand.size.mask()) as u128),
+            OperandSpace::Ram => self.ram.read(offset, &mut value[..len])?,
+            OperandSpace::Regs => read_grown(&self.regs, offset, &mut value[..len]),
+            OperandSpace::Tmp => read_grown(&self.tmps, offset, &mut value[..len]),
+        }
+        Ok(u128::from_le_bytes(value))
+    }
+
+    /// writes the given value to the given operand, truncating it to the operand's size.
+    ///
+    /// only the bytes covered by the operand are written, so writing a sub register leaves the rest of the register intact.
+    /// architecture specific side effects of register writes, like the zeroing of the upper half of a 64 bit x86 register
+    /// when writing its low 32 bits, are not applied here. lifters emit them as explicit instructions instead.
+    pub fn write(&mut self, operand: &Operand, value: u64) -> Result<(), InterpError> {
+        self.write_wide(operand, value as u128)
+    }
+
+    /// writes the given value, which may be up to 16 bytes wide, to the given operand, truncating it to the operand's size.
+    pub fn write_wide(&mut self, operand: &Operand, value: u128) -> Result<(), InterpError> {
+        let offset = operand.addr.offset as usize;
+        let len = operand.size.bytes();
+        let space = match operand.addr.space {
+            OperandSpace::Const => return Err(InterpError::WriteToConst),
+            OperandSpace::Ram => {
+                return self
+                    .ram
+                    .write(operand.addr.offset, &value.to_le_bytes()[..len])
+            }
+            OperandSpace::Regs => &mut self.regs,
+            OperandSpace::Tmp => &mut self.tmps,
+        };
+        if space.len() < offset + len {
+            space.resize(offset + len, 0);
+        }
+        space[offset..offset + len].copy_from_slice(&value.to_le_bytes()[..len]);
+        Ok(())
+    }
+
+    /// reads the value of the given instruction operand, masked to the operand's size.
+    pub fn read_operand(&self, operand: &Operand) -> Result<u64, InterpError> {
+        Ok(self.read(operand)? & operand.size.mask())
+    }
+
+    /// writes the given value to the given instruction operand, truncating it to the operand's size. only the bytes covered
+    /// by the operand are written, like in [`Machine::write`].
+    pub fn write_operand(&mut self, operand: &Operand, value: u64) -> Result<(), InterpError> {
+        self.write(operand, value)
+    }
+
+    /// executes the instructions of the given translation, until reaching its end or until control is transferred
+    /// elsewhere.
+    pub fn step(&mut self, translation: &Translation) -> Result<Flow, InterpError> {
+        self.step_traced(translation, |_, _| {})
+    }
+
+    /// like [`Machine::step`], but calls the given callback before executing each instruction, with the instruction and the
+    /// state of the machine right before executing it. this can be used for tracing the execution of lifted code.
+    pub fn step_traced(
+        &mut self,
+        translation: &Translation,
+        mut trace: impl FnMut(&Insn, &Self),
+    ) -> Result<Flow, InterpError> {
+        for insn in &translation.insns {
+            trace(insn, self);
+            if let Flow::Jump(target) = self.exec(insn)? {
+                return Ok(Flow::Jump(target));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// executes a single instruction.
+    fn exec(&mut self, insn: &Insn) -> Result<Flow, InterpError> {
+        let [first, second] = &insn.operands;
+        let size = first.size;
+        match insn.opcode {
+            Opcode::Move | Opcode::ZeroExtend => {
+                self.write_operand(first, self.read_operand(second)?)?
+            }
+            Opcode::SignExtend => self.write_operand(
+                first,
+                sign_extend(self.read_operand(second)?, second.size) as u64,
+            )?,
+            Opcode::Add => self.write_operand(
+                first,
+                self.read_operand(first)?
+                    .wrapping_add(self.read_operand(second)?),
+            )?,
+            Opcode::Sub => self.write_operand(
+                first,
+                self.read_operand(first)?
+                    .wrapping_sub(self.read_operand(second)?),
+            )?,
+            Opcode::And => self.write_operand(
+                first,
+                self.read_operand(first)? & self.read_operand(second)?,
+            )?,
+            Opcode::Or => self.write_operand(
+                first,
+                self.read_operand(first)? | self.read_operand(second)?,
+            )?,
+            Opcode::Xor => self.write_operand(
+                first,
+                self.read_operand(first)? ^ self.read_operand(second)?,
+            )?,
+            Opcode::Shl => {
+                let amount = self.read_operand(second)?;
+                let value = self.read_operand(first)?;
+                let result = if amount >= size.bits() as u64 {
+                    0
+                } else {
+                    value << amount
+                };
+                self.write_operand(first, result)?
+            }
+            Opcode::Shr => {
+                let amount = self.read_operand(second)?;
+                let value = self.read_operand(first)?;
+                let result = if amount >= size.bits() as u64 {
+                    0
+                } else {
+                    value >> amount
+                };
+                self.write_operand(first, result)?
+            }
+            Opcode::Sar => {
+                let amount = self.read_operand(second)?.min(size.bits() as u64 - 1);
+                let value = sign_extend(self.read_operand(first)?, size);
+                self.write_operand(first, (value >> amount) as u64)?
+            }
+            Opcode::Rol | Opcode::Ror => {
+                let bits = size.bits() as u64;
+                let amount = self.read_operand(second)? % bits;
+                let value = self.read_operand(first)?;
+                let amount = if insn.opcode == Opcode::Rol {
+                    amount
+                } else {
+                    (bits - amount) % bits
+                };
+                let result = if amount == 0 {
+                    value
+                } else {
+                    (value << amount) | (value >> (bits - amount))
+                };
+                self.write_operand(first, result)?
+            }
+            Opcode::UMul | Opcode::SMul => {
+                let half_size = second.size;
+                let low = self.read_operand(first)? & half_size.mask();
+                let value = self.read_operand(second)?;
+                let product = if insn.opcode == Opcode::UMul {
+                    low as u128 * value as u128
+                } else {
+                    (sign_extend(low, half_size) as i128 * sign_extend(value, half_size) as i128)
+                        as u128
+                };
+                self.write_wide(first, product)?
+            }
+            Opcode::UDiv | Opcode::SDiv => {
+                let half_size = second.size;
+                let half_mask = half_size.mask() as u128;
+                let dividend = self.read_wide(first)?;
+                let divisor = self.read_operand(second)?;
+                let (quotient, remainder) = if insn.opcode == Opcode::UDiv {
+                    let quotient = dividend
+                        .checked_div(divisor as u128)
+                        .filter(|&quotient| quotient <= half_mask)
+                        .ok_or(InterpError::DivideError)?;
+                    (quotient, dividend % divisor as u128)
+                } else {
+                    let shift = 128 - first.size.bits();
+                    let dividend = ((dividend << shift) as i128) >> shift;
+                    let divisor = sign_extend(divisor, half_size) as i128;
+                    let half_bits = half_size.bits() as u32;
+                    let quotient = dividend
+                        .checked_div(divisor)
+                        .filter(|quotient| {
+                            (-(1i128 << (half_bits - 1))..1i128 << (half_bits - 1))
+                                .contains(quotient)
+                        })
+                        .ok_or(InterpError::DivideError)?;
+                    (quotient as u128, (dividend % divisor) as u128)
+                };
+                self.write_wide(
+                    first,
+                    ((remainder & half_mask) << half_size.bits()) | (quotient & half_mask),
+                )?
+            }
+            Opcode::Not => self.write_operand(first, !self.read_operand(first)?)?,
+            Opcode::Neg => self.write_operand(first, self.read_operand(first)?.wrapping_neg())?,
+            Opcode::Store => {
+                let addr = self.read_operand(first)?;
+                let value = self.read_operand(second)?;
+                self.write_operand(&Operand::ram(addr, second.size), value)?
+            }
+            Opcode::Load => {
+                let addr = self.read_operand(second)?;
+                let value = self.read_operand(&Operand::ram(addr, size))?;
+                self.write_operand(first, value)?
+            }
+            Opcode::SetCond(cond) => {
+                let value = self.read_operand(second)?;
+                let holds = match cond {
+                    Cond::Zero => value == 0,
+                    Cond::Negative => sign_extend(value, second.size) < 0,
+                    Cond::EvenParity => (value as u8).count_ones().is_multiple_of(2),
+                };
+                self.write_operand(first, holds as u64)?
+            }
+            Opcode::Branch => {
+                if self.read_operand(first)? != 0 {
+                    return Ok(Flow::Jump(self.read_operand(second)?));
+                }
+            }
+            Opcode::Jump | Opcode::Call | Opcode::Return => {
+                return Ok(Flow::Jump(self.read_operand(first)?));
+            }
+            Opcode::Intrinsic(intrinsic) => {
+                return Err(InterpError::UnsupportedIntrinsic(intrinsic))
+            }
+            Opcode::Nop => {}
+        }
+        Ok(Flow::Next)
+    }
+}
+
+/// reads the bytes at the given offset of an operand space which grows on demand into the given buffer. bytes which were
+/// never written read as zero.
+fn read_grown(space: &[u8], offset: u64, buf: &mut [u8]) {
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = space.get(offset as usize + i).copied().unwrap_or(0);
+    }
+}
+
+/// sign extends a value of the given size to 64 bits.
+fn sign_extend(value: u64, size: OperandSize) -> i64 {
+    let shift = 64 - size.bits();
+    ((value << shift) as i64) >> shift
+}
@@ -1,10 +1,75 @@
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    string::ToString,
+    vec::Vec,
+};
+
 use arrayvec::ArrayVec;
+use bitpiece::BitStorage;
+
+// the macros for defining named register operands must be defined before the declarations of the architecture modules, so
+// that they can be used by all of them.
+macro_rules! define_reg_operand {
+    {$name: ident, $offset: expr, $size: ident} => {
+        pub const $name: Operand = Operand::reg($offset, OperandSize::$size);
+
+    };
+}
+
+macro_rules! define_reg_operands_single {
+    {$step_size: expr, $size: ident, $prev_name: ident, $name: ident} => {
+        define_reg_operand! {$name, $prev_name.addr.offset + $step_size, $size}
+    };
+}
+
+macro_rules! define_reg_operands_inner {
+    // the case for the last operand
+    {$step_size: expr, $size: ident, $prev_name: ident, $name: ident} => {
+        define_reg_operands_single!{$step_size, $size, $prev_name, $name}
+    };
+
+    // the common case of the non-last operand
+    {$step_size: expr, $size: ident, $prev_name: ident, $name: ident, $($names: ident),+} => {
+        // define the current operand
+        define_reg_operands_inner! {$step_size, $size, $prev_name, $name}
+
+        // define the rest of the operands
+        define_reg_operands_inner! {$step_size, $size, $name, $($names),+}
+    };
+}
+
+macro_rules! define_reg_operands {
+    {$step_size: expr, $size: ident, $first_name: ident, $($name: ident),+} => {
+        // define the first operand with offset 0
+        define_reg_operand! {$first_name, 0, $size}
+
+        // define the rest of the operands following it
+        define_reg_operands_inner! {$step_size, $size, $first_name, $($name),+}
+    };
+}
 
+pub mod aarch64;
+#[cfg(feature = "alloc")]
+pub mod encoding;
+#[cfg(feature = "alloc")]
+pub mod interp;
+pub mod opt;
+pub mod riscv;
 pub mod x86;
 
 pub const TRANSLATION_MAX_INSNS: usize = 128;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OperandSpace {
     Ram,
     Const,
@@ -13,39 +78,190 @@ pub enum OperandSpace {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Operand {
     pub addr: OperandAddr,
     pub size: OperandSize,
 }
 impl core::fmt::Display for Operand {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}:{}", self.addr, self.size as usize)
+        if self.addr.space == OperandSpace::Const {
+            // only display the bits of the constant which participate at its size.
+            write!(
+                f,
+                "{:?}[0x{:x}]:{}",
+                self.addr.space,
+                self.addr.offset & self.size.mask(),
+                self.size as usize
+            )
+        } else {
+            write!(f, "{}:{}", self.addr, self.size as usize)
+        }
+    }
+}
+/// a group of consecutive registers of the same size, which are laid out in the register space at a fixed distance from
+/// each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegGroup {
+    /// the names of the registers of the group, in the order of their offsets.
+    pub names: &'static [&'static str],
+    /// the offset of the first register of the group.
+    pub first_offset: u64,
+    /// the distance between the offsets of consecutive registers of the group.
+    pub step: u64,
+    /// the size of each register of the group.
+    pub size: OperandSize,
+}
+
+/// a single named register, as described by a [`RegFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegDesc {
+    pub name: &'static str,
+    pub offset: u64,
+    pub size: OperandSize,
+}
+impl RegDesc {
+    /// returns the operand of this register.
+    pub const fn operand(&self) -> Operand {
+        Operand::reg(self.offset, self.size)
+    }
+}
+
+/// a description of the named registers of an architecture, and of their location in the register space. this is the
+/// single source of truth for mapping register operands back to register names, for example when displaying them.
+///
+/// registers may overlap, for example the 32 bit registers of x86 are the low halves of the 64 bit registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegFile {
+    pub groups: &'static [RegGroup],
+}
+impl RegFile {
+    /// returns an iterator over all registers of this register file.
+    pub fn regs(&self) -> impl Iterator<Item = RegDesc> + '_ {
+        self.groups.iter().flat_map(|group| {
+            group.names.iter().enumerate().map(|(i, name)| RegDesc {
+                name,
+                offset: group.first_offset + i as u64 * group.step,
+                size: group.size,
+            })
+        })
+    }
+
+    /// returns the register whose operand is exactly the given operand, if any.
+    pub fn reg_of_operand(&self, operand: &Operand) -> Option<RegDesc> {
+        if operand.addr.space != OperandSpace::Regs {
+            return None;
+        }
+        self.regs()
+            .find(|reg| reg.offset == operand.addr.offset && reg.size == operand.size)
+    }
+
+    /// returns the widest register which starts at the given offset, if any.
+    pub fn reg_at(&self, offset: u64) -> Option<RegDesc> {
+        self.regs()
+            .filter(|reg| reg.offset == offset)
+            .max_by_key(|reg| reg.size.bytes())
+    }
+
+    /// returns the register with the given name, if any.
+    pub fn reg_by_name(&self, name: &str) -> Option<RegDesc> {
+        self.regs().find(|reg| reg.name == name)
+    }
+}
+
+/// a wrapper for displaying an operand in a human readable form, returned by [`Operand::display_annotated`].
+///
+/// register operands which are described by the register file are displayed by their name, and constants are displayed
+/// as signed decimal numbers when their high bit is set. all other operands use the raw format of [`Operand`]'s `Display`
+/// implementation.
+#[derive(Clone, Copy)]
+pub struct DisplayAnnotated<'a> {
+    operand: &'a Operand,
+    reg_file: Option<&'a RegFile>,
+}
+impl core::fmt::Display for DisplayAnnotated<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(reg) = self
+            .reg_file
+            .and_then(|reg_file| reg_file.reg_of_operand(self.operand))
+        {
+            return write!(f, "{}", reg.name);
+        }
+        match self.operand.as_signed() {
+            Some(value) if value < 0 => write!(f, "{}:{}", value, self.operand.size as usize),
+            Some(value) => write!(f, "0x{:x}:{}", value, self.operand.size as usize),
+            None => write!(f, "{}", self.operand),
+        }
     }
 }
+
 impl Operand {
+    /// returns a wrapper which displays this operand in a human readable form, naming registers using the given register
+    /// file, if any.
+    pub const fn display_annotated<'a>(
+        &'a self,
+        reg_file: Option<&'a RegFile>,
+    ) -> DisplayAnnotated<'a> {
+        DisplayAnnotated {
+            operand: self,
+            reg_file,
+        }
+    }
+
+    /// creates a constant operand. the value is truncated to the given size.
     pub const fn constant(value: u64, size: OperandSize) -> Self {
         Self {
             addr: OperandAddr {
                 space: OperandSpace::Const,
-                offset: value,
+                offset: value & size.mask(),
             },
             size,
         }
     }
 
     pub const fn negative_constant(absolute_value: u64, size: OperandSize) -> Self {
-        let mask = if size.bits() == 64 {
-            u64::MAX
-        } else {
-            (1u64 << size.bits()) - 1
-        };
-        Self::constant(absolute_value.wrapping_neg() & mask, size)
+        Self::constant(absolute_value.wrapping_neg(), size)
     }
 
     pub const fn zero(size: OperandSize) -> Self {
         Self::constant(0, size)
     }
 
+    /// returns the value of this operand if it is a constant, truncated to its size.
+    pub const fn as_unsigned(&self) -> Option<u64> {
+        match self.addr.space {
+            OperandSpace::Const => Some(self.addr.offset & self.size.mask()),
+            _ => None,
+        }
+    }
+
+    /// returns the value of this operand if it is a constant, sign extended from its size.
+    pub const fn as_signed(&self) -> Option<i64> {
+        let shift = 64usize.saturating_sub(self.size.bits());
+        match self.as_unsigned() {
+            Some(value) => Some(((value << shift) as i64) >> shift),
+            None => None,
+        }
+    }
+
+    /// returns the offsets of the register bytes covered by this operand, or an empty range if it is not a register operand.
+    #[cfg(feature = "alloc")]
+    fn reg_byte_offsets(&self) -> core::ops::Range<u64> {
+        if self.addr.space == OperandSpace::Regs {
+            self.addr.offset..self.addr.offset + self.size.bytes() as u64
+        } else {
+            0..0
+        }
+    }
+
+    /// creates a tmp operand at the given offset of the tmp space, which holds the scratch values of a translation.
+    ///
+    /// ```
+    /// # use pis::*;
+    /// let tmp = Operand::tmp(4, OperandSize::B4);
+    /// assert_eq!(tmp.addr.space, OperandSpace::Tmp);
+    /// assert_eq!(tmp.addr.offset, 4);
+    /// ```
     pub const fn tmp(offset: u64, size: OperandSize) -> Self {
         Self {
             addr: OperandAddr {
@@ -55,9 +271,69 @@ impl Operand {
             size,
         }
     }
+
+    /// creates a register operand at the given offset of the register space. the layout of the register space is defined
+    /// by each architecture, for example [`Operand::x86_reg`] returns the operands of the x86 general purpose registers.
+    ///
+    /// ```
+    /// # use pis::*;
+    /// let reg = Operand::reg(0x10, OperandSize::B8);
+    /// assert_eq!(reg.addr.space, OperandSpace::Regs);
+    /// assert_eq!(reg.addr.offset, 0x10);
+    /// ```
+    pub const fn reg(offset: u64, size: OperandSize) -> Self {
+        Self {
+            addr: OperandAddr {
+                space: OperandSpace::Regs,
+                offset,
+            },
+            size,
+        }
+    }
+
+    /// creates an operand for the memory of the given size at the given address.
+    ///
+    /// ```
+    /// # use pis::*;
+    /// let ram = Operand::ram(0x1000, OperandSize::B2);
+    /// assert_eq!(ram.addr.space, OperandSpace::Ram);
+    /// assert_eq!(ram.addr.offset, 0x1000);
+    /// ```
+    pub const fn ram(addr: u64, size: OperandSize) -> Self {
+        Self {
+            addr: OperandAddr {
+                space: OperandSpace::Ram,
+                offset: addr,
+            },
+            size,
+        }
+    }
+}
+
+/// hands out non overlapping tmp operands, for lifters which need scratch space while translating an instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TmpAllocator {
+    next_offset: u64,
+}
+impl TmpAllocator {
+    pub const fn new() -> Self {
+        Self { next_offset: 0 }
+    }
+    /// allocates a tmp operand of the given size, which doesn't overlap any of the previously allocated tmps. the offset of
+    /// the tmp is aligned to its size.
+    pub fn alloc(&mut self, size: OperandSize) -> Operand {
+        let offset = self.next_offset.next_multiple_of(size.bytes() as u64);
+        self.next_offset = offset + size.bytes() as u64;
+        Operand::tmp(offset, size)
+    }
+    /// frees all of the allocated tmps, so that the allocator can be reused for translating the next instruction.
+    pub fn reset(&mut self) {
+        self.next_offset = 0;
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OperandAddr {
     pub space: OperandSpace,
     pub offset: u64,
@@ -69,6 +345,7 @@ impl core::fmt::Display for OperandAddr {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OperandSize {
     /// 1 byte
     B1 = 1,
@@ -78,6 +355,8 @@ pub enum OperandSize {
     B4 = 4,
     /// 8 bytes
     B8 = 8,
+    /// 16 bytes. this size is only used for the double width operands of the multiplication and division opcodes.
+    B16 = 16,
 }
 impl OperandSize {
     pub const fn bytes(&self) -> usize {
@@ -86,22 +365,243 @@ impl OperandSize {
     pub const fn bits(&self) -> usize {
         self.bytes() * 8
     }
+    /// returns a mask of the bits of a 64 bit value which participate in an operation of this size.
+    pub const fn mask(&self) -> u64 {
+        if self.bits() >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << self.bits()) - 1
+        }
+    }
+    /// returns the operand size which is exactly `bytes` bytes wide, if there is one.
+    pub const fn from_bytes(bytes: usize) -> Option<Self> {
+        match bytes {
+            1 => Some(Self::B1),
+            2 => Some(Self::B2),
+            4 => Some(Self::B4),
+            8 => Some(Self::B8),
+            16 => Some(Self::B16),
+            _ => None,
+        }
+    }
+    /// returns the operand size which is exactly `bits` bits wide, if there is one.
+    pub const fn from_bits(bits: usize) -> Option<Self> {
+        if !bits.is_multiple_of(8) {
+            return None;
+        }
+        Self::from_bytes(bits / 8)
+    }
+    /// returns the operand size of the given bitpiece storage type, for example [`OperandSize::B4`] for [`u32`].
+    pub const fn of_storage<S: BitStorage>() -> Self {
+        match Self::from_bits(S::BITS) {
+            Some(size) => size,
+            None => panic!("bit storage type has no matching operand size"),
+        }
+    }
+}
+
+/// an architecture specific operation which can't be expressed using the generic ir opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Intrinsic {
+    /// a fast system call from user mode into the kernel.
+    Syscall,
+    /// a fast return from a system call back into user mode.
+    Sysret,
+    /// a legacy fast system call from user mode into the kernel.
+    Sysenter,
+    /// a legacy fast return from a system call back into user mode.
+    Sysexit,
+    /// a hint that the code is executing a spin loop.
+    Pause,
+    /// marks a valid target of an indirect branch in 64 bit code, for control flow enforcement.
+    Endbr64,
+    /// marks a valid target of an indirect branch in 32 bit code, for control flow enforcement.
+    Endbr32,
+    /// writes the value of the first operand to the control register with the given index.
+    WriteControlReg(u8),
+    /// reads the value of the control register with the given index into the first operand.
+    ReadControlReg(u8),
+    /// writes the value of the first operand to the debug register with the given index.
+    WriteDebugReg(u8),
+    /// reads the value of the debug register with the given index into the first operand.
+    ReadDebugReg(u8),
+}
+impl Intrinsic {
+    /// does this intrinsic transfer control to some other code, such that execution doesn't simply continue at the next
+    /// instruction.
+    pub const fn affects_control_flow(&self) -> bool {
+        match self {
+            Self::Syscall | Self::Sysret | Self::Sysenter | Self::Sysexit => true,
+            Self::Pause
+            | Self::Endbr64
+            | Self::Endbr32
+            | Self::WriteControlReg(_)
+            | Self::ReadControlReg(_)
+            | Self::WriteDebugReg(_)
+            | Self::ReadDebugReg(_) => false,
+        }
+    }
+}
+
+/// a condition on a single value, which is evaluated by [`Opcode::SetCond`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Cond {
+    /// the value is zero.
+    Zero,
+    /// the most significant bit of the value, at its operand size, is set.
+    Negative,
+    /// the least significant byte of the value has an even number of set bits.
+    EvenParity,
 }
 
+/// an ir opcode.
+///
+/// arithmetic opcodes operate on values of the size of their destination operand, and their results wrap around at that
+/// size, with any bits above it discarded. for example, an `Add` of two 1 byte operands with the value `0x80` results in
+/// `0`. the carry out of the operation is never reflected in the destination, and must be computed explicitly by separate
+/// instructions if it is needed, for example for updating the flags.
+///
+/// the invariants of each opcode, which are checked by [`Translation::validate`], are:
+/// - operands which are written must not be constants.
+/// - `Move`, `Add`, `Sub`, `And`, `Or` and `Xor` require both operands to have the same size. the shift amount of the shift
+///   opcodes may have any size.
+/// - `ZeroExtend` and `SignExtend` require the first operand to be at least as large as the second operand.
+/// - `UMul`, `SMul`, `UDiv` and `SDiv` require the first operand to be twice as large as the second operand. 16 byte
+///   operands may only be used as the first operand of these opcodes.
+/// - the address operands of `Store` and `Load` must hold the address as a value, so they must not be ram operands.
+/// - the first operand of `SetCond` and `Branch` must be a 1 byte operand.
+/// - operands which are documented as unused must be zero constants, of any size.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Opcode {
+    /// copies the value of the second operand into the first operand.
     Move,
+    /// copies the value of the second operand into the first operand, which may be larger, filling the extra bits with
+    /// zeroes.
+    ZeroExtend,
+    /// copies the value of the second operand into the first operand, which may be larger, filling the extra bits with
+    /// copies of the sign bit of the second operand.
+    SignExtend,
+    /// adds the second operand to the first operand, wrapping around at the operand size.
     Add,
+    /// subtracts the second operand from the first operand, wrapping around at the operand size.
+    Sub,
+    /// ands the first operand with the second operand.
+    And,
+    /// ors the first operand with the second operand.
+    Or,
+    /// xors the first operand with the second operand.
+    Xor,
+    /// shifts the first operand left by the amount of bits held in the second operand, filling the vacated bits with zeroes.
+    Shl,
+    /// shifts the first operand right by the amount of bits held in the second operand, filling the vacated bits with
+    /// zeroes.
+    Shr,
+    /// shifts the first operand right by the amount of bits held in the second operand, filling the vacated bits with copies
+    /// of the sign bit.
+    Sar,
+    /// rotates the first operand left by the amount of bits held in the second operand, modulo the size of the first operand
+    /// in bits. the bits shifted out of the most significant bit are shifted back into the least significant bit.
+    Rol,
+    /// rotates the first operand right by the amount of bits held in the second operand, modulo the size of the first operand
+    /// in bits. the bits shifted out of the least significant bit are shifted back into the most significant bit.
+    Ror,
+    /// multiplies the low half of the first operand, which is twice as large as the second operand, by the second operand as
+    /// unsigned values, and writes the full double width product to the first operand.
+    UMul,
+    /// like `UMul`, but multiplies the values as signed values.
+    SMul,
+    /// divides the first operand, which is twice as large as the second operand, by the second operand as unsigned values.
+    /// the quotient is written to the low half of the first operand, and the remainder to its high half. execution fails if
+    /// the second operand is zero, or if the quotient doesn't fit in the low half.
+    UDiv,
+    /// like `UDiv`, but divides the values as signed values. the remainder has the sign of the dividend.
+    SDiv,
+    /// inverts the bits of the first operand. the second operand is unused and should be zero.
+    Not,
+    /// negates the first operand as a two's complement value, wrapping around at its size. the second operand is unused and
+    /// should be zero.
+    Neg,
+    /// writes the value of the second operand to the memory at the address held in the first operand.
     Store,
+    /// reads the memory at the address held in the second operand into the first operand. the size of the memory access is the
+    /// size of the first operand.
+    Load,
+    /// evaluates the condition on the value of the second operand, and writes the result to the first operand, which must
+    /// be a 1 byte operand, as 1 if the condition holds and 0 otherwise. this is used for computing flags, for example the
+    /// zero flag of an `add` is the `Zero` condition on its result.
+    ///
+    /// conditions which involve more than a single value, like the carry out of an addition, are computed from the sign
+    /// bits of the inputs and the result using the bitwise opcodes.
+    SetCond(Cond),
+    /// transfers control to the address held in the second operand if the value of the first operand, which must be a 1 byte
+    /// operand, is nonzero. otherwise, execution continues at the next instruction.
+    Branch,
+    /// transfers control to the address held in the first operand. the second operand is unused and should be zero.
+    Jump,
+    /// calls the function at the address held in the first operand. the return address is not saved implicitly, and should
+    /// be saved by separate instructions before the call, as required by the architecture. the second operand is unused and
+    /// should be zero.
+    Call,
+    /// returns from a function to the return address held in the first operand. the second operand is unused and should be
+    /// zero.
+    Return,
+    /// an architecture specific operation. the meaning of the operands is described by each intrinsic, and unused operands
+    /// should be zero.
+    Intrinsic(Intrinsic),
+    /// an instruction with no effect. the first operand is a constant holding the length in bytes of the original
+    /// instruction, and the second operand is unused and should be zero.
+    Nop,
+}
+impl Opcode {
+    /// does this opcode end the basic block that it is a part of.
+    pub const fn is_block_terminator(&self) -> bool {
+        match self {
+            Self::Move
+            | Self::ZeroExtend
+            | Self::SignExtend
+            | Self::Add
+            | Self::Sub
+            | Self::And
+            | Self::Or
+            | Self::Xor
+            | Self::Shl
+            | Self::Shr
+            | Self::Sar
+            | Self::Rol
+            | Self::Ror
+            | Self::UMul
+            | Self::SMul
+            | Self::UDiv
+            | Self::SDiv
+            | Self::Not
+            | Self::Neg
+            | Self::Store
+            | Self::Load
+            | Self::SetCond(_)
+            | Self::Nop => false,
+            Self::Branch | Self::Jump | Self::Call | Self::Return => true,
+            Self::Intrinsic(intrinsic) => intrinsic.affects_control_flow(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Insn {
     pub opcode: Opcode,
     pub operands: [Operand; 2],
+    /// is this memory access part of an atomic read-modify-write of its memory, for example the `Load` and `Store` of an x86
+    /// instruction with a `lock` prefix.
+    pub is_atomic: bool,
 }
 impl core::fmt::Display for Insn {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_atomic {
+            write!(f, "atomic ")?;
+        }
         write!(
             f,
             "{:?} {}, {}",
@@ -114,13 +614,99 @@ impl Insn {
         Self {
             opcode,
             operands: [first_operand, second_operand],
+            is_atomic: false,
+        }
+    }
+    /// the operands whose values are read by this instruction. this doesn't include memory read by a `Load`, only the operand
+    /// holding its address.
+    pub fn read_operands(&self) -> &[Operand] {
+        match self.opcode {
+            Opcode::Move
+            | Opcode::ZeroExtend
+            | Opcode::SignExtend
+            | Opcode::Load
+            | Opcode::SetCond(_) => &self.operands[1..],
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::And
+            | Opcode::Or
+            | Opcode::Xor
+            | Opcode::Shl
+            | Opcode::Shr
+            | Opcode::Sar
+            | Opcode::Rol
+            | Opcode::Ror
+            | Opcode::UMul
+            | Opcode::SMul
+            | Opcode::UDiv
+            | Opcode::SDiv
+            | Opcode::Store
+            | Opcode::Branch => &self.operands,
+            Opcode::Intrinsic(Intrinsic::WriteControlReg(_) | Intrinsic::WriteDebugReg(_)) => {
+                &self.operands[..1]
+            }
+            Opcode::Not | Opcode::Neg | Opcode::Jump | Opcode::Call | Opcode::Return => {
+                &self.operands[..1]
+            }
+            Opcode::Intrinsic(_) | Opcode::Nop => &[],
+        }
+    }
+    /// the operands which are written by this instruction. this doesn't include memory written by a `Store`.
+    pub fn written_operands(&self) -> &[Operand] {
+        match self.opcode {
+            Opcode::Move
+            | Opcode::ZeroExtend
+            | Opcode::SignExtend
+            | Opcode::Add
+            | Opcode::Sub
+            | Opcode::And
+            | Opcode::Or
+            | Opcode::Xor
+            | Opcode::Shl
+            | Opcode::Shr
+            | Opcode::Sar
+            | Opcode::Rol
+            | Opcode::Ror
+            | Opcode::UMul
+            | Opcode::SMul
+            | Opcode::UDiv
+            | Opcode::SDiv
+            | Opcode::Not
+            | Opcode::Neg
+            | Opcode::Load
+            | Opcode::SetCond(_) => &self.operands[..1],
+            Opcode::Intrinsic(Intrinsic::ReadControlReg(_) | Intrinsic::ReadDebugReg(_)) => {
+                &self.operands[..1]
+            }
+            Opcode::Store
+            | Opcode::Branch
+            | Opcode::Jump
+            | Opcode::Call
+            | Opcode::Return
+            | Opcode::Intrinsic(_)
+            | Opcode::Nop => &[],
         }
     }
 }
 
+/// a summary of which register bytes are accessed by a translation, in the form required for data flow analysis between
+/// translated blocks.
+///
+/// registers are tracked at byte granularity, using the offsets of their bytes in [`OperandSpace::Regs`], so partial register
+/// writes only kill the bytes that they actually write.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RegSummary {
+    /// the register bytes whose value at the start of the translation may be read.
+    pub read_before_write: BTreeSet<u64>,
+    /// the register bytes which are written by the translation.
+    pub written: BTreeSet<u64>,
+}
+
 pub type TranslationInsns = ArrayVec<Insn, TRANSLATION_MAX_INSNS>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Translation {
     pub insns: TranslationInsns,
 }
@@ -132,14 +718,541 @@ impl core::fmt::Display for Translation {
         Ok(())
     }
 }
+/// a wrapper for displaying a translation with numbered instructions, returned by [`Translation::display_numbered`].
+#[derive(Clone, Copy)]
+pub struct DisplayNumbered<'a> {
+    translation: &'a Translation,
+}
+impl core::fmt::Display for DisplayNumbered<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let last_index = self.translation.insns.len().saturating_sub(1);
+        let width = last_index.checked_ilog10().unwrap_or(0) as usize + 1;
+        for (i, insn) in self.translation.insns.iter().enumerate() {
+            writeln!(f, "  {:0width$}: {}", i, insn, width = width)?;
+        }
+        Ok(())
+    }
+}
 impl Translation {
     pub fn new() -> Self {
         Self {
             insns: TranslationInsns::new(),
         }
     }
+
+    /// returns a wrapper which displays the instructions of this translation, each prefixed by its index. the indices are
+    /// zero padded to a common width so that the instructions line up.
+    pub fn display_numbered(&self) -> DisplayNumbered<'_> {
+        DisplayNumbered { translation: self }
+    }
+}
+impl Translation {
+    /// checks that all of the instructions of this translation satisfy the invariants of their opcodes, which are described
+    /// in [`Opcode`].
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for (i, insn) in self.insns.iter().enumerate() {
+            let [first, second] = &insn.operands;
+            let is_zero = |operand: &Operand| *operand == Operand::zero(operand.size);
+            if insn
+                .written_operands()
+                .iter()
+                .any(|operand| operand.addr.space == OperandSpace::Const)
+            {
+                return Err(ValidationError::WriteToConst(i));
+            }
+            let is_double_width = matches!(
+                insn.opcode,
+                Opcode::UMul | Opcode::SMul | Opcode::UDiv | Opcode::SDiv
+            );
+            if (!is_double_width && first.size == OperandSize::B16)
+                || second.size == OperandSize::B16
+            {
+                return Err(ValidationError::SizeMismatch(i));
+            }
+            match insn.opcode {
+                Opcode::Move
+                | Opcode::Add
+                | Opcode::Sub
+                | Opcode::And
+                | Opcode::Or
+                | Opcode::Xor => {
+                    if first.size != second.size {
+                        return Err(ValidationError::SizeMismatch(i));
+                    }
+                }
+                Opcode::ZeroExtend | Opcode::SignExtend => {
+                    if first.size.bytes() < second.size.bytes() {
+                        return Err(ValidationError::SizeMismatch(i));
+                    }
+                }
+                Opcode::UMul | Opcode::SMul | Opcode::UDiv | Opcode::SDiv => {
+                    if first.size.bytes() != second.size.bytes() * 2 {
+                        return Err(ValidationError::SizeMismatch(i));
+                    }
+                }
+                Opcode::Shl | Opcode::Shr | Opcode::Sar | Opcode::Rol | Opcode::Ror => {}
+                Opcode::Store | Opcode::Load => {
+                    let addr = if insn.opcode == Opcode::Store {
+                        first
+                    } else {
+                        second
+                    };
+                    if addr.addr.space == OperandSpace::Ram {
+                        return Err(ValidationError::RamAddress(i));
+                    }
+                }
+                Opcode::SetCond(_) | Opcode::Branch => {
+                    if first.size != OperandSize::B1 {
+                        return Err(ValidationError::InvalidConditionSize(i));
+                    }
+                }
+                Opcode::Not | Opcode::Neg | Opcode::Jump | Opcode::Call | Opcode::Return => {
+                    if !is_zero(second) {
+                        return Err(ValidationError::UnusedOperandNotZero(i));
+                    }
+                }
+                Opcode::Intrinsic(intrinsic) => {
+                    let uses_first = matches!(
+                        intrinsic,
+                        Intrinsic::WriteControlReg(_)
+                            | Intrinsic::ReadControlReg(_)
+                            | Intrinsic::WriteDebugReg(_)
+                            | Intrinsic::ReadDebugReg(_)
+                    );
+                    if (!uses_first && !is_zero(first)) || !is_zero(second) {
+                        return Err(ValidationError::UnusedOperandNotZero(i));
+                    }
+                }
+                Opcode::Nop => {
+                    if !is_zero(second) {
+                        return Err(ValidationError::UnusedOperandNotZero(i));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+#[cfg(feature = "alloc")]
+impl Translation {
+    /// summarizes the register bytes read and written by this translation.
+    pub fn block_reg_summary(&self) -> RegSummary {
+        let mut summary = RegSummary::default();
+        for insn in &self.insns {
+            // the reads of an instruction happen before its writes, so `add rax, rax` reads the original value of `rax`.
+            for operand in insn.read_operands() {
+                for byte_offset in operand.reg_byte_offsets() {
+                    if !summary.written.contains(&byte_offset) {
+                        summary.read_before_write.insert(byte_offset);
+                    }
+                }
+            }
+            for operand in insn.written_operands() {
+                summary.written.extend(operand.reg_byte_offsets());
+            }
+        }
+        summary
+    }
+}
+#[cfg(feature = "alloc")]
+impl Translation {
+    /// renders this translation as a graphviz dot graph, with a node for each instruction and edges for the possible flows of
+    /// control between them. control enters the translation through the `entry` node and leaves it through the `exit` node.
+    pub fn to_dot(&self) -> String {
+        use core::fmt::Write;
+
+        let mut dot = String::from("digraph translation {\n");
+        writeln!(dot, "    entry [shape=oval];").unwrap();
+        writeln!(dot, "    exit [shape=oval];").unwrap();
+        if self.insns.is_empty() {
+            writeln!(dot, "    entry -> exit;").unwrap();
+        } else {
+            writeln!(dot, "    entry -> insn0;").unwrap();
+        }
+        for (i, insn) in self.insns.iter().enumerate() {
+            let label = insn.to_string().replace('"', "\\\"");
+            writeln!(
+                dot,
+                "    insn{} [shape=box, label=\"{}: {}\"];",
+                i, i, label
+            )
+            .unwrap();
+        }
+        for (i, insn) in self.insns.iter().enumerate() {
+            let is_last = i + 1 == self.insns.len();
+            if insn.opcode.is_block_terminator() || is_last {
+                writeln!(dot, "    insn{} -> exit;", i).unwrap();
+            } else {
+                writeln!(dot, "    insn{} -> insn{};", i, i + 1).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+impl Translation {
+    /// merges pairs of adjacent `Store` instructions which write the two halves of a single value to contiguous constant
+    /// addresses into a single wider `Store`, and similarly pairs of adjacent `Load` instructions which read contiguous
+    /// constant addresses into the two halves of a single value into a single wider `Load`.
+    ///
+    /// the halves are considered to be the halves of a single value if they are contiguous in the same operand space, or if
+    /// they are both constants. memory and all operand spaces are assumed to be little endian.
+    ///
+    /// only adjacent accesses are merged, so there is never any other instruction between them which could observe the
+    /// partially accessed memory or value.
+    pub fn coalesce_mem_access(&mut self) {
+        let mut coalesced = TranslationInsns::new();
+        for insn in self.insns.drain(..) {
+            if let Some(merged) = coalesced
+                .last()
+                .and_then(|prev| merge_stores(prev, &insn).or_else(|| merge_loads(prev, &insn)))
+            {
+                *coalesced.last_mut().unwrap() = merged;
+            } else {
+                coalesced.push(insn);
+            }
+        }
+        self.insns = coalesced;
+    }
+}
+impl Default for Translation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a helper for building a translation, with a method for emitting each kind of instruction, and an allocator for the tmps
+/// used by the translation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TranslationBuilder {
+    translation: Translation,
+    tmps: TmpAllocator,
+}
+impl TranslationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// creates a builder which allocates its tmps using the given allocator, so that they don't overlap the tmps which were
+    /// already allocated by it.
+    pub fn with_tmps(tmps: TmpAllocator) -> Self {
+        Self {
+            translation: Translation::new(),
+            tmps,
+        }
+    }
+    /// emits an instruction with the given opcode and operands.
+    pub fn insn(&mut self, opcode: Opcode, first_operand: Operand, second_operand: Operand) {
+        self.translation
+            .insns
+            .push(Insn::new(opcode, first_operand, second_operand));
+    }
+    pub fn mov(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::Move, dst, src)
+    }
+    pub fn add(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::Add, dst, src)
+    }
+    pub fn sub(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::Sub, dst, src)
+    }
+    pub fn and(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::And, dst, src)
+    }
+    pub fn or(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::Or, dst, src)
+    }
+    pub fn xor(&mut self, dst: Operand, src: Operand) {
+        self.insn(Opcode::Xor, dst, src)
+    }
+    pub fn store(&mut self, addr: Operand, value: Operand) {
+        self.insn(Opcode::Store, addr, value)
+    }
+    pub fn load(&mut self, dst: Operand, addr: Operand) {
+        self.insn(Opcode::Load, dst, addr)
+    }
+    /// allocates a tmp operand which doesn't overlap any of the other tmps allocated by this builder.
+    pub fn alloc_tmp(&mut self, size: OperandSize) -> Operand {
+        self.tmps.alloc(size)
+    }
+    /// returns the built translation.
+    pub fn build(self) -> Translation {
+        self.translation
+    }
+}
+impl core::ops::Deref for TranslationBuilder {
+    type Target = Translation;
+
+    fn deref(&self) -> &Self::Target {
+        &self.translation
+    }
+}
+impl core::ops::DerefMut for TranslationBuilder {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.translation
+    }
+}
+impl From<TranslationBuilder> for Translation {
+    fn from(builder: TranslationBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// a consumer of lifted ir instructions, which receives them one at a time.
+pub trait InsnSink {
+    fn emit(&mut self, insn: Insn);
+}
+impl InsnSink for Translation {
+    fn emit(&mut self, insn: Insn) {
+        self.insns.push(insn);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranslateError {
+    /// the provided code is empty.
+    UnexpectedEof,
+    /// the instruction extends past the end of the provided code.
+    TruncatedInstruction,
+    /// the instruction's opcode is unknown or not supported. this contains the opcode byte which could not be decoded.
+    UnknownOpcode(u8),
+    /// the instruction can't be translated in the current cpu mode.
+    UnsupportedInMode,
+    /// the instruction is longer than the maximum instruction length of the architecture.
+    TooLong,
+    /// the instruction has a prefix which requests an atomic access, like the x86 `lock` prefix, but it is not an atomic
+    /// read-modify-write of a memory operand.
+    InvalidLock,
+    /// the instruction accesses a register with a size which that register can't be accessed with, for example a byte sized
+    /// access to an x86 register which is only byte addressable with a rex prefix, in an instruction without one.
+    InvalidRegisterSize,
+}
+
+/// a violation of the invariants of an opcode, which are described in [`Opcode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationError {
+    /// the instruction at the given index writes to a constant operand.
+    WriteToConst(usize),
+    /// the operands of the instruction at the given index have different sizes.
+    SizeMismatch(usize),
+    /// the address operand of the instruction at the given index is a ram operand.
+    RamAddress(usize),
+    /// the condition operand of the instruction at the given index is not a 1 byte operand.
+    InvalidConditionSize(usize),
+    /// an unused operand of the instruction at the given index is not a zero constant.
+    UnusedOperandNotZero(usize),
+}
+
+/// returns the size of a memory access which covers two contiguous accesses of the given size, if a single access can be
+/// that wide. memory accesses are at most 8 bytes wide, since [`OperandSize::B16`] is only valid for the double width
+/// operands of the multiplication and division opcodes.
+fn merged_access_size(half_size: OperandSize) -> Option<OperandSize> {
+    match OperandSize::from_bytes(half_size.bytes() * 2)? {
+        OperandSize::B16 => None,
+        merged_size => Some(merged_size),
+    }
+}
+
+/// tries to merge two stores of the halves of a value to contiguous constant addresses into a single store.
+fn merge_stores(first: &Insn, second: &Insn) -> Option<Insn> {
+    if first.opcode != Opcode::Store
+        || second.opcode != Opcode::Store
+        || first.is_atomic
+        || second.is_atomic
+    {
+        return None;
+    }
+    let [first_addr, first_value] = &first.operands;
+    let [second_addr, second_value] = &second.operands;
+    if first_addr.addr.space != OperandSpace::Const
+        || second_addr.addr.space != OperandSpace::Const
+        || first_addr.size != second_addr.size
+        || first_value.size != second_value.size
+    {
+        return None;
+    }
+    let half_size = first_value.size;
+    let merged_size = merged_access_size(half_size)?;
+
+    // order the stores by address, since the high half may be stored before the low half.
+    let half_len = half_size.bytes() as u64;
+    let (addr, low, high) =
+        if second_addr.addr.offset == first_addr.addr.offset.wrapping_add(half_len) {
+            (first_addr, first_value, second_value)
+        } else if first_addr.addr.offset == second_addr.addr.offset.wrapping_add(half_len) {
+            (second_addr, second_value, first_value)
+        } else {
+            return None;
+        };
+
+    let merged_value =
+        if low.addr.space == OperandSpace::Const && high.addr.space == OperandSpace::Const {
+            Operand::constant(
+                (high.addr.offset << half_size.bits()) | (low.addr.offset & half_size.mask()),
+                merged_size,
+            )
+        } else if low.addr.space == high.addr.space
+            && high.addr.offset == low.addr.offset.wrapping_add(half_len)
+        {
+            Operand {
+                addr: low.addr.clone(),
+                size: merged_size,
+            }
+        } else {
+            return None;
+        };
+
+    Some(Insn::new(Opcode::Store, addr.clone(), merged_value))
+}
+
+/// tries to merge two loads from contiguous constant addresses into the halves of a value into a single load.
+fn merge_loads(first: &Insn, second: &Insn) -> Option<Insn> {
+    if first.opcode != Opcode::Load
+        || second.opcode != Opcode::Load
+        || first.is_atomic
+        || second.is_atomic
+    {
+        return None;
+    }
+    let [first_dst, first_addr] = &first.operands;
+    let [second_dst, second_addr] = &second.operands;
+    if first_addr.addr.space != OperandSpace::Const
+        || second_addr.addr.space != OperandSpace::Const
+        || first_addr.size != second_addr.size
+        || first_dst.size != second_dst.size
+        || first_dst.addr.space != second_dst.addr.space
+    {
+        return None;
+    }
+    let half_size = first_dst.size;
+    let merged_size = merged_access_size(half_size)?;
+
+    // order the loads by address, since the high half may be loaded before the low half.
+    let half_len = half_size.bytes() as u64;
+    let (addr, low, high) =
+        if second_addr.addr.offset == first_addr.addr.offset.wrapping_add(half_len) {
+            (first_addr, first_dst, second_dst)
+        } else if first_addr.addr.offset == second_addr.addr.offset.wrapping_add(half_len) {
+            (second_addr, second_dst, first_dst)
+        } else {
+            return None;
+        };
+    if high.addr.offset != low.addr.offset.wrapping_add(half_len) {
+        return None;
+    }
+
+    Some(Insn::new(
+        Opcode::Load,
+        Operand {
+            addr: low.addr.clone(),
+            size: merged_size,
+        },
+        addr.clone(),
+    ))
+}
+
+/// the result of translating a single instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TranslationResult {
+    pub translation: Translation,
+    /// the length of the translated instruction in bytes, including all of its prefixes. this can be used to advance to the
+    /// next instruction.
+    pub bytes_consumed: usize,
+}
+
+/// information about a single instruction of a translated block.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockInsnInfo {
+    /// the offset of the instruction from the start of the block.
+    pub offset: usize,
+    /// the length of the instruction in bytes.
+    pub bytes_consumed: usize,
+    /// the range of ir instructions in the block's translation which were produced by this instruction.
+    pub ir_insns: core::ops::Range<usize>,
+}
+
+/// a range of bytes of a translated block which could not be decoded, and was skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockGap {
+    /// the offset of the first skipped byte from the start of the block.
+    pub offset: usize,
+    /// the amount of skipped bytes.
+    pub len: usize,
+    /// the error returned when trying to decode an instruction at the start of the gap.
+    pub error: TranslateError,
+}
+
+/// the way in which block translation handles instructions which can't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DecodeErrorPolicy {
+    /// stop translating the block at the undecodable instruction. the block contains the instructions before it, and the
+    /// error is recorded in [`BlockTranslation::error`].
+    #[default]
+    Strict,
+    /// skip a single byte at the undecodable instruction and continue translating after it, since the length of the
+    /// instruction is unknown. consecutive skipped bytes are recorded as a single [`BlockGap`]. this is useful for code
+    /// regions which may be corrupt or contain data.
+    BestEffort,
+}
+
+/// the result of translating a block of consecutive instructions.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockTranslation {
+    /// the ir instructions of all the translated instructions, one after the other.
+    pub translation: Translation,
+    /// information about each of the translated instructions, in order.
+    pub insns: Vec<BlockInsnInfo>,
+    /// the ranges of bytes which were skipped since they could not be decoded, in order. only translation with
+    /// [`DecodeErrorPolicy::BestEffort`] skips bytes.
+    pub gaps: Vec<BlockGap>,
+    /// the error which stopped the translation of the block, if any. only translation with [`DecodeErrorPolicy::Strict`]
+    /// stops on errors.
+    pub error: Option<TranslateError>,
+}
+#[cfg(feature = "alloc")]
+impl BlockTranslation {
+    /// the total length in bytes of the translated instructions, including the skipped gaps between them.
+    pub fn bytes_consumed(&self) -> usize {
+        let insns_end = self
+            .insns
+            .last()
+            .map_or(0, |insn| insn.offset + insn.bytes_consumed);
+        let gaps_end = self.gaps.last().map_or(0, |gap| gap.offset + gap.len);
+        insns_end.max(gaps_end)
+    }
+}
+
+/// a block of a lifted function.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionBlock {
+    /// the translation of the block.
+    pub block: BlockTranslation,
+    /// the addresses of the blocks to which control may flow when leaving this block, when they are known. targets which
+    /// are outside of the lifted code have no block of their own.
+    pub successors: Vec<u64>,
+}
+
+/// a function which was lifted by following its control flow from its entry point.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Function {
+    /// the address of the entry block of the function.
+    pub entry: u64,
+    /// the blocks of the function, by their start address. no two blocks overlap.
+    pub blocks: BTreeMap<u64, FunctionBlock>,
 }
 
 pub trait ArchCtx {
-    fn translate(&self, code: &[u8]) -> Translation;
+    /// translates the first instruction in the given code, which is located at the given address. the address is used for
+    /// resolving pc relative operands, like the targets of relative branches.
+    fn translate_at(&self, code: &[u8], addr: u64) -> Result<TranslationResult, TranslateError>;
+
+    /// returns the description of the registers of this architecture.
+    fn reg_file(&self) -> &'static RegFile;
+
+    /// translates the first instruction in the given code, assuming that it is located at address 0.
+    fn translate(&self, code: &[u8]) -> Result<TranslationResult, TranslateError> {
+        self.translate_at(code, 0)
+    }
 }
@@ -0,0 +1,168 @@
+use crate::{
+    Insn, Opcode, Operand, OperandSpace, Translation, TranslationInsns, TRANSLATION_MAX_INSNS,
+};
+
+/// replaces common instruction idioms with simpler equivalent instructions.
+///
+/// a `Xor` or a `Sub` of an operand with itself, which are the canonical ways of zeroing a register, are replaced with a
+/// `Move` of zero into that operand. an `And` or an `Or` of an operand with itself doesn't change it, so it is removed.
+pub fn simplify_idioms(translation: &mut Translation) {
+    translation.insns.retain(|insn| {
+        let [dst, src] = &insn.operands;
+        if dst != src {
+            return true;
+        }
+        match insn.opcode {
+            Opcode::Xor | Opcode::Sub => {
+                *insn = Insn::new(Opcode::Move, dst.clone(), Operand::zero(dst.size));
+                true
+            }
+            Opcode::And | Opcode::Or => false,
+            _ => true,
+        }
+    });
+}
+
+/// folds consecutive operations with constants on the same operand into a single instruction, and removes operations which
+/// don't change the value of their operand.
+///
+/// for example, `Move tmp, 0; Add tmp, 5; Add tmp, 3` is folded into `Move tmp, 8`. the folded constants wrap around at the
+/// size of the operand, like the original operations.
+pub fn fold_constants(translation: &mut Translation) {
+    let mut folded = TranslationInsns::new();
+    for insn in translation.insns.drain(..) {
+        if let Some(merged) = folded.last().and_then(|prev| fold_pair(prev, &insn)) {
+            *folded.last_mut().unwrap() = merged;
+            if is_identity(folded.last().unwrap()) {
+                folded.pop();
+            }
+        } else if !is_identity(&insn) {
+            folded.push(insn);
+        }
+    }
+    translation.insns = folded;
+}
+
+/// returns the value of the constant source operand of the given instruction, if it is a constant with the same size as the
+/// destination operand.
+fn const_src(insn: &Insn) -> Option<u64> {
+    let [dst, src] = &insn.operands;
+    src.as_unsigned().filter(|_| src.size == dst.size)
+}
+
+/// returns whether the given instruction doesn't change the value of its destination operand.
+fn is_identity(insn: &Insn) -> bool {
+    let Some(value) = const_src(insn) else {
+        return false;
+    };
+    match insn.opcode {
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Or
+        | Opcode::Xor
+        | Opcode::Shl
+        | Opcode::Shr
+        | Opcode::Sar => value == 0,
+        Opcode::Rol | Opcode::Ror => value % insn.operands[0].size.bits() as u64 == 0,
+        Opcode::And => value == insn.operands[0].size.mask(),
+        _ => false,
+    }
+}
+
+/// tries to fold two consecutive operations with constants on the same operand into a single instruction.
+fn fold_pair(first: &Insn, second: &Insn) -> Option<Insn> {
+    let dst = &first.operands[0];
+    if *dst != second.operands[0] {
+        return None;
+    }
+    let first_value = const_src(first)?;
+    let second_value = const_src(second)?;
+    let (opcode, value) = match (first.opcode, second.opcode) {
+        (Opcode::Move, Opcode::Add) => (Opcode::Move, first_value.wrapping_add(second_value)),
+        (Opcode::Move, Opcode::Sub) => (Opcode::Move, first_value.wrapping_sub(second_value)),
+        (Opcode::Move, Opcode::And) => (Opcode::Move, first_value & second_value),
+        (Opcode::Move, Opcode::Or) => (Opcode::Move, first_value | second_value),
+        (Opcode::Move, Opcode::Xor) => (Opcode::Move, first_value ^ second_value),
+        (Opcode::Add, Opcode::Add) => (Opcode::Add, first_value.wrapping_add(second_value)),
+        (Opcode::Add, Opcode::Sub) => (Opcode::Add, first_value.wrapping_sub(second_value)),
+        (Opcode::Sub, Opcode::Add) => (Opcode::Add, second_value.wrapping_sub(first_value)),
+        (Opcode::Sub, Opcode::Sub) => (Opcode::Sub, first_value.wrapping_add(second_value)),
+        (Opcode::And, Opcode::And) => (Opcode::And, first_value & second_value),
+        (Opcode::Or, Opcode::Or) => (Opcode::Or, first_value | second_value),
+        (Opcode::Xor, Opcode::Xor) => (Opcode::Xor, first_value ^ second_value),
+        _ => return None,
+    };
+    Some(Insn::new(
+        opcode,
+        dst.clone(),
+        Operand::constant(value, dst.size),
+    ))
+}
+
+/// removes instructions whose only effect is writing to a tmp which is not read by any of the following instructions of the
+/// translation.
+///
+/// writes to registers and to ram, along with `Load`s, which may fault, and instructions with other side effects, are
+/// always considered live.
+pub fn eliminate_dead_stores(translation: &mut Translation) {
+    let insns = &translation.insns;
+    let mut is_live = [true; TRANSLATION_MAX_INSNS];
+    // scan backwards, so that the liveness of each instruction only depends on the instructions after it which are live.
+    for i in (0..insns.len()).rev() {
+        let [dst] = insns[i].written_operands() else {
+            continue;
+        };
+        if dst.addr.space != OperandSpace::Tmp || insns[i].opcode == Opcode::Load {
+            continue;
+        }
+        is_live[i] = false;
+        for (later_insn, _) in insns[i + 1..]
+            .iter()
+            .zip(&is_live[i + 1..])
+            .filter(|(_, is_live)| **is_live)
+        {
+            if later_insn
+                .read_operands()
+                .iter()
+                .any(|operand| overlaps(operand, dst))
+            {
+                is_live[i] = true;
+                break;
+            }
+            if later_insn
+                .written_operands()
+                .iter()
+                .any(|operand| covers(operand, dst))
+            {
+                // the tmp is overwritten before being read.
+                break;
+            }
+        }
+    }
+    let mut i = 0;
+    translation.insns.retain(|_| {
+        i += 1;
+        is_live[i - 1]
+    });
+}
+
+/// returns the range of offsets covered by the given operand in its operand space.
+fn offsets(operand: &Operand) -> core::ops::Range<u64> {
+    operand.addr.offset..operand.addr.offset + operand.size.bytes() as u64
+}
+
+/// returns whether the given operands share any bytes.
+fn overlaps(a: &Operand, b: &Operand) -> bool {
+    let (a_offsets, b_offsets) = (offsets(a), offsets(b));
+    a.addr.space == b.addr.space
+        && a_offsets.start < b_offsets.end
+        && b_offsets.start < a_offsets.end
+}
+
+/// returns whether the first operand covers all of the bytes of the second operand.
+fn covers(a: &Operand, b: &Operand) -> bool {
+    let (a_offsets, b_offsets) = (offsets(a), offsets(b));
+    a.addr.space == b.addr.space
+        && a_offsets.start <= b_offsets.start
+        && b_offsets.end <= a_offsets.end
+}
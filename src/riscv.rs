@@ -0,0 +1,182 @@
+use crate::{
+    ArchCtx, Opcode, Operand, OperandSize, RegFile, RegGroup, TranslateError, TranslationBuilder,
+    TranslationResult,
+};
+
+/// the distance between the offsets of consecutive integer registers in the register space. the 32 integer registers are
+/// stored one after the other starting at offset 0, in the order of their encoding.
+pub const XREG_STEP: u64 = 8;
+
+define_reg_operands! {
+    XREG_STEP, B8,
+    X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, X11, X12, X13, X14, X15,
+    X16, X17, X18, X19, X20, X21, X22, X23, X24, X25, X26, X27, X28, X29, X30, X31
+}
+
+/// the integer registers of risc-v, laid out as described in [`XREG_STEP`].
+pub const REG_FILE: RegFile = RegFile {
+    groups: &[RegGroup {
+        names: &[
+            "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13",
+            "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25",
+            "x26", "x27", "x28", "x29", "x30", "x31",
+        ],
+        first_offset: 0,
+        step: XREG_STEP,
+        size: OperandSize::B8,
+    }],
+};
+
+/// the length of an instruction in bytes. compressed instructions are not supported.
+pub const INSN_LEN: usize = 4;
+
+/// returns the operand of the integer register with the given 5 bit index.
+fn xreg_operand(index: u32) -> Operand {
+    Operand::reg(index as u64 * XREG_STEP, OperandSize::B8)
+}
+
+/// the fields of an encoded instruction which are shared by most of the instruction formats.
+struct EncodedInsn(u32);
+impl EncodedInsn {
+    fn opcode(&self) -> u32 {
+        self.0 & 0x7f
+    }
+    fn rd(&self) -> u32 {
+        (self.0 >> 7) & 0x1f
+    }
+    fn funct3(&self) -> u32 {
+        (self.0 >> 12) & 0b111
+    }
+    fn rs1(&self) -> u32 {
+        (self.0 >> 15) & 0x1f
+    }
+    fn rs2(&self) -> u32 {
+        (self.0 >> 20) & 0x1f
+    }
+    fn funct7(&self) -> u32 {
+        self.0 >> 25
+    }
+    /// the sign extended immediate of the I instruction format.
+    fn i_imm(&self) -> i64 {
+        ((self.0 as i32) >> 20) as i64
+    }
+    /// the sign extended immediate of the S instruction format.
+    fn s_imm(&self) -> i64 {
+        ((((self.0 as i32) >> 25) << 5) | ((self.0 >> 7) & 0x1f) as i32) as i64
+    }
+    /// the sign extended immediate of the J instruction format.
+    fn j_imm(&self) -> i64 {
+        let imm = (((self.0 as i32) >> 31) << 20) as u32
+            | (self.0 & 0xff000)
+            | ((self.0 >> 9) & 0x800)
+            | ((self.0 >> 20) & 0x7fe);
+        imm as i32 as i64
+    }
+}
+
+pub struct RiscvCtx;
+impl RiscvCtx {
+    /// returns the operand of the given source register. the `x0` register is hardwired to 0.
+    fn src_reg(&self, index: u32) -> Operand {
+        if index == 0 {
+            Operand::zero(OperandSize::B8)
+        } else {
+            xreg_operand(index)
+        }
+    }
+    /// returns the operand to which the given destination register should be written. writes to the `x0` register are
+    /// discarded, so they are redirected to a tmp.
+    fn dst_reg(&self, index: u32, builder: &mut TranslationBuilder) -> Operand {
+        if index == 0 {
+            builder.alloc_tmp(OperandSize::B8)
+        } else {
+            xreg_operand(index)
+        }
+    }
+    /// emits instructions which set `dst` to the sum of `a` and `b`, taking into account that `dst` may be the same operand
+    /// as either of them.
+    fn add(&self, dst: Operand, a: Operand, b: Operand, builder: &mut TranslationBuilder) {
+        if dst == b {
+            builder.add(dst, a);
+        } else {
+            if dst != a {
+                builder.mov(dst.clone(), a);
+            }
+            builder.add(dst, b);
+        }
+    }
+    /// emits instructions which compute the address `rs1 + imm` into a tmp, and returns the tmp.
+    fn mem_address(&self, rs1: u32, imm: i64, builder: &mut TranslationBuilder) -> Operand {
+        let addr = builder.alloc_tmp(OperandSize::B8);
+        builder.mov(addr.clone(), self.src_reg(rs1));
+        if imm != 0 {
+            builder.add(addr.clone(), Operand::constant(imm as u64, OperandSize::B8));
+        }
+        addr
+    }
+}
+impl ArchCtx for RiscvCtx {
+    fn reg_file(&self) -> &'static RegFile {
+        &REG_FILE
+    }
+
+    fn translate_at(&self, code: &[u8], addr: u64) -> Result<TranslationResult, TranslateError> {
+        if code.is_empty() {
+            return Err(TranslateError::UnexpectedEof);
+        }
+        let Some(encoded) = code.first_chunk::<INSN_LEN>() else {
+            return Err(TranslateError::TruncatedInstruction);
+        };
+        let insn = EncodedInsn(u32::from_le_bytes(*encoded));
+        let unknown = TranslateError::UnknownOpcode(insn.opcode() as u8);
+
+        let mut builder = TranslationBuilder::new();
+        match (insn.opcode(), insn.funct3()) {
+            // add
+            (0b0110011, 0b000) if insn.funct7() == 0 => {
+                let dst = self.dst_reg(insn.rd(), &mut builder);
+                let (a, b) = (self.src_reg(insn.rs1()), self.src_reg(insn.rs2()));
+                self.add(dst, a, b, &mut builder);
+            }
+            // addi
+            (0b0010011, 0b000) => {
+                let dst = self.dst_reg(insn.rd(), &mut builder);
+                let imm = Operand::constant(insn.i_imm() as u64, OperandSize::B8);
+                self.add(dst, self.src_reg(insn.rs1()), imm, &mut builder);
+            }
+            // ld
+            (0b0000011, 0b011) => {
+                let addr = self.mem_address(insn.rs1(), insn.i_imm(), &mut builder);
+                let dst = self.dst_reg(insn.rd(), &mut builder);
+                builder.load(dst, addr);
+            }
+            // sd
+            (0b0100011, 0b011) => {
+                let addr = self.mem_address(insn.rs1(), insn.s_imm(), &mut builder);
+                builder.store(addr, self.src_reg(insn.rs2()));
+            }
+            // jal
+            (0b1101111, _) => {
+                let target =
+                    Operand::constant(addr.wrapping_add(insn.j_imm() as u64), OperandSize::B8);
+                if insn.rd() == 0 {
+                    builder.insn(Opcode::Jump, target, Operand::zero(OperandSize::B1));
+                } else {
+                    // a jump which saves the return address is a call.
+                    builder.mov(
+                        xreg_operand(insn.rd()),
+                        Operand::constant(addr.wrapping_add(INSN_LEN as u64), OperandSize::B8),
+                    );
+                    builder.insn(Opcode::Call, target, Operand::zero(OperandSize::B1));
+                }
+            }
+            _ => return Err(unknown),
+        }
+        let translation = builder.build();
+        debug_assert_eq!(translation.validate(), Ok(()));
+        Ok(TranslationResult {
+            translation,
+            bytes_consumed: INSN_LEN,
+        })
+    }
+}
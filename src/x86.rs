@@ -1,58 +1,217 @@
-use std::path::StripPrefixError;
+// the code generated by the `bitpiece` macro triggers this lint.
+#![allow(clippy::unused_unit)]
 
-use crate::{ArchCtx, Insn, Opcode, Operand, OperandAddr, OperandSize, OperandSpace, Translation};
-use bitpiece::{bitpiece, BitPiece, BitStorage};
-use strum::{EnumIter, IntoEnumIterator};
+#[cfg(feature = "alloc")]
+use crate::{
+    BlockGap, BlockInsnInfo, BlockTranslation, DecodeErrorPolicy, Function, FunctionBlock,
+};
+#[cfg(feature = "alloc")]
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
 
-macro_rules! define_reg_operand {
-    {$name: ident, $offset: expr, $size: ident} => {
-        pub const $name: Operand = Operand {
-            addr: OperandAddr {
-                space: OperandSpace::Regs,
-                offset: $offset,
-            },
-            size: OperandSize::$size,
-        };
+use crate::{
+    ArchCtx, Cond, Insn, InsnSink, Intrinsic, Opcode, Operand, OperandAddr, OperandSize,
+    OperandSpace, RegFile, RegGroup, TmpAllocator, TranslateError, Translation, TranslationBuilder,
+    TranslationResult,
+};
+use bitpiece::{bitpiece, BitPiece, BitStorage, B2, B3};
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
 
-    };
+/// the distance between the offsets of consecutive general purpose registers in the register space. this is shared by the
+/// named register operands and by [`Reg::operand`] so that both always agree on the register layout.
+///
+/// the register space is laid out as follows:
+/// - the 16 general purpose registers, starting at offset 0, in the order of their encoding.
+/// - the flags, starting at [`FLAGS_OFFSET`], one byte per flag.
+/// - the bases of the 6 segments, starting at [`SEGMENT_BASES_OFFSET`], in the order of their encoding.
+pub const GPR_STEP: u64 = 8;
+
+define_reg_operands! {
+    GPR_STEP, B8,
+    RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI, R8, R9, R10, R11, R12, R13, R14, R15
+}
+define_reg_operands! {
+    GPR_STEP, B1,
+    AL, CL, DL, BL, SPL, BPL, SIL, DIL, R8B, R9B, R10B, R11B, R12B, R13B, R14B, R15B
 }
 
-macro_rules! define_reg_operands_single {
-    {$step_size: literal, $size: ident, $prev_name: ident, $name: ident} => {
-        define_reg_operand! {$name, $prev_name.addr.offset + $step_size, $size}
-    };
+/// the offset in the register space at which the flags are stored, right after the 16 general purpose registers.
+/// each flag is stored as a separate 1 byte operand whose value is either 0 or 1, at the offset `FLAGS_OFFSET + flag`
+/// where `flag` is the index of the flag in [`Flag`].
+pub const FLAGS_OFFSET: u64 = 16 * GPR_STEP;
+
+/// a flag of the flags register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Flag {
+    /// carry flag
+    Cf,
+    /// parity flag
+    Pf,
+    /// auxiliary carry flag
+    Af,
+    /// zero flag
+    Zf,
+    /// sign flag
+    Sf,
+    /// overflow flag
+    Of,
+    /// direction flag
+    Df,
 }
 
-macro_rules! define_reg_operands_inner {
-    // the case for the last operand
-    {$step_size: literal, $size: ident, $prev_name: ident, $name: ident} => {
-        define_reg_operands_single!{$step_size, $size, $prev_name, $name}
-    };
+/// returns the operand of the given flag, in the flags region of the register space.
+pub const fn flag(flag: Flag) -> Operand {
+    Operand::reg(FLAGS_OFFSET + flag as u64, OperandSize::B1)
+}
 
-    // the common case of the non-last operand
-    {$step_size: literal, $size: ident, $prev_name: ident, $name: ident, $($names: ident),+} => {
-        // define the current operand
-        define_reg_operands_inner! {$step_size, $size, $prev_name, $name}
+pub const FLAG_CF: Operand = flag(Flag::Cf);
+pub const FLAG_PF: Operand = flag(Flag::Pf);
+pub const FLAG_AF: Operand = flag(Flag::Af);
+pub const FLAG_ZF: Operand = flag(Flag::Zf);
+pub const FLAG_SF: Operand = flag(Flag::Sf);
+pub const FLAG_OF: Operand = flag(Flag::Of);
+pub const FLAG_DF: Operand = flag(Flag::Df);
 
-        // define the rest of the operands
-        define_reg_operands_inner! {$step_size, $size, $name, $($names),+}
-    };
+/// a condition encoded in the low 4 bits of the opcode of the `jcc`, `setcc` and `cmovcc` instruction families.
+///
+/// the conditions come in pairs, where the odd condition of each pair is the negation of the even one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum ConditionCode {
+    /// overflow, `OF`
+    O,
+    /// not overflow, `!OF`
+    No,
+    /// below, `CF`
+    B,
+    /// above or equal, `!CF`
+    Ae,
+    /// equal, `ZF`
+    E,
+    /// not equal, `!ZF`
+    Ne,
+    /// below or equal, `CF | ZF`
+    Be,
+    /// above, `!(CF | ZF)`
+    A,
+    /// sign, `SF`
+    S,
+    /// not sign, `!SF`
+    Ns,
+    /// parity, `PF`
+    P,
+    /// not parity, `!PF`
+    Np,
+    /// less, `SF ^ OF`
+    L,
+    /// greater or equal, `!(SF ^ OF)`
+    Ge,
+    /// less or equal, `(SF ^ OF) | ZF`
+    Le,
+    /// greater, `!((SF ^ OF) | ZF)`
+    G,
 }
+impl ConditionCode {
+    /// returns the condition encoded in the low 4 bits of the given opcode byte.
+    pub fn from_opcode_low_nibble(opcode: u8) -> Self {
+        match opcode & 0xf {
+            0x0 => Self::O,
+            0x1 => Self::No,
+            0x2 => Self::B,
+            0x3 => Self::Ae,
+            0x4 => Self::E,
+            0x5 => Self::Ne,
+            0x6 => Self::Be,
+            0x7 => Self::A,
+            0x8 => Self::S,
+            0x9 => Self::Ns,
+            0xa => Self::P,
+            0xb => Self::Np,
+            0xc => Self::L,
+            0xd => Self::Ge,
+            0xe => Self::Le,
+            0xf => Self::G,
+            _ => unreachable!(),
+        }
+    }
 
-macro_rules! define_reg_operands {
-    {$step_size: literal, $size: ident, $first_name: ident, $($name: ident),+} => {
-        // define the first operand with offset 0
-        define_reg_operand! {$first_name, 0, $size}
+    /// returns whether this condition is the negation of the test performed by [`ConditionCode::to_flag_test`].
+    pub fn is_negated(&self) -> bool {
+        (*self as u8) & 1 != 0
+    }
 
-        // define the rest of the operands following it
-        define_reg_operands_inner! {$step_size, $size, $first_name, $($name),+}
-    };
+    /// returns the flag expression tested by this condition.
+    pub fn to_flag_test(&self) -> FlagTest {
+        let (xored_flags, ored_flag): (&'static [Flag], Option<Flag>) = match self {
+            Self::O | Self::No => (&[Flag::Of], None),
+            Self::B | Self::Ae => (&[Flag::Cf], None),
+            Self::E | Self::Ne => (&[Flag::Zf], None),
+            Self::Be | Self::A => (&[Flag::Cf], Some(Flag::Zf)),
+            Self::S | Self::Ns => (&[Flag::Sf], None),
+            Self::P | Self::Np => (&[Flag::Pf], None),
+            Self::L | Self::Ge => (&[Flag::Sf, Flag::Of], None),
+            Self::Le | Self::G => (&[Flag::Sf, Flag::Of], Some(Flag::Zf)),
+        };
+        FlagTest {
+            xored_flags,
+            ored_flag,
+            is_negated: self.is_negated(),
+        }
+    }
+
+    /// evaluates this condition given the values of the flags.
+    pub fn evaluate(&self, flag_value: impl Fn(Flag) -> bool) -> bool {
+        self.to_flag_test().evaluate(flag_value)
+    }
+}
+
+/// a boolean expression of the flags, of the form `((xored_flags[0] ^ xored_flags[1] ^ ...) | ored_flag) ^ is_negated`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlagTest {
+    /// the flags which are xored together. never empty.
+    pub xored_flags: &'static [Flag],
+    /// a flag which is ored into the xored flags, if any.
+    pub ored_flag: Option<Flag>,
+    /// whether the result is negated.
+    pub is_negated: bool,
+}
+impl FlagTest {
+    /// evaluates this expression given the values of the flags.
+    pub fn evaluate(&self, flag_value: impl Fn(Flag) -> bool) -> bool {
+        let xored = self
+            .xored_flags
+            .iter()
+            .fold(false, |acc, &flag| acc ^ flag_value(flag));
+        let ored = self.ored_flag.is_some_and(&flag_value);
+        (xored | ored) ^ self.is_negated
+    }
 }
 
-define_reg_operands! {8, B8, RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI}
-define_reg_operands! {8, B1, AL, CL, DL, BL, SPL, BPL, SIL, DIL}
+/// the offset in the register space at which the segment base registers are stored, right after the flags. the base of
+/// each segment is stored as an 8 byte operand at the offset `SEGMENT_BASES_OFFSET + segment * GPR_STEP`, where `segment` is
+/// the index of the segment in [`Segment`].
+pub const SEGMENT_BASES_OFFSET: u64 = FLAGS_OFFSET + GPR_STEP;
 
-#[bitpiece(3)]
+/// returns the operand of the base address of the given segment, in the segment bases region of the register space.
+pub const fn segment_base(segment: Segment) -> Operand {
+    Operand::reg(
+        SEGMENT_BASES_OFFSET + segment as u64 * GPR_STEP,
+        OperandSize::B8,
+    )
+}
+
+pub const ES_BASE: Operand = segment_base(Segment::Es);
+pub const CS_BASE: Operand = segment_base(Segment::Cs);
+pub const SS_BASE: Operand = segment_base(Segment::Ss);
+pub const DS_BASE: Operand = segment_base(Segment::Ds);
+pub const FS_BASE: Operand = segment_base(Segment::Fs);
+pub const GS_BASE: Operand = segment_base(Segment::Gs);
+
+/// a general purpose register, identified by its 4 bit index. the registers `r8` to `r15` can only be encoded using the
+/// extension bits of the rex prefix.
+#[bitpiece(4)]
 #[derive(Debug, Clone, Copy)]
 pub enum Reg {
     Rax = 0,
@@ -63,17 +222,70 @@ pub enum Reg {
     RbpCh = 5,
     RsiDh = 6,
     RdiBh = 7,
+    R8 = 8,
+    R9 = 9,
+    R10 = 10,
+    R11 = 11,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+    R15 = 15,
 }
 impl Reg {
-    pub const MAX_VALUE: Reg = Reg::RdiBh;
-    pub fn operand(&self, size: OperandSize) -> Operand {
-        Operand {
-            addr: OperandAddr {
-                space: OperandSpace::Regs,
-                offset: *self as u64 * 8,
-            },
-            size,
+    pub const MAX_VALUE: Reg = Reg::R15;
+    /// returns the operand of the given size which accesses the low bytes of this register.
+    ///
+    /// ```
+    /// # use pis::{x86::*, *};
+    /// assert_eq!(Reg::Rcx.operand(OperandSize::B4), Operand::reg(8, OperandSize::B4));
+    /// ```
+    pub const fn operand(&self, size: OperandSize) -> Operand {
+        Operand::reg(*self as u64 * GPR_STEP, size)
+    }
+    /// returns the operand of this register with the given size, or an error if that register and size combination can't be
+    /// expressed by [`Reg::operand`] in an instruction with or without a rex prefix.
+    ///
+    /// without a rex prefix, byte sized accesses to the last 4 of the first 8 registers refer to the legacy high byte
+    /// registers (`ah`, `ch`, `dh`, `bh`) rather than to `spl`, `bpl`, `sil` and `dil`, and the high byte registers are not at
+    /// the register's offset. the registers `r8` to `r15` can't be accessed at all without a rex prefix.
+    pub fn checked_operand(
+        &self,
+        size: OperandSize,
+        has_rex_prefix: bool,
+    ) -> Result<Operand, TranslateError> {
+        let is_high_byte_reg =
+            matches!(self, Self::RspAh | Self::RbpCh | Self::RsiDh | Self::RdiBh)
+                && size == OperandSize::B1;
+        let requires_rex_prefix = is_high_byte_reg || *self as u8 >= 8;
+        if (requires_rex_prefix && !has_rex_prefix) || size == OperandSize::B16 {
+            return Err(TranslateError::InvalidRegisterSize);
         }
+        Ok(self.operand(size))
+    }
+    /// returns the operand of the legacy high byte register of this register (`ah`, `ch`, `dh` or `bh`), which is its second
+    /// byte, or an error if this register doesn't have one in an instruction with or without a rex prefix.
+    ///
+    /// only the first 4 registers have a high byte register, and it can't be accessed in an instruction with a rex prefix.
+    pub fn high_byte_operand(&self, has_rex_prefix: bool) -> Result<Operand, TranslateError> {
+        if has_rex_prefix || !matches!(self, Self::Rax | Self::Rcx | Self::Rdx | Self::Rbx) {
+            return Err(TranslateError::InvalidRegisterSize);
+        }
+        Ok(Operand::reg(*self as u64 * GPR_STEP + 1, OperandSize::B1))
+    }
+}
+
+impl Operand {
+    /// creates the operand of the given size which accesses the low bytes of the given x86 general purpose register. this
+    /// is the same as [`Reg::operand`].
+    ///
+    /// ```
+    /// # use pis::{x86::*, *};
+    /// let rcx = Operand::x86_reg(Reg::Rcx, OperandSize::B8);
+    /// assert_eq!(rcx.addr.offset, 8);
+    /// assert_eq!(rcx, RCX);
+    /// ```
+    pub const fn x86_reg(reg: Reg, size: OperandSize) -> Self {
+        reg.operand(size)
     }
 }
 
@@ -129,30 +341,200 @@ pub struct InsnLegacyPrefixes {
     pub by_group: [Option<LegacyPrefix>; LegacyPrefixGroup::GROUPS_AMOUNT],
 }
 impl InsnLegacyPrefixes {
+    /// adds the given prefix to the prefixes of the instruction.
+    ///
+    /// an instruction may contain multiple prefixes of the same group, in which case the last one wins, like it does on real
+    /// cpus for the segment override prefixes. repeating a prefix is harmless, so `66 66 90` is resolved as `66 90`.
     pub fn add(&mut self, prefix: LegacyPrefix) {
-        let group = prefix.group();
-        let prefix_entry = &mut self.by_group[group.index()];
-        assert!(
-            prefix_entry.is_none(),
-            "multiple legacy prefixes of the same group {:?} - {:?} and {:?}",
-            group,
-            prefix_entry.unwrap(),
-            prefix
-        );
-
-        *prefix_entry = Some(prefix);
+        self.by_group[prefix.group().index()] = Some(prefix);
     }
     pub fn contains(&self, prefix: LegacyPrefix) -> bool {
         self.by_group[prefix.group().index()] == Some(prefix)
     }
+    pub fn has_lock(&self) -> bool {
+        self.contains(LegacyPrefix::Lock)
+    }
+    /// does this instruction have a `rep` or `repz` prefix. these are both encoded using the same prefix byte.
+    pub fn has_rep(&self) -> bool {
+        self.contains(LegacyPrefix::RepOrRepz)
+    }
+    pub fn has_repnz(&self) -> bool {
+        self.contains(LegacyPrefix::Repnz)
+    }
+    pub fn has_operand_size_override(&self) -> bool {
+        self.contains(LegacyPrefix::OperandSizeOverride)
+    }
+    pub fn has_address_size_override(&self) -> bool {
+        self.contains(LegacyPrefix::AddressSizeOverride)
+    }
+    /// returns the segment selected by the segment override prefix of this instruction, if it has one.
+    ///
+    /// note that for branch instructions, the `cs` and `ds` segment override prefixes are used as branch hints.
+    pub fn segment_override(&self) -> Option<Segment> {
+        match self.by_group[LegacyPrefixGroup::Group2.index()]? {
+            LegacyPrefix::CsSegmentOverrideOrBranchNotTaken => Some(Segment::Cs),
+            LegacyPrefix::SsSegmentOverride => Some(Segment::Ss),
+            LegacyPrefix::DsSegmentOverrideOrBranchTaken => Some(Segment::Ds),
+            LegacyPrefix::EsSegmentOverride => Some(Segment::Es),
+            LegacyPrefix::FsSegmentOverride => Some(Segment::Fs),
+            LegacyPrefix::GsSegmentOverride => Some(Segment::Gs),
+            _ => None,
+        }
+    }
+}
+
+/// a segment register, in the order of its encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Segment {
+    Es,
+    Cs,
+    Ss,
+    Ds,
+    Fs,
+    Gs,
+}
+
+const GPR_NAMES: [[&str; 16]; 4] = [
+    [
+        "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+        "r13b", "r14b", "r15b",
+    ],
+    [
+        "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w",
+        "r13w", "r14w", "r15w",
+    ],
+    [
+        "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d",
+        "r12d", "r13d", "r14d", "r15d",
+    ],
+    [
+        "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ],
+];
+const FLAG_NAMES: [&str; 7] = ["cf", "pf", "af", "zf", "sf", "of", "df"];
+const SEGMENT_BASE_NAMES: [&str; 6] = [
+    "es_base", "cs_base", "ss_base", "ds_base", "fs_base", "gs_base",
+];
+
+/// the registers of x86, laid out as described in [`GPR_STEP`].
+pub const REG_FILE: RegFile = RegFile {
+    groups: &[
+        RegGroup {
+            names: &GPR_NAMES[0],
+            first_offset: 0,
+            step: GPR_STEP,
+            size: OperandSize::B1,
+        },
+        RegGroup {
+            names: &GPR_NAMES[1],
+            first_offset: 0,
+            step: GPR_STEP,
+            size: OperandSize::B2,
+        },
+        RegGroup {
+            names: &GPR_NAMES[2],
+            first_offset: 0,
+            step: GPR_STEP,
+            size: OperandSize::B4,
+        },
+        RegGroup {
+            names: &GPR_NAMES[3],
+            first_offset: 0,
+            step: GPR_STEP,
+            size: OperandSize::B8,
+        },
+        RegGroup {
+            names: &FLAG_NAMES,
+            first_offset: FLAGS_OFFSET,
+            step: 1,
+            size: OperandSize::B1,
+        },
+        RegGroup {
+            names: &SEGMENT_BASE_NAMES,
+            first_offset: SEGMENT_BASES_OFFSET,
+            step: GPR_STEP,
+            size: OperandSize::B8,
+        },
+    ],
+};
+
+/// the maximum length of an x86 instruction in bytes, including all of its prefixes. cpus raise a `#GP` exception when trying
+/// to execute longer instructions.
+pub const MAX_INSN_LEN: usize = 15;
+
+/// a cursor over the code of an instruction, which tracks the amount of bytes consumed from it.
+///
+/// all multi byte values are little endian. reading past the end of the code fails with [`TranslateError::UnexpectedEof`],
+/// and doesn't consume anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteReader<'a> {
+    code: &'a [u8],
+    position: usize,
+}
+impl<'a> ByteReader<'a> {
+    /// creates a reader positioned at the start of the given code.
+    pub fn new(code: &'a [u8]) -> Self {
+        Self { code, position: 0 }
+    }
+    /// returns the amount of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// returns the bytes which were not consumed yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.code[self.position..]
+    }
+    /// returns the next byte without consuming it, or `None` if the end of the code was reached.
+    pub fn peek_u8(&self) -> Option<u8> {
+        self.remaining().first().copied()
+    }
+    /// consumes the next `len` bytes and returns them.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], TranslateError> {
+        let bytes = self
+            .remaining()
+            .get(..len)
+            .ok_or(TranslateError::UnexpectedEof)?;
+        self.position += len;
+        Ok(bytes)
+    }
+    /// consumes the next `len` bytes without looking at them.
+    pub fn skip(&mut self, len: usize) -> Result<(), TranslateError> {
+        self.read_bytes(len).map(|_| ())
+    }
+    /// consumes the next `N` bytes and returns them as an array.
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], TranslateError> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+    /// consumes and returns the next byte.
+    pub fn read_u8(&mut self) -> Result<u8, TranslateError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+    /// consumes and returns the next 2 bytes as a little endian value.
+    pub fn read_u16(&mut self) -> Result<u16, TranslateError> {
+        self.read_array().map(u16::from_le_bytes)
+    }
+    /// consumes and returns the next 4 bytes as a little endian value.
+    pub fn read_u32(&mut self) -> Result<u32, TranslateError> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+    /// consumes and returns the next 8 bytes as a little endian value.
+    pub fn read_u64(&mut self) -> Result<u64, TranslateError> {
+        self.read_array().map(u64::from_le_bytes)
+    }
 }
 
-fn extract_legacy_prefixes(code: &mut &[u8]) -> InsnLegacyPrefixes {
+fn extract_legacy_prefixes(code: &mut ByteReader) -> InsnLegacyPrefixes {
     let mut prefixes = InsnLegacyPrefixes {
         by_group: [None; LegacyPrefixGroup::GROUPS_AMOUNT],
     };
-    while !code.is_empty() {
-        let Some(matching_prefix) = LegacyPrefix::iter().find(|prefix| code[0] == *prefix as u8)
+    // stop after the maximum instruction length, so that long runs of prefixes are not scanned needlessly. such an
+    // instruction is rejected anyway, since there is no room left for its opcode.
+    for _ in 0..MAX_INSN_LEN {
+        let Some(byte) = code.peek_u8() else {
+            break;
+        };
+        let Some(matching_prefix) = LegacyPrefix::iter().find(|prefix| byte == *prefix as u8)
         else {
             // non-prefix byte, so we are done parsing the prefixes
             break;
@@ -161,46 +543,351 @@ fn extract_legacy_prefixes(code: &mut &[u8]) -> InsnLegacyPrefixes {
         prefixes.add(matching_prefix);
 
         // advance by 1 byte
-        *code = &code[1..];
+        code.position += 1;
     }
 
     prefixes
 }
 
 #[bitpiece(4)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct RexPrefix {
-    pub w_bit: bool,
-    pub r_bit: bool,
-    pub x_bit: bool,
+    // bitpiece fields start from the least significant bit, so the fields are in reverse order of the REX byte `0100WRXB`.
     pub b_bit: bool,
+    pub x_bit: bool,
+    pub r_bit: bool,
+    pub w_bit: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub struct InsnPrefixes {
     pub legacy: InsnLegacyPrefixes,
     pub rex: Option<RexPrefix>,
 }
 
-fn extract_rex_prefix(code: &mut &[u8]) -> Option<RexPrefix> {
-    if code[0] & 0xf0 == 0b0100_0000 {
-        let rex_prefix = RexPrefix::from_bits(code[0] & 0xf);
+fn extract_rex_prefix(code: &mut ByteReader) -> Option<RexPrefix> {
+    let byte = code.peek_u8().filter(|byte| byte & 0xf0 == 0b0100_0000)?;
 
-        // skip the rex byte
-        *code = &code[1..];
+    // skip the rex byte
+    code.position += 1;
 
-        Some(rex_prefix)
-    } else {
-        None
-    }
+    Some(RexPrefix::from_bits(byte & 0xf))
 }
 
-fn extract_prefixes(code: &mut &[u8]) -> InsnPrefixes {
+/// extracts the prefixes of an instruction. the rex prefix only exists in long mode, since in the other modes its bytes are
+/// the opcodes of the single byte forms of `inc` and `dec`.
+fn extract_prefixes(code: &mut ByteReader, cpu_mode: &X86CpuMode) -> InsnPrefixes {
     let legacy = extract_legacy_prefixes(code);
-    let rex = extract_rex_prefix(code);
+    let rex = match cpu_mode {
+        X86CpuMode::LongMode => extract_rex_prefix(code),
+        X86CpuMode::RealMode | X86CpuMode::ProtectedMode => None,
+    };
     InsnPrefixes { legacy, rex }
 }
 
+#[bitpiece(8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModRm {
+    pub rm: B3,
+    pub reg: B3,
+    pub mod_bits: B2,
+}
+impl ModRm {
+    /// does the r/m field of this modrm byte encode a register operand rather than a memory operand.
+    pub fn is_rm_reg(&self) -> bool {
+        self.mod_bits().0 == 0b11
+    }
+    /// returns the 4 bit register index encoded by the reg field, extended using the `R` bit of the rex prefix.
+    pub fn effective_reg(&self, rex: Option<RexPrefix>) -> u8 {
+        self.reg().0 | ((rex.is_some_and(|rex| rex.r_bit()) as u8) << 3)
+    }
+    /// returns the 4 bit register index encoded by the r/m field, extended using the `B` bit of the rex prefix.
+    pub fn effective_rm(&self, rex: Option<RexPrefix>) -> u8 {
+        self.rm().0 | ((rex.is_some_and(|rex| rex.b_bit()) as u8) << 3)
+    }
+}
+
+fn extract_modrm(code: &mut ByteReader) -> Result<ModRm, TranslateError> {
+    Ok(ModRm::from_bits(code.read_u8()?))
+}
+
+#[bitpiece(8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sib {
+    pub base: B3,
+    pub index: B3,
+    pub scale: B2,
+}
+impl Sib {
+    /// returns the 4 bit register index encoded by the base field, extended using the `B` bit of the rex prefix.
+    pub fn effective_base(&self, rex: Option<RexPrefix>) -> u8 {
+        self.base().0 | ((rex.is_some_and(|rex| rex.b_bit()) as u8) << 3)
+    }
+    /// returns the 4 bit register index encoded by the index field, extended using the `X` bit of the rex prefix.
+    pub fn effective_index(&self, rex: Option<RexPrefix>) -> u8 {
+        self.index().0 | ((rex.is_some_and(|rex| rex.x_bit()) as u8) << 3)
+    }
+}
+
+fn extract_sib(code: &mut ByteReader) -> Result<Sib, TranslateError> {
+    Ok(Sib::from_bits(code.read_u8()?))
+}
+
+/// extracts a little endian value of `len` bytes and sign extends it to 64 bits.
+fn extract_sign_extended(code: &mut ByteReader, len: usize) -> Result<i64, TranslateError> {
+    Ok(match len {
+        0 => 0,
+        1 => code.read_u8()? as i8 as i64,
+        2 => code.read_u16()? as i16 as i64,
+        4 => code.read_u32()? as i32 as i64,
+        8 => code.read_u64()? as i64,
+        _ => unreachable!(),
+    })
+}
+
+/// extracts the displacement which follows the modrm and sib bytes, for 32 and 64 bit addressing, according to the mod
+/// field of the modrm byte. this doesn't handle the special base-less encodings, which always use a 32 bit displacement.
+fn extract_disp(code: &mut ByteReader, mod_bits: u8) -> Result<i64, TranslateError> {
+    match mod_bits {
+        0b01 => extract_sign_extended(code, 1),
+        0b10 => extract_sign_extended(code, 4),
+        _ => Ok(0),
+    }
+}
+
+/// extracts an immediate of the given operand size. immediates are at most 32 bits, and for 64 bit operands they are sign
+/// extended from 32 bits. the returned value is truncated to the operand size.
+fn extract_imm(code: &mut ByteReader, size: OperandSize) -> Result<u64, TranslateError> {
+    let len = size.bytes().min(4);
+    Ok(extract_sign_extended(code, len)? as u64 & size.mask())
+}
+
+/// the operand encoded by the r/m field of a modrm byte.
+enum ModRmRmOperand {
+    /// a general purpose register.
+    Reg(Operand),
+    /// a memory operand. the contained operand is a tmp operand which holds the address of the accessed memory. the
+    /// address is only known at runtime, so the memory itself should be accessed using `Load` and `Store`.
+    Mem(Operand),
+}
+
+/// marks the memory accesses of a `lock` prefixed instruction as atomic. the `lock` prefix is only valid on read-modify-write
+/// instructions whose destination is a memory operand, which are the instructions that store to memory, and cpus raise a
+/// `#UD` exception otherwise.
+fn mark_locked_memory_accesses(
+    mnemonic: InsnMnemonic,
+    translation: &mut Translation,
+) -> Result<(), TranslateError> {
+    let is_lockable = matches!(
+        mnemonic,
+        InsnMnemonic::Add
+            | InsnMnemonic::Or
+            | InsnMnemonic::Adc
+            | InsnMnemonic::Sbb
+            | InsnMnemonic::And
+            | InsnMnemonic::Sub
+            | InsnMnemonic::Xor
+            | InsnMnemonic::Xchg
+            | InsnMnemonic::Xadd
+            | InsnMnemonic::Inc
+            | InsnMnemonic::Dec
+            | InsnMnemonic::Not
+            | InsnMnemonic::Neg
+    );
+    let has_memory_dst = translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Store);
+    if !is_lockable || !has_memory_dst {
+        return Err(TranslateError::InvalidLock);
+    }
+    for insn in &mut translation.insns {
+        if matches!(insn.opcode, Opcode::Load | Opcode::Store) {
+            insn.is_atomic = true;
+        }
+    }
+    Ok(())
+}
+
+/// returns the operand of the general purpose register with the given 4 bit index.
+fn gpr_operand(index: u8, size: OperandSize) -> Operand {
+    Reg::from_bits(index).operand(size)
+}
+
+/// returns the operand of the byte sized general purpose register with the given 4 bit index.
+///
+/// without a rex prefix, indices 4 to 7 refer to the legacy high byte registers (`ah`, `ch`, `dh`, `bh`), which are the
+/// second byte of the first 4 registers. with a rex prefix, they refer to `spl`, `bpl`, `sil` and `dil`.
+fn byte_gpr_operand(index: u8, has_rex_prefix: bool) -> Operand {
+    let operand = if (4..8).contains(&index) && !has_rex_prefix {
+        Reg::from_bits(index - 4).high_byte_operand(has_rex_prefix)
+    } else {
+        Reg::from_bits(index).checked_operand(OperandSize::B1, has_rex_prefix)
+    };
+    // the extension bits of the index are only decoded from a rex prefix, so every decoded index is valid.
+    operand.expect("decoded byte register index requires a rex prefix")
+}
+
+/// skips the modrm byte of an instruction, along with the sib byte and displacement that follow it, without decoding the
+/// operand that they encode. this is used for instructions whose operand has no effect, but which must still be decoded to
+/// find the instruction's length.
+fn skip_modrm(code: &mut ByteReader, address_size: OperandSize) -> Result<(), TranslateError> {
+    let modrm = extract_modrm(code)?;
+    let mod_bits = modrm.mod_bits().0;
+    let rm = modrm.rm().0;
+
+    let disp_len = if address_size == OperandSize::B2 {
+        // 16 bit addressing has no sib byte, and uses 16 bit displacements.
+        match (mod_bits, rm) {
+            (0b00, 0b110) => 2,
+            (0b01, _) => 1,
+            (0b10, _) => 2,
+            _ => 0,
+        }
+    } else {
+        let mut sib_base = None;
+        if mod_bits != 0b11 && rm == 0b100 {
+            sib_base = Some(code.read_u8()? & 0b111);
+        }
+        match mod_bits {
+            0b00 if rm == 0b101 || sib_base == Some(0b101) => 4,
+            0b01 => 1,
+            0b10 => 4,
+            _ => 0,
+        }
+    };
+    code.skip(disp_len)
+}
+
+/// decodes the memory operand encoded by the given modrm byte and the sib byte and displacement that follow it, and emits
+/// instructions which compute its address into the memory address tmp. returns the tmp operand holding the address.
+///
+/// `imm_len` is the length of the immediate which follows the displacement, which is needed for resolving rip relative
+/// addresses, since they are relative to the address of the next instruction.
+fn translate_modrm_mem_address(
+    code: &mut ByteReader,
+    modrm: ModRm,
+    imm_len: usize,
+    ctx: &mut PostPrefixesCtx,
+    cpu_mode: &X86CpuMode,
+    translation: &mut Translation,
+) -> Result<Operand, TranslateError> {
+    let address_size = ctx.address_size;
+    let rex = ctx.prefixes.rex;
+    let mod_bits = modrm.mod_bits().0;
+    let rm = modrm.rm().0;
+    debug_assert!(mod_bits != 0b11);
+
+    let mut is_rip_relative = false;
+
+    // the base register, the index register along with its scale, and the displacement.
+    let (base, index, disp): (Option<u8>, Option<(u8, u8)>, i64) =
+        if address_size == OperandSize::B2 {
+            // 16 bit addressing has no sib byte, and uses a fixed table of base and index registers.
+            let (bx, bp, si, di) = (
+                Reg::Rbx as u8,
+                Reg::RbpCh as u8,
+                Reg::RsiDh as u8,
+                Reg::RdiBh as u8,
+            );
+            let (base, index) = match rm {
+                0b000 => (Some(bx), Some(si)),
+                0b001 => (Some(bx), Some(di)),
+                0b010 => (Some(bp), Some(si)),
+                0b011 => (Some(bp), Some(di)),
+                0b100 => (Some(si), None),
+                0b101 => (Some(di), None),
+                0b110 if mod_bits == 0b00 => (None, None),
+                0b110 => (Some(bp), None),
+                _ => (Some(bx), None),
+            };
+            let disp_len = match (mod_bits, base) {
+                (0b00, None) => 2,
+                (0b01, _) => 1,
+                (0b10, _) => 2,
+                _ => 0,
+            };
+            (
+                base,
+                index.map(|index| (index, 0)),
+                extract_sign_extended(code, disp_len)?,
+            )
+        } else {
+            let (base, index) = if rm == 0b100 {
+                let sib = extract_sib(code)?;
+                let base = if mod_bits == 0b00 && sib.base().0 == 0b101 {
+                    // no base register, a 32 bit displacement is used instead.
+                    None
+                } else {
+                    Some(sib.effective_base(rex))
+                };
+                // an index of `rsp` means no index. note that `r12` can be used as an index.
+                let index = sib.effective_index(rex);
+                let index = (index != Reg::RspAh as u8).then_some((index, sib.scale().0));
+                (base, index)
+            } else if mod_bits == 0b00 && rm == 0b101 {
+                // in long mode, this encodes a 32 bit displacement relative to the address of the next instruction. otherwise,
+                // there is no base register, and the 32 bit displacement is used as an absolute address.
+                is_rip_relative = matches!(cpu_mode, X86CpuMode::LongMode);
+                (None, None)
+            } else {
+                (Some(modrm.effective_rm(rex)), None)
+            };
+            let disp = if mod_bits == 0b00 && base.is_none() {
+                extract_sign_extended(code, 4)?
+            } else {
+                extract_disp(code, mod_bits)?
+            };
+            (base, index, disp)
+        };
+
+    let addr = ctx.tmps.alloc(address_size);
+    if is_rip_relative {
+        let next_insn_addr = ctx.addr_of(code).wrapping_add(imm_len as u64);
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            addr.clone(),
+            Operand::constant(next_insn_addr.wrapping_add(disp as u64), address_size),
+        ));
+        return Ok(addr);
+    }
+    translation.insns.push(Insn::new(
+        Opcode::Move,
+        addr.clone(),
+        match base {
+            Some(base) => gpr_operand(base, address_size),
+            None => Operand::zero(address_size),
+        },
+    ));
+    if let Some((index, scale)) = index {
+        // scale the index by repeatedly doubling it in a separate tmp.
+        let scaled_index = ctx.tmps.alloc(address_size);
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            scaled_index.clone(),
+            gpr_operand(index, address_size),
+        ));
+        for _ in 0..scale {
+            translation.insns.push(Insn::new(
+                Opcode::Add,
+                scaled_index.clone(),
+                scaled_index.clone(),
+            ));
+        }
+        translation
+            .insns
+            .push(Insn::new(Opcode::Add, addr.clone(), scaled_index));
+    }
+    if disp != 0 {
+        translation.insns.push(Insn::new(
+            Opcode::Add,
+            addr.clone(),
+            Operand::constant(disp as u64, address_size),
+        ));
+    }
+    Ok(addr)
+}
+
 pub enum X86CpuMode {
     RealMode,
     ProtectedMode,
@@ -219,13 +906,357 @@ struct PostPrefixesCtx {
     operand_size: OperandSize,
     address_size: OperandSize,
     prefixes: InsnPrefixes,
+    /// the address of the first byte of the instruction, including its prefixes.
+    insn_addr: u64,
+    /// the allocator of the tmps used while translating the instruction.
+    tmps: TmpAllocator,
+}
+impl PostPrefixesCtx {
+    /// returns the address of the next byte of the instruction to be read by the given reader. once the whole instruction
+    /// was decoded, this is the address of the next instruction.
+    fn addr_of(&self, code: &ByteReader) -> u64 {
+        self.insn_addr.wrapping_add(code.position() as u64)
+    }
+    /// returns the register encoded by the low 3 bits of an opcode, extended using the `B` bit of the rex prefix.
+    fn opcode_reg(&self, opcode_low_bits: u8) -> Reg {
+        Reg::from_bits(
+            opcode_low_bits | ((self.prefixes.rex.is_some_and(|rex| rex.b_bit()) as u8) << 3),
+        )
+    }
+    /// returns the general purpose register operand with the given 4 bit index and size. byte sized operands take the legacy
+    /// high byte registers into account.
+    fn gpr_operand(&self, index: u8, size: OperandSize) -> Operand {
+        if size == OperandSize::B1 {
+            byte_gpr_operand(index, self.prefixes.rex.is_some())
+        } else {
+            gpr_operand(index, size)
+        }
+    }
+    /// returns the operand of the given register which is implicitly accessed by the instruction, with the given size.
+    fn implicit_gpr_operand(&self, reg: Reg, size: OperandSize) -> Result<Operand, TranslateError> {
+        reg.checked_operand(size, self.prefixes.rex.is_some())
+    }
+    /// returns the register operand encoded by the reg field of the given modrm byte, with the given size.
+    fn modrm_reg_operand(&self, modrm: ModRm, size: OperandSize) -> Operand {
+        self.gpr_operand(modrm.effective_reg(self.prefixes.rex), size)
+    }
+    /// returns the register operand encoded by the r/m field of the given modrm byte, with the given size. the modrm byte
+    /// must encode a register operand rather than a memory operand.
+    fn modrm_rm_reg_operand(&self, modrm: ModRm, size: OperandSize) -> Operand {
+        debug_assert!(modrm.is_rm_reg());
+        self.gpr_operand(modrm.effective_rm(self.prefixes.rex), size)
+    }
+    /// decodes the operand encoded by the r/m field of the given modrm byte, using the given size for register operands.
+    /// for memory operands, the instructions which compute the address are emitted into the given translation.
+    ///
+    /// `imm_len` is the length of the immediate which follows the r/m operand, if the instruction has one.
+    fn modrm_rm_operand(
+        &mut self,
+        code: &mut ByteReader,
+        modrm: ModRm,
+        size: OperandSize,
+        imm_len: usize,
+        cpu_mode: &X86CpuMode,
+        translation: &mut Translation,
+    ) -> Result<ModRmRmOperand, TranslateError> {
+        if modrm.is_rm_reg() {
+            Ok(ModRmRmOperand::Reg(self.modrm_rm_reg_operand(modrm, size)))
+        } else {
+            let addr =
+                translate_modrm_mem_address(code, modrm, imm_len, self, cpu_mode, translation)?;
+            Ok(ModRmRmOperand::Mem(self.linear_address(
+                addr,
+                cpu_mode,
+                translation,
+            )))
+        }
+    }
+    /// converts the given effective address into a linear address, by adding the base of the segment selected by the
+    /// instruction's segment override prefix, and emits the required instructions into the given translation. returns the
+    /// operand holding the linear address.
+    ///
+    /// in long mode, only the `fs` and `gs` segments have a base, and the cpu treats the bases of all other segments as 0.
+    /// accesses which use the default segment of the instruction, without an override, are assumed to use a flat segment
+    /// with a base of 0.
+    fn linear_address(
+        &mut self,
+        addr: Operand,
+        cpu_mode: &X86CpuMode,
+        translation: &mut Translation,
+    ) -> Operand {
+        let segment = match (self.prefixes.legacy.segment_override(), cpu_mode) {
+            (Some(segment @ (Segment::Fs | Segment::Gs)), _) => segment,
+            (Some(segment), X86CpuMode::RealMode | X86CpuMode::ProtectedMode) => segment,
+            (Some(_), X86CpuMode::LongMode) | (None, _) => return addr,
+        };
+        // segment bases are 64 bits wide in long mode and 32 bits wide otherwise, and the linear address has the same size
+        // even when the effective address is smaller.
+        let linear_size = match cpu_mode {
+            X86CpuMode::LongMode => OperandSize::B8,
+            X86CpuMode::RealMode | X86CpuMode::ProtectedMode => OperandSize::B4,
+        };
+        let linear_addr = if addr.size == linear_size && addr.addr.space == OperandSpace::Tmp {
+            // the address was computed into a tmp of its own, so the base can be added to it in place.
+            addr
+        } else {
+            // copy the effective address to a tmp, since it may be a register which must not be modified, like the index
+            // register of a string instruction, and zero extend it to the size of the linear address.
+            let linear_addr = self.tmps.alloc(linear_size);
+            translation
+                .insns
+                .push(Insn::new(Opcode::ZeroExtend, linear_addr.clone(), addr));
+            linear_addr
+        };
+        translation.insns.push(Insn::new(
+            Opcode::Add,
+            linear_addr.clone(),
+            Operand {
+                size: linear_size,
+                ..segment_base(segment)
+            },
+        ));
+        linear_addr
+    }
+}
+
+/// the mnemonic of a decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum InsnMnemonic {
+    Push,
+    Pop,
+    Mov,
+    Movzx,
+    Movsx,
+    Cmovcc,
+    Setcc,
+    Lea,
+    Xchg,
+    Xadd,
+    Test,
+    Inc,
+    Dec,
+    Not,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Rol,
+    Ror,
+    Shl,
+    Shr,
+    Sar,
+    Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Nop,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Xlat,
+    Movs,
+    Stos,
+    Lods,
+    Scas,
+    Jcc,
+    Jmp,
+    Call,
+    Ret,
+    Syscall,
+    Sysret,
+    Sysenter,
+    Sysexit,
+    Pause,
+    Endbr64,
+    Endbr32,
+    Prefetchnta,
+    Prefetcht0,
+    Prefetcht1,
+    Prefetcht2,
+}
+
+impl InsnMnemonic {
+    /// returns the textual form of this mnemonic, as used in disassembly, for example `push`.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
+
+/// a decoded instruction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DecodedInsn {
+    /// the length of the instruction in bytes, including its prefixes.
+    pub length: usize,
+    pub mnemonic: InsnMnemonic,
+    pub prefixes: InsnPrefixes,
+    /// the ir translation of the instruction.
+    pub translation: Translation,
+}
+
+/// the handler of an opcode in the one byte opcode map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum OneByteOpcodeHandler {
+    Unknown,
+    Alu,
+    IncDecReg,
+    PushReg,
+    PopReg,
+    PushImm,
+    PushImm8,
+    ImulImm,
+    ImulImm8,
+    Group1,
+    TestRmReg,
+    XchgRmReg,
+    MovRmReg,
+    Lea,
+    PopRm,
+    /// `nop`, `pause`, or an `xchg` of the accumulator with `r8`, depending on the prefixes.
+    Nop,
+    XchgAccumulator,
+    StringOp,
+    MovReg8Imm,
+    MovRegImm,
+    Group2,
+    Ret,
+    MovRmImm,
+    Xlat,
+    CallRel,
+    JmpRel,
+    JmpRel8,
+    JccRel8,
+    Cmc,
+    Group3,
+    Clc,
+    Stc,
+    Cld,
+    Std,
+    Group4,
+    Group5,
+    TwoByteOpcode,
+}
+impl OneByteOpcodeHandler {
+    const fn of_opcode(opcode: u8) -> Self {
+        match opcode {
+            0x00..=0x3d if opcode & 0b111 <= 0b101 => Self::Alu,
+            0x0f => Self::TwoByteOpcode,
+            0x40..=0x4f => Self::IncDecReg,
+            0x50..=0x57 => Self::PushReg,
+            0x58..=0x5f => Self::PopReg,
+            0x68 => Self::PushImm,
+            0x69 => Self::ImulImm,
+            0x6a => Self::PushImm8,
+            0x6b => Self::ImulImm8,
+            0x70..=0x7f => Self::JccRel8,
+            0x80..=0x83 => Self::Group1,
+            0x84 | 0x85 => Self::TestRmReg,
+            0x86 | 0x87 => Self::XchgRmReg,
+            0x88..=0x8b => Self::MovRmReg,
+            0x8d => Self::Lea,
+            0x8f => Self::PopRm,
+            0x90 => Self::Nop,
+            0x91..=0x97 => Self::XchgAccumulator,
+            0xa4 | 0xa5 | 0xaa..=0xaf => Self::StringOp,
+            0xb0..=0xb7 => Self::MovReg8Imm,
+            0xb8..=0xbf => Self::MovRegImm,
+            0xc0 | 0xc1 | 0xd0..=0xd3 => Self::Group2,
+            0xc3 => Self::Ret,
+            0xc6 | 0xc7 => Self::MovRmImm,
+            0xd7 => Self::Xlat,
+            0xe8 => Self::CallRel,
+            0xe9 => Self::JmpRel,
+            0xeb => Self::JmpRel8,
+            0xf5 => Self::Cmc,
+            0xf6 | 0xf7 => Self::Group3,
+            0xf8 => Self::Clc,
+            0xf9 => Self::Stc,
+            0xfc => Self::Cld,
+            0xfd => Self::Std,
+            0xfe => Self::Group4,
+            0xff => Self::Group5,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// the handler of an opcode in the two byte opcode map, which is the opcode byte following a `0x0f` escape byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum TwoByteOpcodeHandler {
+    Unknown,
+    Syscall,
+    Sysret,
+    /// prefetch hints, reserved hint nops, the multi byte nop and the cet branch target markers.
+    HintNop,
+    MovSpecialReg,
+    Sysenter,
+    Sysexit,
+    Cmov,
+    Jcc,
+    Setcc,
+    Imul,
+    Movzx,
+    Movsx,
+    Xadd,
+}
+impl TwoByteOpcodeHandler {
+    const fn of_opcode(opcode: u8) -> Self {
+        match opcode {
+            0x05 => Self::Syscall,
+            0x07 => Self::Sysret,
+            0x18..=0x1f => Self::HintNop,
+            0x20..=0x23 => Self::MovSpecialReg,
+            0x34 => Self::Sysenter,
+            0x35 => Self::Sysexit,
+            0x40..=0x4f => Self::Cmov,
+            0x80..=0x8f => Self::Jcc,
+            0x90..=0x9f => Self::Setcc,
+            0xaf => Self::Imul,
+            0xb6 | 0xb7 => Self::Movzx,
+            0xbe | 0xbf => Self::Movsx,
+            0xc0 | 0xc1 => Self::Xadd,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// builds a table which maps each of the 256 values of an opcode byte to its handler, so that dispatching an opcode is a
+/// single lookup.
+macro_rules! opcode_handler_table {
+    ($handler:ty) => {{
+        let mut table = [<$handler>::Unknown; 256];
+        let mut opcode = 0;
+        while opcode < table.len() {
+            table[opcode] = <$handler>::of_opcode(opcode as u8);
+            opcode += 1;
+        }
+        table
+    }};
 }
 
+/// the handlers of the one byte opcode map. each handler is a single byte, so the whole table spans only a few cache lines.
+static ONE_BYTE_OPCODE_HANDLERS: [OneByteOpcodeHandler; 256] =
+    opcode_handler_table!(OneByteOpcodeHandler);
+/// the handlers of the two byte opcode map.
+static TWO_BYTE_OPCODE_HANDLERS: [TwoByteOpcodeHandler; 256] =
+    opcode_handler_table!(TwoByteOpcodeHandler);
+
 pub struct X86Ctx {
     /// the cpu mode in which we are executing.
     pub cpu_mode: X86CpuMode,
     /// the code segment's default operand size, determined by the `D` flag in the code segment descriptor.
     pub code_segment_default_operand_size: X86SegmentDefaultOperandSize,
+    /// should `nop` instructions be translated to an [`Opcode::Nop`] instruction instead of to an empty translation.
+    pub preserve_nops: bool,
 }
 impl X86Ctx {
     fn stack_width(&self) -> OperandSize {
@@ -235,69 +1266,1879 @@ impl X86Ctx {
             X86CpuMode::LongMode => OperandSize::B8,
         }
     }
+    /// returns the size of the values pushed and popped by the stack instructions which operate on values of the
+    /// instruction's operand size. in long mode, these instructions can't operate on 32 bit values, and default to 64 bits.
+    fn stack_operand_size(&self, ctx: &PostPrefixesCtx) -> OperandSize {
+        match self.cpu_mode {
+            X86CpuMode::LongMode if ctx.operand_size != OperandSize::B2 => OperandSize::B8,
+            _ => ctx.operand_size,
+        }
+    }
+    /// returns the stack pointer register operand with the given size, for example `esp` for a 4 byte size.
     fn stack_pointer_operand_of_size(&self, size: OperandSize) -> Operand {
-        match size {
-            OperandSize::B1 => todo!(),
-            OperandSize::B2 => todo!(),
-            OperandSize::B4 => todo!(),
-            OperandSize::B8 => todo!(),
+        Reg::RspAh.operand(size)
+    }
+    /// emits instructions which push the given value onto the stack. the stack pointer is decremented by the size of the
+    /// value.
+    fn push(&self, value: Operand, builder: &mut TranslationBuilder) {
+        let stack_width = self.stack_width();
+        let sp = self.stack_pointer_operand_of_size(stack_width);
+        // when pushing the stack pointer, the pushed value is the stack pointer before it was decremented.
+        let value = if value.addr == sp.addr {
+            let old_sp = builder.alloc_tmp(value.size);
+            builder.mov(old_sp.clone(), value);
+            old_sp
+        } else {
+            value
+        };
+        builder.sub(
+            sp.clone(),
+            Operand::constant(value.size.bytes() as u64, stack_width),
+        );
+        builder.store(sp, value);
+    }
+    /// emits instructions which pop a value from the stack into the given operand. the stack pointer is incremented by the
+    /// size of the operand.
+    fn pop(&self, dst: Operand, builder: &mut TranslationBuilder) {
+        let stack_width = self.stack_width();
+        let sp = self.stack_pointer_operand_of_size(stack_width);
+        builder.load(dst.clone(), sp.clone());
+        // when popping into the stack pointer, the popped value overrides the incremented stack pointer.
+        if dst.addr != sp.addr {
+            builder.add(sp, Operand::constant(dst.size.bytes() as u64, stack_width));
         }
     }
     fn translate_push_reg(&self, reg: Reg, ctx: PostPrefixesCtx) -> Translation {
+        let size = self.stack_operand_size(&ctx);
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        self.push(reg.operand(size), &mut builder);
+        builder.build()
+    }
+    /// translates a `push` of an immediate of the given length, which is sign extended to the instruction's operand size.
+    fn translate_push_imm(
+        &self,
+        code: &mut ByteReader,
+        imm_len: usize,
+        ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = self.stack_operand_size(&ctx);
+        let imm = extract_sign_extended(code, imm_len)? as u64;
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        self.push(Operand::constant(imm, size), &mut builder);
+        Ok(builder.build())
+    }
+    /// translates a `pop` into an r/m operand. the address of a memory operand is calculated after incrementing the stack
+    /// pointer, like real cpus do.
+    fn translate_pop_rm(
+        &self,
+        code: &mut ByteReader,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let modrm = extract_modrm(code)?;
+        if modrm.reg().0 != 0 {
+            return Err(TranslateError::UnknownOpcode(0x8f));
+        }
+        let size = self.stack_operand_size(&ctx);
+        if modrm.is_rm_reg() {
+            let dst = ctx.modrm_rm_reg_operand(modrm, size);
+            let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+            self.pop(dst, &mut builder);
+            return Ok(builder.build());
+        }
+        // the address is decoded before building the translation so that its tmps are allocated by the same allocator, but
+        // it is only calculated after the pop.
+        let mut addr_calculation = Translation::new();
+        let ModRmRmOperand::Mem(addr) =
+            ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut addr_calculation)?
+        else {
+            unreachable!()
+        };
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        let value = builder.alloc_tmp(size);
+        self.pop(value.clone(), &mut builder);
+        builder.insns.extend(addr_calculation.insns);
+        builder.store(addr, value);
+        Ok(builder.build())
+    }
+    fn translate_pop_reg(&self, reg: Reg, ctx: PostPrefixesCtx) -> Translation {
+        let size = self.stack_operand_size(&ctx);
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        self.pop(reg.operand(size), &mut builder);
+        builder.build()
+    }
+    /// translates a `call` of a relative target, which pushes the address of the next instruction as the return address.
+    fn translate_call_rel(
+        &self,
+        code: &mut ByteReader,
+        ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let target = self.extract_rel_target(code, self.near_rel_len(&ctx), &ctx)?;
+        let return_addr = Operand::constant(ctx.addr_of(code), self.stack_width());
+
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        self.push(return_addr, &mut builder);
+        builder.insn(Opcode::Call, target, Operand::zero(OperandSize::B1));
+        Ok(builder.build())
+    }
+    /// translates a `ret`, which pops the return address from the stack and returns to it.
+    fn translate_ret(&self, ctx: PostPrefixesCtx) -> Translation {
+        let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+        let return_addr = builder.alloc_tmp(self.stack_width());
+        self.pop(return_addr.clone(), &mut builder);
+        builder.insn(Opcode::Return, return_addr, Operand::zero(OperandSize::B1));
+        builder.build()
+    }
+    /// emits the implicit zero extension which happens when writing to the given general purpose register operand. in long
+    /// mode, writing to a 32 bit register zeroes the upper 32 bits of the full register.
+    fn zero_extend_gpr_write(&self, dst: &Operand, translation: &mut Translation) {
+        if matches!(self.cpu_mode, X86CpuMode::LongMode) && dst.size == OperandSize::B4 {
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                Operand {
+                    addr: OperandAddr {
+                        offset: dst.addr.offset + 4,
+                        ..dst.addr
+                    },
+                    size: OperandSize::B4,
+                },
+                Operand::zero(OperandSize::B4),
+            ));
+        }
+    }
+    /// translates a `mov` of an immediate to a register encoded in the opcode, with the given size. unlike other
+    /// instructions, the 64 bit form (`movabs`) has a full 64 bit immediate.
+    fn translate_mov_reg_imm(
+        &self,
+        code: &mut ByteReader,
+        reg: Reg,
+        size: OperandSize,
+        ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let imm = if size == OperandSize::B8 {
+            extract_sign_extended(code, 8)? as u64
+        } else {
+            extract_imm(code, size)?
+        };
+
         let mut translation = Translation::new();
+        let dst = ctx.gpr_operand(reg as u8, size);
         translation.insns.push(Insn::new(
-            Opcode::Add,
-            RSP,
-            Operand::negative_constant(8, OperandSize::B8),
+            Opcode::Move,
+            dst.clone(),
+            Operand::constant(imm, size),
         ));
+        self.zero_extend_gpr_write(&dst, &mut translation);
+        Ok(translation)
+    }
+    /// translates a `mov` of an immediate to an r/m operand. `opcode` is the instruction's opcode, whose low bit selects
+    /// between byte sized operands and operands of the instruction's operand size.
+    fn translate_mov_rm_imm(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        if modrm.reg().0 != 0 {
+            return Err(TranslateError::UnknownOpcode(opcode));
+        }
+        let imm_len = size.bytes().min(4);
+
+        let mut translation = Translation::new();
+        let rm =
+            ctx.modrm_rm_operand(code, modrm, size, imm_len, &self.cpu_mode, &mut translation)?;
+        let imm = Operand::constant(extract_imm(code, size)?, size);
+        match rm {
+            ModRmRmOperand::Reg(dst) => {
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, dst.clone(), imm));
+                self.zero_extend_gpr_write(&dst, &mut translation);
+            }
+            ModRmRmOperand::Mem(addr) => {
+                translation.insns.push(Insn::new(Opcode::Store, addr, imm));
+            }
+        }
+        Ok(translation)
+    }
+    /// returns the mnemonic and the ir opcode of the alu operation with the given 3 bit index, which is shared by the classic
+    /// alu opcode block and by the group 1 opcodes. `opcode` is the instruction's opcode, which is used for reporting
+    /// errors.
+    fn alu_operation(
+        &self,
+        index: u8,
+        opcode: u8,
+    ) -> Result<(InsnMnemonic, Opcode), TranslateError> {
+        Ok(match index {
+            0 => (InsnMnemonic::Add, Opcode::Add),
+            1 => (InsnMnemonic::Or, Opcode::Or),
+            4 => (InsnMnemonic::And, Opcode::And),
+            5 => (InsnMnemonic::Sub, Opcode::Sub),
+            6 => (InsnMnemonic::Xor, Opcode::Xor),
+            7 => (InsnMnemonic::Cmp, Opcode::Sub),
+            2 => (InsnMnemonic::Adc, Opcode::Add),
+            3 => (InsnMnemonic::Sbb, Opcode::Sub),
+            _ => return Err(TranslateError::UnknownOpcode(opcode)),
+        })
+    }
+    /// emits the instructions of an alu operation with the given mnemonic and ir opcode, which writes its result to `dst`,
+    /// and updates the flags according to the result. returns whether `dst` was written, which is not the case for `cmp`,
+    /// which only updates the flags.
+    ///
+    /// `adc` and `sbb` also add or subtract the carry flag. the auxiliary carry flag is not lifted.
+    fn emit_alu(
+        &self,
+        mnemonic: InsnMnemonic,
+        alu_opcode: Opcode,
+        dst: Operand,
+        src: Operand,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) -> bool {
+        if matches!(alu_opcode, Opcode::And | Opcode::Or | Opcode::Xor) {
+            translation
+                .insns
+                .push(Insn::new(alu_opcode, dst.clone(), src));
+            self.emit_logic_flags(&dst, translation);
+            return true;
+        }
+
+        // the result is calculated into a tmp, since the flags are calculated from the inputs, and the destination may also
+        // be the source.
+        let result = ctx.tmps.alloc(dst.size);
         translation
             .insns
-            .push(Insn::new(Opcode::Store, RSP, reg.operand(OperandSize::B8)));
+            .push(Insn::new(Opcode::Move, result.clone(), dst.clone()));
         translation
+            .insns
+            .push(Insn::new(alu_opcode, result.clone(), src.clone()));
+        if matches!(mnemonic, InsnMnemonic::Adc | InsnMnemonic::Sbb) {
+            let carry_in = ctx.tmps.alloc(dst.size);
+            translation
+                .insns
+                .push(Insn::new(Opcode::ZeroExtend, carry_in.clone(), FLAG_CF));
+            translation
+                .insns
+                .push(Insn::new(alu_opcode, result.clone(), carry_in));
+        }
+        self.emit_add_sub_flags(alu_opcode, &dst, &src, &result, true, ctx, translation);
+
+        if mnemonic == InsnMnemonic::Cmp {
+            return false;
+        }
+        translation.insns.push(Insn::new(Opcode::Move, dst, result));
+        true
     }
+    /// emits instructions which set the flags according to an addition or a subtraction, selected by the given ir opcode,
+    /// of `b` to or from `a`, which produced `result`. the carry into the operation, if any, is taken into account through
+    /// the result. the carry flag is only updated if `update_carry` is set, since `inc` and `dec` preserve it.
+    #[allow(clippy::too_many_arguments)]
+    fn emit_add_sub_flags(
+        &self,
+        alu_opcode: Opcode,
+        a: &Operand,
+        b: &Operand,
+        result: &Operand,
+        update_carry: bool,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) {
+        let size = result.size;
+        let mut emit = |opcode, first: &Operand, second: &Operand| {
+            translation
+                .insns
+                .push(Insn::new(opcode, first.clone(), second.clone()))
+        };
 
-    fn resolve_operand_size(&self, prefixes: &InsnPrefixes) -> OperandSize {
-        match self.cpu_mode {
-            X86CpuMode::RealMode => {
-                if prefixes.legacy.contains(LegacyPrefix::OperandSizeOverride) {
-                    OperandSize::B4
-                } else {
-                    OperandSize::B2
-                }
+        if update_carry {
+            // the carry out of the most significant bit is `(a & b) | ((a | b) & !result)` for an addition, and the borrow
+            // out of it is `(!a & b) | ((!a | b) & result)` for a subtraction, which are both `(x & b) | ((x | b) & y)`.
+            let [x, y, carry, tmp] = [(); 4].map(|_| ctx.tmps.alloc(size));
+            emit(Opcode::Move, &x, a);
+            emit(Opcode::Move, &y, result);
+            if alu_opcode == Opcode::Sub {
+                emit(Opcode::Not, &x, &Operand::zero(size));
+            } else {
+                emit(Opcode::Not, &y, &Operand::zero(size));
             }
-            X86CpuMode::ProtectedMode => match self.code_segment_default_operand_size {
-                X86SegmentDefaultOperandSize::B16 => {
-                    if prefixes.legacy.contains(LegacyPrefix::OperandSizeOverride) {
-                        OperandSize::B4
-                    } else {
-                        OperandSize::B2
-                    }
+            emit(Opcode::Move, &carry, &x);
+            emit(Opcode::And, &carry, b);
+            emit(Opcode::Move, &tmp, &x);
+            emit(Opcode::Or, &tmp, b);
+            emit(Opcode::And, &tmp, &y);
+            emit(Opcode::Or, &carry, &tmp);
+            emit(Opcode::SetCond(Cond::Negative), &FLAG_CF, &carry);
+        }
+
+        // an addition overflows when both inputs have a different sign than the result, which is `(a ^ result) & (b ^
+        // result)`, and a subtraction overflows when the inputs have different signs and the result has a different sign
+        // than `a`, which is `(a ^ b) & (a ^ result)`.
+        let [overflow, tmp] = [(); 2].map(|_| ctx.tmps.alloc(size));
+        emit(Opcode::Move, &overflow, a);
+        emit(Opcode::Xor, &overflow, result);
+        emit(Opcode::Move, &tmp, b);
+        if alu_opcode == Opcode::Sub {
+            emit(Opcode::Xor, &tmp, a);
+        } else {
+            emit(Opcode::Xor, &tmp, result);
+        }
+        emit(Opcode::And, &overflow, &tmp);
+        emit(Opcode::SetCond(Cond::Negative), &FLAG_OF, &overflow);
+
+        self.emit_result_flags(result, translation);
+    }
+    /// translates an instruction of group 1 (`0x80` to `0x83`), which performs the alu operation selected by the reg field
+    /// of the modrm byte on an r/m operand and an immediate. `0x80` has byte sized operands, `0x81` has an immediate of the
+    /// instruction's operand size, and `0x83` has a byte immediate which is sign extended to the operand size. `0x82` is an
+    /// alias of `0x80` which is invalid in long mode.
+    ///
+    /// the flags are updated as described in `emit_alu`.
+    fn translate_group1(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        if opcode == 0x82 && matches!(self.cpu_mode, X86CpuMode::LongMode) {
+            return Err(TranslateError::UnsupportedInMode);
+        }
+        let size = if matches!(opcode, 0x80 | 0x82) {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let imm_len = if opcode == 0x81 {
+            size.bytes().min(4)
+        } else {
+            1
+        };
+        let modrm = extract_modrm(code)?;
+        let (mnemonic, alu_opcode) = self.alu_operation(modrm.reg().0, opcode)?;
+
+        let mut translation = Translation::new();
+        let rm =
+            ctx.modrm_rm_operand(code, modrm, size, imm_len, &self.cpu_mode, &mut translation)?;
+        let imm = if opcode == 0x81 {
+            extract_imm(code, size)?
+        } else {
+            extract_sign_extended(code, 1)? as u64
+        };
+        let imm = Operand::constant(imm, size);
+        match rm {
+            ModRmRmOperand::Reg(dst) => {
+                if self.emit_alu(
+                    mnemonic,
+                    alu_opcode,
+                    dst.clone(),
+                    imm,
+                    &mut ctx,
+                    &mut translation,
+                ) {
+                    self.zero_extend_gpr_write(&dst, &mut translation);
                 }
-                X86SegmentDefaultOperandSize::B32 => {
-                    if prefixes.legacy.contains(LegacyPrefix::OperandSizeOverride) {
-                        OperandSize::B2
-                    } else {
-                        OperandSize::B4
-                    }
+            }
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                if self.emit_alu(
+                    mnemonic,
+                    alu_opcode,
+                    value.clone(),
+                    imm,
+                    &mut ctx,
+                    &mut translation,
+                ) {
+                    translation
+                        .insns
+                        .push(Insn::new(Opcode::Store, addr, value));
                 }
-            },
-            X86CpuMode::LongMode => match prefixes.rex {
-                Some(rex_prefix) if rex_prefix.w_bit() => OperandSize::B8,
-                _ => {
-                    if prefixes.legacy.contains(LegacyPrefix::OperandSizeOverride) {
-                        OperandSize::B2
-                    } else {
-                        OperandSize::B4
+            }
+        }
+        Ok((mnemonic, translation))
+    }
+    /// translates an instruction of the classic alu opcode block (`0x00` to `0x3d`). bits 3 to 5 of the opcode select the
+    /// operation. the low bit selects between byte sized operands and operands of the instruction's operand size. the
+    /// second bit indicates that the modrm register is the destination rather than the source, and the third bit indicates
+    /// an immediate source with the accumulator as the destination.
+    ///
+    /// the flags are updated as described in `emit_alu`.
+    fn translate_alu(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let (mnemonic, alu_opcode) = self.alu_operation((opcode >> 3) & 0b111, opcode)?;
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+
+        let mut translation = Translation::new();
+        if opcode & 0b100 != 0 {
+            let dst = ctx.implicit_gpr_operand(Reg::Rax, size)?;
+            let imm = extract_imm(code, size)?;
+            if self.emit_alu(
+                mnemonic,
+                alu_opcode,
+                dst.clone(),
+                Operand::constant(imm, size),
+                &mut ctx,
+                &mut translation,
+            ) {
+                self.zero_extend_gpr_write(&dst, &mut translation);
+            }
+            return Ok((mnemonic, translation));
+        }
+
+        let reg_is_dst = opcode & 0b10 != 0;
+        let modrm = extract_modrm(code)?;
+        let reg = ctx.modrm_reg_operand(modrm, size);
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        match rm {
+            ModRmRmOperand::Mem(addr) if !reg_is_dst => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                if self.emit_alu(
+                    mnemonic,
+                    alu_opcode,
+                    value.clone(),
+                    reg,
+                    &mut ctx,
+                    &mut translation,
+                ) {
+                    translation
+                        .insns
+                        .push(Insn::new(Opcode::Store, addr, value));
+                }
+            }
+            rm => {
+                let rm = self.rm_value(rm, size, &mut ctx, &mut translation);
+                let (dst, src) = if reg_is_dst { (reg, rm) } else { (rm, reg) };
+                if self.emit_alu(
+                    mnemonic,
+                    alu_opcode,
+                    dst.clone(),
+                    src,
+                    &mut ctx,
+                    &mut translation,
+                ) {
+                    self.zero_extend_gpr_write(&dst, &mut translation);
+                }
+            }
+        }
+        Ok((mnemonic, translation))
+    }
+    /// translates a `mov` between a register and an r/m operand. `opcode` is the instruction's opcode, whose low bit
+    /// selects between byte sized operands and operands of the instruction's operand size, and whose second bit indicates
+    /// that the register is the destination rather than the source.
+    fn translate_mov_rm_reg(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let reg_is_dst = opcode & 0b10 != 0;
+        let modrm = extract_modrm(code)?;
+        let reg = ctx.modrm_reg_operand(modrm, size);
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        match rm {
+            ModRmRmOperand::Reg(rm) => {
+                let (dst, src) = if reg_is_dst { (reg, rm) } else { (rm, reg) };
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, dst.clone(), src));
+                self.zero_extend_gpr_write(&dst, &mut translation);
+            }
+            ModRmRmOperand::Mem(addr) => {
+                if reg_is_dst {
+                    translation
+                        .insns
+                        .push(Insn::new(Opcode::Load, reg.clone(), addr));
+                    self.zero_extend_gpr_write(&reg, &mut translation);
+                } else {
+                    translation.insns.push(Insn::new(Opcode::Store, addr, reg));
+                }
+            }
+        }
+        Ok(translation)
+    }
+    /// translates an `xchg` of an r/m operand with a register. `xchg` with a memory operand is always atomic on real
+    /// hardware, but it is translated as a plain swap.
+    fn translate_xchg_rm_reg(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        let reg = ctx.modrm_reg_operand(modrm, size);
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        match rm {
+            ModRmRmOperand::Reg(rm) => self.emit_xchg_regs(reg, rm, &mut ctx, &mut translation),
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Store, addr, reg.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, reg.clone(), value));
+                self.zero_extend_gpr_write(&reg, &mut translation);
+            }
+        }
+        Ok(translation)
+    }
+    /// translates an `xadd`, which stores the sum of an r/m operand and a register in the r/m operand, and the original value
+    /// of the r/m operand in the register. `opcode` is the instruction's second opcode byte.
+    fn translate_xadd(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        let reg = ctx.modrm_reg_operand(modrm, size);
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        let sum = ctx.tmps.alloc(size);
+        match rm {
+            ModRmRmOperand::Reg(rm) => {
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, sum.clone(), rm.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Add, sum.clone(), reg.clone()));
+                // the sum is written last, so that it wins when both operands are the same register.
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, reg.clone(), rm.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, rm.clone(), sum));
+                self.zero_extend_gpr_write(&reg, &mut translation);
+                self.zero_extend_gpr_write(&rm, &mut translation);
+            }
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, sum.clone(), value.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Add, sum.clone(), reg.clone()));
+                translation.insns.push(Insn::new(Opcode::Store, addr, sum));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, reg.clone(), value));
+                self.zero_extend_gpr_write(&reg, &mut translation);
+            }
+        }
+        Ok(translation)
+    }
+    /// translates the short form of `xchg`, which exchanges the accumulator with the given register.
+    fn translate_xchg_accumulator(&self, reg: Reg, mut ctx: PostPrefixesCtx) -> Translation {
+        let mut translation = Translation::new();
+        self.emit_xchg_regs(
+            Reg::Rax.operand(ctx.operand_size),
+            reg.operand(ctx.operand_size),
+            &mut ctx,
+            &mut translation,
+        );
+        translation
+    }
+    /// emits instructions which exchange the values of the given register operands, by moving one of them through a tmp.
+    fn emit_xchg_regs(
+        &self,
+        first: Operand,
+        second: Operand,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) {
+        let tmp = ctx.tmps.alloc(first.size);
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, tmp.clone(), first.clone()));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, first.clone(), second.clone()));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, second.clone(), tmp));
+        self.zero_extend_gpr_write(&first, translation);
+        self.zero_extend_gpr_write(&second, translation);
+    }
+    /// translates a `movzx` or `movsx`, which move a 1 or 2 byte r/m operand into a larger register, extending it with zeroes
+    /// or with copies of its sign bit. `opcode` is the instruction's second opcode byte.
+    fn translate_mov_extend(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let src_size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            OperandSize::B2
+        };
+        let extend_opcode = if opcode & 0b1000 == 0 {
+            Opcode::ZeroExtend
+        } else {
+            Opcode::SignExtend
+        };
+        let modrm = extract_modrm(code)?;
+        let dst = ctx.modrm_reg_operand(modrm, ctx.operand_size);
+
+        let mut translation = Translation::new();
+        let src = match ctx.modrm_rm_operand(
+            code,
+            modrm,
+            src_size,
+            0,
+            &self.cpu_mode,
+            &mut translation,
+        )? {
+            ModRmRmOperand::Reg(rm) => rm,
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(src_size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr));
+                value
+            }
+        };
+        translation
+            .insns
+            .push(Insn::new(extend_opcode, dst.clone(), src));
+        self.zero_extend_gpr_write(&dst, &mut translation);
+        Ok(translation)
+    }
+    /// translates a `lea`, which stores the address of its memory operand in its destination register, without accessing
+    /// the memory. the address is truncated or zero extended to the operand size.
+    fn translate_lea(
+        &self,
+        code: &mut ByteReader,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let modrm = extract_modrm(code)?;
+        if modrm.is_rm_reg() {
+            // a register operand has no address.
+            return Err(TranslateError::UnknownOpcode(0x8d));
+        }
+        let dst = ctx.modrm_reg_operand(modrm, ctx.operand_size);
+
+        let mut translation = Translation::new();
+        let addr = translate_modrm_mem_address(
+            code,
+            modrm,
+            0,
+            &mut ctx,
+            &self.cpu_mode,
+            &mut translation,
+        )?;
+        if ctx.operand_size.bytes() <= ctx.address_size.bytes() {
+            // the tmp space is little endian, so the low bytes of the address are at its start.
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                dst.clone(),
+                Operand {
+                    size: ctx.operand_size,
+                    ..addr
+                },
+            ));
+        } else {
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                dst.clone(),
+                Operand::zero(ctx.operand_size),
+            ));
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                Operand {
+                    size: ctx.address_size,
+                    ..dst.clone()
+                },
+                addr,
+            ));
+        }
+        self.zero_extend_gpr_write(&dst, &mut translation);
+        Ok(translation)
+    }
+    /// emits instructions which evaluate the given condition, and returns the 1 byte operand which holds 1 if the condition
+    /// holds and 0 otherwise.
+    fn translate_cond_code(
+        &self,
+        cond: ConditionCode,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) -> Operand {
+        let FlagTest {
+            xored_flags,
+            ored_flag,
+            is_negated,
+        } = cond.to_flag_test();
+        if let ([single_flag], None, false) = (xored_flags, ored_flag, is_negated) {
+            return flag(*single_flag);
+        }
+
+        let result = ctx.tmps.alloc(OperandSize::B1);
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            result.clone(),
+            flag(xored_flags[0]),
+        ));
+        for &xored_flag in &xored_flags[1..] {
+            translation
+                .insns
+                .push(Insn::new(Opcode::Xor, result.clone(), flag(xored_flag)));
+        }
+        if let Some(ored_flag) = ored_flag {
+            translation
+                .insns
+                .push(Insn::new(Opcode::Or, result.clone(), flag(ored_flag)));
+        }
+        if is_negated {
+            translation.insns.push(Insn::new(
+                Opcode::Xor,
+                result.clone(),
+                Operand::constant(1, OperandSize::B1),
+            ));
+        }
+        result
+    }
+    /// returns the size of the instruction pointer, which is the size of the target address of branches.
+    fn insn_pointer_size(&self, ctx: &PostPrefixesCtx) -> OperandSize {
+        match self.cpu_mode {
+            X86CpuMode::LongMode => OperandSize::B8,
+            X86CpuMode::RealMode | X86CpuMode::ProtectedMode => ctx.operand_size,
+        }
+    }
+    /// returns the length of the relative displacement of near branches. it is 16 bits wide for 16 bit operand sizes, which
+    /// are not available in long mode, and 32 bits wide otherwise.
+    fn near_rel_len(&self, ctx: &PostPrefixesCtx) -> usize {
+        match (&self.cpu_mode, ctx.operand_size) {
+            (X86CpuMode::LongMode, _) => 4,
+            (_, OperandSize::B2) => 2,
+            _ => 4,
+        }
+    }
+    /// extracts a relative displacement of `rel_len` bytes, which must be the last part of the instruction, and returns the
+    /// constant target address that it encodes.
+    fn extract_rel_target(
+        &self,
+        code: &mut ByteReader,
+        rel_len: usize,
+        ctx: &PostPrefixesCtx,
+    ) -> Result<Operand, TranslateError> {
+        let rel = extract_sign_extended(code, rel_len)?;
+        // the displacement is relative to the address of the next instruction.
+        let next_insn_addr = ctx.addr_of(code);
+        Ok(Operand::constant(
+            next_insn_addr.wrapping_add(rel as u64),
+            self.insn_pointer_size(ctx),
+        ))
+    }
+    /// translates a conditional jump with a relative displacement of `rel_len` bytes.
+    fn translate_jcc(
+        &self,
+        code: &mut ByteReader,
+        cond: ConditionCode,
+        rel_len: usize,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let target = self.extract_rel_target(code, rel_len, &ctx)?;
+
+        let mut translation = Translation::new();
+        let cond = self.translate_cond_code(cond, &mut ctx, &mut translation);
+        translation
+            .insns
+            .push(Insn::new(Opcode::Branch, cond, target));
+        Ok(translation)
+    }
+    /// translates a jump to a relative target, with a relative displacement of `rel_len` bytes.
+    fn translate_jmp_rel(
+        &self,
+        code: &mut ByteReader,
+        rel_len: usize,
+        ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let target = self.extract_rel_target(code, rel_len, &ctx)?;
+
+        let mut translation = Translation::new();
+        translation.insns.push(Insn::new(
+            Opcode::Jump,
+            target,
+            Operand::zero(OperandSize::B1),
+        ));
+        Ok(translation)
+    }
+    /// translates an instruction of the `0xff` opcode group, whose operation is selected by the reg field of its modrm byte.
+    fn translate_group5(
+        &self,
+        code: &mut ByteReader,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let modrm = extract_modrm(code)?;
+        let mut translation = Translation::new();
+        match modrm.reg().0 {
+            0 | 1 => self.translate_inc_dec(code, modrm, ctx.operand_size, ctx),
+            6 => {
+                let size = self.stack_operand_size(&ctx);
+                let rm =
+                    ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+                let mut builder = TranslationBuilder::with_tmps(ctx.tmps);
+                builder.insns.extend(translation.insns);
+                let value = match rm {
+                    ModRmRmOperand::Reg(reg) => reg,
+                    ModRmRmOperand::Mem(addr) => {
+                        let value = builder.alloc_tmp(size);
+                        builder.load(value.clone(), addr);
+                        value
                     }
+                };
+                self.push(value, &mut builder);
+                Ok((InsnMnemonic::Push, builder.build()))
+            }
+            4 => {
+                let size = self.insn_pointer_size(&ctx);
+                let target = match ctx.modrm_rm_operand(
+                    code,
+                    modrm,
+                    size,
+                    0,
+                    &self.cpu_mode,
+                    &mut translation,
+                )? {
+                    ModRmRmOperand::Reg(reg) => reg,
+                    ModRmRmOperand::Mem(addr) => {
+                        let target = ctx.tmps.alloc(size);
+                        translation
+                            .insns
+                            .push(Insn::new(Opcode::Load, target.clone(), addr));
+                        target
+                    }
+                };
+                translation.insns.push(Insn::new(
+                    Opcode::Jump,
+                    target,
+                    Operand::zero(OperandSize::B1),
+                ));
+                Ok((InsnMnemonic::Jmp, translation))
+            }
+            _ => Err(TranslateError::UnknownOpcode(0xff)),
+        }
+    }
+    /// translates a shift or rotate of group 2, selected by the reg field of the modrm byte. the amount is an immediate byte
+    /// for `0xc0` and `0xc1`, 1 for `0xd0` and `0xd1`, and `cl` for `0xd2` and `0xd3`. the even opcodes have byte sized
+    /// operands.
+    ///
+    /// the amount is masked to 6 bits for 64 bit operands and to 5 bits otherwise, like on real cpus. the flags are not yet
+    /// lifted.
+    fn translate_group2(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        let (mnemonic, shift_opcode) = match modrm.reg().0 {
+            0 => (InsnMnemonic::Rol, Opcode::Rol),
+            1 => (InsnMnemonic::Ror, Opcode::Ror),
+            // `/6` is an undocumented alias of `shl`.
+            4 | 6 => (InsnMnemonic::Shl, Opcode::Shl),
+            5 => (InsnMnemonic::Shr, Opcode::Shr),
+            7 => (InsnMnemonic::Sar, Opcode::Sar),
+            _ => return Err(TranslateError::UnknownOpcode(opcode)),
+        };
+        let amount_mask = if size == OperandSize::B8 { 0x3f } else { 0x1f };
+        let imm_len = if opcode <= 0xc1 { 1 } else { 0 };
+
+        let mut translation = Translation::new();
+        let rm =
+            ctx.modrm_rm_operand(code, modrm, size, imm_len, &self.cpu_mode, &mut translation)?;
+        let amount = match opcode {
+            0xc0 | 0xc1 => Operand::constant(
+                extract_sign_extended(code, 1)? as u64 & amount_mask,
+                OperandSize::B1,
+            ),
+            0xd0 | 0xd1 => Operand::constant(1, OperandSize::B1),
+            _ => {
+                let amount = ctx.tmps.alloc(OperandSize::B1);
+                translation.insns.push(Insn::new(
+                    Opcode::Move,
+                    amount.clone(),
+                    ctx.implicit_gpr_operand(Reg::Rcx, OperandSize::B1)?,
+                ));
+                translation.insns.push(Insn::new(
+                    Opcode::And,
+                    amount.clone(),
+                    Operand::constant(amount_mask, OperandSize::B1),
+                ));
+                amount
+            }
+        };
+        match rm {
+            ModRmRmOperand::Reg(dst) => {
+                translation
+                    .insns
+                    .push(Insn::new(shift_opcode, dst.clone(), amount));
+                self.zero_extend_gpr_write(&dst, &mut translation);
+            }
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                translation
+                    .insns
+                    .push(Insn::new(shift_opcode, value.clone(), amount));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Store, addr, value));
+            }
+        }
+        Ok((mnemonic, translation))
+    }
+    /// translates an instruction of the `0xf6` and `0xf7` opcode group, whose operation is selected by the reg field of its
+    /// modrm byte.
+    fn translate_group3(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let size = if opcode == 0xf6 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        let (mnemonic, unary_opcode) = match modrm.reg().0 {
+            // `/1` is an undocumented alias of `test`.
+            0 | 1 => {
+                let translation = self.translate_test_rm_imm(code, modrm, size, ctx)?;
+                return Ok((InsnMnemonic::Test, translation));
+            }
+            2 => (InsnMnemonic::Not, Opcode::Not),
+            3 => (InsnMnemonic::Neg, Opcode::Neg),
+            4 => (InsnMnemonic::Mul, Opcode::UMul),
+            5 => (InsnMnemonic::Imul, Opcode::SMul),
+            6 => (InsnMnemonic::Div, Opcode::UDiv),
+            7 => (InsnMnemonic::Idiv, Opcode::SDiv),
+            _ => unreachable!(),
+        };
+        if modrm.reg().0 >= 4 {
+            let translation = self.translate_mul_div(code, modrm, size, unary_opcode, ctx)?;
+            return Ok((mnemonic, translation));
+        }
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        let (value, mem_addr) = match rm {
+            ModRmRmOperand::Reg(reg) => (reg, None),
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr.clone()));
+                (value, Some(addr))
+            }
+        };
+        if unary_opcode == Opcode::Neg {
+            // `neg` sets the flags like a subtraction of the operand from zero.
+            let original = ctx.tmps.alloc(size);
+            translation
+                .insns
+                .push(Insn::new(Opcode::Move, original.clone(), value.clone()));
+            translation
+                .insns
+                .push(Insn::new(Opcode::Neg, value.clone(), Operand::zero(size)));
+            self.emit_add_sub_flags(
+                Opcode::Sub,
+                &Operand::zero(size),
+                &original,
+                &value,
+                true,
+                &mut ctx,
+                &mut translation,
+            );
+        } else {
+            translation
+                .insns
+                .push(Insn::new(unary_opcode, value.clone(), Operand::zero(size)));
+        }
+        match mem_addr {
+            Some(addr) => translation
+                .insns
+                .push(Insn::new(Opcode::Store, addr, value)),
+            None => self.zero_extend_gpr_write(&value, &mut translation),
+        }
+        Ok((mnemonic, translation))
+    }
+    /// translates a `setcc`, which writes 1 to a byte sized r/m operand if the condition holds, and 0 otherwise.
+    fn translate_setcc(
+        &self,
+        code: &mut ByteReader,
+        cond: ConditionCode,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let modrm = extract_modrm(code)?;
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(
+            code,
+            modrm,
+            OperandSize::B1,
+            0,
+            &self.cpu_mode,
+            &mut translation,
+        )?;
+        let cond = self.translate_cond_code(cond, &mut ctx, &mut translation);
+        match rm {
+            ModRmRmOperand::Reg(dst) => translation.insns.push(Insn::new(Opcode::Move, dst, cond)),
+            ModRmRmOperand::Mem(addr) => {
+                translation.insns.push(Insn::new(Opcode::Store, addr, cond))
+            }
+        }
+        Ok(translation)
+    }
+    /// translates a conditional move of an r/m operand into a register.
+    ///
+    /// the condition is lowered without branching, so that the instruction remains a single straight line translation. the
+    /// condition is turned into a mask of all ones or all zeroes, and the register is xored with the masked difference
+    /// between it and the source. the source is always read, and in long mode a 32 bit destination is always zero
+    /// extended, even if the condition doesn't hold, like on real cpus.
+    fn translate_cmov(
+        &self,
+        code: &mut ByteReader,
+        cond: ConditionCode,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = ctx.operand_size;
+        let modrm = extract_modrm(code)?;
+        let dst = ctx.modrm_reg_operand(modrm, size);
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        let src = self.rm_value(rm, size, &mut ctx, &mut translation);
+        let cond = self.translate_cond_code(cond, &mut ctx, &mut translation);
+
+        let mask = ctx.tmps.alloc(size);
+        translation
+            .insns
+            .push(Insn::new(Opcode::ZeroExtend, mask.clone(), cond));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Neg, mask.clone(), Operand::zero(size)));
+        let difference = ctx.tmps.alloc(size);
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, difference.clone(), src));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Xor, difference.clone(), dst.clone()));
+        translation
+            .insns
+            .push(Insn::new(Opcode::And, difference.clone(), mask));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Xor, dst.clone(), difference));
+        self.zero_extend_gpr_write(&dst, &mut translation);
+        Ok(translation)
+    }
+    /// translates a `test` of an r/m operand with a register.
+    fn translate_test_rm_reg(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let modrm = extract_modrm(code)?;
+        let reg = ctx.modrm_reg_operand(modrm, size);
+
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        let rm = self.rm_value(rm, size, &mut ctx, &mut translation);
+        self.emit_test(rm, reg, &mut ctx, &mut translation);
+        Ok(translation)
+    }
+    /// translates a `test` of an r/m operand with an immediate.
+    fn translate_test_rm_imm(
+        &self,
+        code: &mut ByteReader,
+        modrm: ModRm,
+        size: OperandSize,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(
+            code,
+            modrm,
+            size,
+            size.bytes().min(4),
+            &self.cpu_mode,
+            &mut translation,
+        )?;
+        let imm = Operand::constant(extract_imm(code, size)?, size);
+        let rm = self.rm_value(rm, size, &mut ctx, &mut translation);
+        self.emit_test(rm, imm, &mut ctx, &mut translation);
+        Ok(translation)
+    }
+    /// returns an operand holding the value of the given r/m operand, loading it into a tmp if it is a memory operand.
+    fn rm_value(
+        &self,
+        rm: ModRmRmOperand,
+        size: OperandSize,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) -> Operand {
+        match rm {
+            ModRmRmOperand::Reg(reg) => reg,
+            ModRmRmOperand::Mem(addr) => {
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), addr));
+                value
+            }
+        }
+    }
+    /// emits the instructions of a `test`, which ands the given operands into a tmp, and sets the flags according to the
+    /// result without storing it.
+    fn emit_test(
+        &self,
+        first: Operand,
+        second: Operand,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) {
+        let result = ctx.tmps.alloc(first.size);
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, result.clone(), first));
+        translation
+            .insns
+            .push(Insn::new(Opcode::And, result.clone(), second));
+        self.emit_logic_flags(&result, translation);
+    }
+    /// emits instructions which set the flags according to the given result of a bitwise logic operation, which clears the
+    /// carry and overflow flags.
+    fn emit_logic_flags(&self, result: &Operand, translation: &mut Translation) {
+        self.emit_result_flags(result, translation);
+        for flag in [FLAG_CF, FLAG_OF] {
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                flag,
+                Operand::zero(OperandSize::B1),
+            ));
+        }
+    }
+    /// emits instructions which set the zero, sign and parity flags according to the given result of an operation.
+    fn emit_result_flags(&self, result: &Operand, translation: &mut Translation) {
+        for (cond, flag) in [
+            (Cond::Zero, FLAG_ZF),
+            (Cond::Negative, FLAG_SF),
+            (Cond::EvenParity, FLAG_PF),
+        ] {
+            translation
+                .insns
+                .push(Insn::new(Opcode::SetCond(cond), flag, result.clone()));
+        }
+    }
+    /// translates the one operand forms of `mul`, `imul`, `div` and `idiv`, which operate on the double width value held in
+    /// the accumulator and the data register, using the given double width opcode.
+    ///
+    /// for byte sized operands the double width value is `ax`, and otherwise its low half is in `rax` and its high half is
+    /// in `rdx`, in the operand size. the multiplications write the product to the double width value, and the divisions
+    /// write the quotient to its low half and the remainder to its high half.
+    fn translate_mul_div(
+        &self,
+        code: &mut ByteReader,
+        modrm: ModRm,
+        size: OperandSize,
+        opcode: Opcode,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let mut translation = Translation::new();
+        let src =
+            match ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)? {
+                ModRmRmOperand::Reg(reg) => reg,
+                ModRmRmOperand::Mem(addr) => {
+                    let value = ctx.tmps.alloc(size);
+                    translation
+                        .insns
+                        .push(Insn::new(Opcode::Load, value.clone(), addr));
+                    value
                 }
+            };
+        let wide_size = OperandSize::from_bytes(size.bytes() * 2).unwrap();
+        let is_div = matches!(opcode, Opcode::UDiv | Opcode::SDiv);
+        let wide = if size == OperandSize::B1 {
+            Reg::Rax.operand(OperandSize::B2)
+        } else {
+            ctx.tmps.alloc(wide_size)
+        };
+        let low = Operand {
+            size,
+            ..wide.clone()
+        };
+        let high = Operand {
+            addr: OperandAddr {
+                offset: wide.addr.offset + size.bytes() as u64,
+                ..wide.addr
             },
+            size,
+        };
+        if size != OperandSize::B1 {
+            translation
+                .insns
+                .push(Insn::new(Opcode::Move, low.clone(), Reg::Rax.operand(size)));
+            if is_div {
+                translation.insns.push(Insn::new(
+                    Opcode::Move,
+                    high.clone(),
+                    Reg::Rdx.operand(size),
+                ));
+            }
+        }
+        translation.insns.push(Insn::new(opcode, wide.clone(), src));
+
+        if !is_div {
+            self.emit_mul_overflow_flags(opcode, &low, &high, &mut ctx, &mut translation);
+        }
+
+        if size != OperandSize::B1 {
+            for (reg, half) in [(Reg::Rax, low), (Reg::Rdx, high)] {
+                let dst = reg.operand(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, dst.clone(), half));
+                self.zero_extend_gpr_write(&dst, &mut translation);
+            }
         }
+        Ok(translation)
     }
+    /// emits instructions which set the carry and overflow flags after a multiplication with the given opcode, whose full
+    /// product is split into the given `low` and `high` halves. the flags are set when the high half of the product is
+    /// significant, which is when it is not just the zero or sign extension of the low half.
+    fn emit_mul_overflow_flags(
+        &self,
+        opcode: Opcode,
+        low: &Operand,
+        high: &Operand,
+        ctx: &mut PostPrefixesCtx,
+        translation: &mut Translation,
+    ) {
+        let size = low.size;
+        let extension = ctx.tmps.alloc(size);
+        if opcode == Opcode::UMul {
+            translation.insns.push(Insn::new(
+                Opcode::Move,
+                extension.clone(),
+                Operand::zero(size),
+            ));
+        } else {
+            translation
+                .insns
+                .push(Insn::new(Opcode::Move, extension.clone(), low.clone()));
+            translation.insns.push(Insn::new(
+                Opcode::Sar,
+                extension.clone(),
+                Operand::constant(size.bits() as u64 - 1, OperandSize::B1),
+            ));
+        }
+        translation
+            .insns
+            .push(Insn::new(Opcode::Xor, extension.clone(), high.clone()));
+        translation
+            .insns
+            .push(Insn::new(Opcode::SetCond(Cond::Zero), FLAG_CF, extension));
+        translation.insns.push(Insn::new(
+            Opcode::Xor,
+            FLAG_CF,
+            Operand::constant(1, OperandSize::B1),
+        ));
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, FLAG_OF, FLAG_CF));
+    }
+    /// translates the two and three operand forms of `imul`, which multiply an r/m operand by either the destination
+    /// register (`0x0f 0xaf`) or an immediate (`0x69` and `0x6b`), and write the product truncated to the operand size to
+    /// the destination register. `0x69` has an immediate of the instruction's operand size, and `0x6b` has a byte immediate
+    /// which is sign extended to the operand size.
+    ///
+    /// like the single operand form, the carry and overflow flags are set if the truncated product differs from the full
+    /// product.
+    fn translate_imul_truncating(
+        &self,
+        code: &mut ByteReader,
+        imm_len: Option<usize>,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let size = ctx.operand_size;
+        let modrm = extract_modrm(code)?;
+        let dst = ctx.modrm_reg_operand(modrm, size);
 
-    fn resolve_address_size(&self, prefixes: &InsnPrefixes) -> OperandSize {
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(
+            code,
+            modrm,
+            size,
+            imm_len.unwrap_or(0),
+            &self.cpu_mode,
+            &mut translation,
+        )?;
+        let src = self.rm_value(rm, size, &mut ctx, &mut translation);
+        let multiplier = match imm_len {
+            None => dst.clone(),
+            Some(1) => {
+                Operand::constant(extract_sign_extended(code, 1)? as u64 & size.mask(), size)
+            }
+            Some(_) => Operand::constant(extract_imm(code, size)?, size),
+        };
+
+        let product = ctx
+            .tmps
+            .alloc(OperandSize::from_bytes(size.bytes() * 2).unwrap());
+        let low = Operand {
+            size,
+            ..product.clone()
+        };
+        let high = Operand {
+            addr: OperandAddr {
+                offset: product.addr.offset + size.bytes() as u64,
+                ..product.addr
+            },
+            size,
+        };
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, low.clone(), src));
+        translation
+            .insns
+            .push(Insn::new(Opcode::SMul, product.clone(), multiplier));
+        self.emit_mul_overflow_flags(Opcode::SMul, &low, &high, &mut ctx, &mut translation);
+
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, dst.clone(), low));
+        self.zero_extend_gpr_write(&dst, &mut translation);
+        Ok(translation)
+    }
+    /// translates the instructions of opcode `0xfe`, which are the byte sized `inc` and `dec`.
+    fn translate_group4(
+        &self,
+        code: &mut ByteReader,
+        ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let modrm = extract_modrm(code)?;
+        match modrm.reg().0 {
+            0 | 1 => self.translate_inc_dec(code, modrm, OperandSize::B1, ctx),
+            _ => Err(TranslateError::UnknownOpcode(0xfe)),
+        }
+    }
+    /// translates an `inc` or `dec` of an r/m operand of the given size, according to the reg field of the modrm byte. the
+    /// flags are updated like for an `add` or `sub` of 1, except for the carry flag, which is preserved.
+    fn translate_inc_dec(
+        &self,
+        code: &mut ByteReader,
+        modrm: ModRm,
+        size: OperandSize,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let mut translation = Translation::new();
+        let rm = ctx.modrm_rm_operand(code, modrm, size, 0, &self.cpu_mode, &mut translation)?;
+        Ok(self.emit_inc_dec(modrm.reg().0 == 0, rm, size, ctx, translation))
+    }
+    /// translates the single byte form of `inc` and `dec`, which encodes its register in the low 3 bits of the opcode. this
+    /// form doesn't exist in long mode, where its opcodes are used as the rex prefix.
+    fn translate_inc_dec_reg(
+        &self,
+        opcode: u8,
+        ctx: PostPrefixesCtx,
+    ) -> (InsnMnemonic, Translation) {
+        let size = ctx.operand_size;
+        let reg = ctx.gpr_operand(opcode & 0b111, size);
+        self.emit_inc_dec(
+            opcode < 0x48,
+            ModRmRmOperand::Reg(reg),
+            size,
+            ctx,
+            Translation::new(),
+        )
+    }
+    /// emits an `inc` or a `dec` of the given operand into the given translation, which already contains the calculation of
+    /// the operand's address if it is a memory operand.
+    fn emit_inc_dec(
+        &self,
+        is_inc: bool,
+        rm: ModRmRmOperand,
+        size: OperandSize,
+        mut ctx: PostPrefixesCtx,
+        mut translation: Translation,
+    ) -> (InsnMnemonic, Translation) {
+        let (mnemonic, opcode) = if is_inc {
+            (InsnMnemonic::Inc, Opcode::Add)
+        } else {
+            (InsnMnemonic::Dec, Opcode::Sub)
+        };
+        let one = Operand::constant(1, size);
+
+        let mem_addr = match &rm {
+            ModRmRmOperand::Reg(_) => None,
+            ModRmRmOperand::Mem(addr) => Some(addr.clone()),
+        };
+        let value = self.rm_value(rm, size, &mut ctx, &mut translation);
+        let result = ctx.tmps.alloc(size);
+        translation
+            .insns
+            .push(Insn::new(Opcode::Move, result.clone(), value.clone()));
+        translation
+            .insns
+            .push(Insn::new(opcode, result.clone(), one.clone()));
+        self.emit_add_sub_flags(
+            opcode,
+            &value,
+            &one,
+            &result,
+            false,
+            &mut ctx,
+            &mut translation,
+        );
+        match mem_addr {
+            Some(addr) => translation
+                .insns
+                .push(Insn::new(Opcode::Store, addr, result)),
+            None => {
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Move, value.clone(), result));
+                self.zero_extend_gpr_write(&value, &mut translation);
+            }
+        }
+        (mnemonic, translation)
+    }
+    fn translate_nop(&self, insn_len: usize) -> Translation {
+        let mut translation = Translation::new();
+        if self.preserve_nops {
+            translation.insns.push(Insn::new(
+                Opcode::Nop,
+                Operand::constant(insn_len as u64, OperandSize::B1),
+                Operand::zero(OperandSize::B1),
+            ));
+        }
+        translation
+    }
+    /// translates an instruction which sets or clears the given flag, like `stc` or `cld`.
+    fn translate_set_flag(&self, flag: Operand, value: bool) -> Translation {
+        let mut translation = Translation::new();
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            flag,
+            Operand::constant(value as u64, OperandSize::B1),
+        ));
+        translation
+    }
+    fn translate_complement_carry_flag(&self) -> Translation {
+        let mut translation = Translation::new();
+        translation.insns.push(Insn::new(
+            Opcode::Xor,
+            FLAG_CF,
+            Operand::constant(1, OperandSize::B1),
+        ));
+        translation
+    }
+    /// translates a string instruction, which operates on the memory pointed to by `rsi` and `rdi`, and then advances them
+    /// according to the direction flag. `code` is the code following the opcode, which is used to find the address of the
+    /// next instruction.
+    ///
+    /// with a `rep` prefix, the instruction is repeated `rcx` times. this is translated as a single iteration which exits to
+    /// the next instruction when the counter is zero, and otherwise decrements the counter and jumps back to the
+    /// instruction itself. `repz` and `repnz` prefixed `scas` also exit when the zero flag is cleared or set, respectively.
+    fn translate_string_op(
+        &self,
+        code: &ByteReader,
+        opcode: u8,
+        mut ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let size = if opcode & 1 == 0 {
+            OperandSize::B1
+        } else {
+            ctx.operand_size
+        };
+        let mnemonic = match opcode {
+            0xa4 | 0xa5 => InsnMnemonic::Movs,
+            0xaa | 0xab => InsnMnemonic::Stos,
+            0xac | 0xad => InsnMnemonic::Lods,
+            _ => InsnMnemonic::Scas,
+        };
+        let address_size = ctx.address_size;
+        let counter = Reg::Rcx.operand(address_size);
+        let accumulator = ctx.implicit_gpr_operand(Reg::Rax, size)?;
+        let src_index = Reg::RsiDh.operand(address_size);
+        let dst_index = Reg::RdiBh.operand(address_size);
+        let next_insn_addr = Operand::constant(ctx.addr_of(code), self.insn_pointer_size(&ctx));
+        let is_rep = ctx.prefixes.legacy.has_rep() || ctx.prefixes.legacy.has_repnz();
+
+        let mut translation = Translation::new();
+        if is_rep {
+            let is_done = ctx.tmps.alloc(OperandSize::B1);
+            translation.insns.push(Insn::new(
+                Opcode::SetCond(Cond::Zero),
+                is_done.clone(),
+                counter.clone(),
+            ));
+            translation
+                .insns
+                .push(Insn::new(Opcode::Branch, is_done, next_insn_addr.clone()));
+        }
+
+        // the destination is always in the `es` segment, which is treated as flat since it can't be overridden, while the
+        // source may have a segment override.
+        match mnemonic {
+            InsnMnemonic::Movs => {
+                let src = ctx.linear_address(src_index.clone(), &self.cpu_mode, &mut translation);
+                let value = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), src));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Store, dst_index.clone(), value));
+            }
+            InsnMnemonic::Stos => {
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Store, dst_index.clone(), accumulator));
+            }
+            InsnMnemonic::Lods => {
+                let src = ctx.linear_address(src_index.clone(), &self.cpu_mode, &mut translation);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, accumulator.clone(), src));
+                self.zero_extend_gpr_write(&accumulator, &mut translation);
+            }
+            _ => {
+                // the flags are set like a `cmp` of the accumulator with the value.
+                let value = ctx.tmps.alloc(size);
+                let difference = ctx.tmps.alloc(size);
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Load, value.clone(), dst_index.clone()));
+                translation.insns.push(Insn::new(
+                    Opcode::Move,
+                    difference.clone(),
+                    accumulator.clone(),
+                ));
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Sub, difference.clone(), value.clone()));
+                self.emit_add_sub_flags(
+                    Opcode::Sub,
+                    &accumulator,
+                    &value,
+                    &difference,
+                    true,
+                    &mut ctx,
+                    &mut translation,
+                );
+            }
+        }
+
+        // the indices advance by the operand size, or move back by it when the direction flag is set. this is calculated
+        // without branching as `size - 2 * size * df`.
+        let backward_step = ctx.tmps.alloc(address_size);
+        translation.insns.push(Insn::new(
+            Opcode::ZeroExtend,
+            backward_step.clone(),
+            FLAG_DF,
+        ));
+        translation.insns.push(Insn::new(
+            Opcode::Shl,
+            backward_step.clone(),
+            Operand::constant(size.bytes().trailing_zeros() as u64 + 1, address_size),
+        ));
+        let advance = |index: Operand, translation: &mut Translation| {
+            translation.insns.push(Insn::new(
+                Opcode::Add,
+                index.clone(),
+                Operand::constant(size.bytes() as u64, address_size),
+            ));
+            translation
+                .insns
+                .push(Insn::new(Opcode::Sub, index.clone(), backward_step.clone()));
+            self.zero_extend_gpr_write(&index, translation);
+        };
+        if matches!(mnemonic, InsnMnemonic::Movs | InsnMnemonic::Lods) {
+            advance(src_index, &mut translation);
+        }
+        if mnemonic != InsnMnemonic::Lods {
+            advance(dst_index, &mut translation);
+        }
+
+        if is_rep {
+            translation.insns.push(Insn::new(
+                Opcode::Sub,
+                counter.clone(),
+                Operand::constant(1, address_size),
+            ));
+            self.zero_extend_gpr_write(&counter, &mut translation);
+            if mnemonic == InsnMnemonic::Scas {
+                let is_done = if ctx.prefixes.legacy.has_rep() {
+                    let is_done = ctx.tmps.alloc(OperandSize::B1);
+                    translation.insns.push(Insn::new(
+                        Opcode::SetCond(Cond::Zero),
+                        is_done.clone(),
+                        FLAG_ZF,
+                    ));
+                    is_done
+                } else {
+                    FLAG_ZF
+                };
+                translation
+                    .insns
+                    .push(Insn::new(Opcode::Branch, is_done, next_insn_addr));
+            }
+            translation.insns.push(Insn::new(
+                Opcode::Jump,
+                Operand::constant(ctx.insn_addr, self.insn_pointer_size(&ctx)),
+                Operand::zero(OperandSize::B1),
+            ));
+        }
+        Ok((mnemonic, translation))
+    }
+    fn translate_xlat(&self, mut ctx: PostPrefixesCtx) -> Translation {
+        let mut translation = Translation::new();
+        let addr = ctx.tmps.alloc(ctx.address_size);
+
+        // zero extend `al` to the address size, using the fact that the tmp space is little endian.
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            addr.clone(),
+            Operand::zero(ctx.address_size),
+        ));
+        translation.insns.push(Insn::new(
+            Opcode::Move,
+            Operand {
+                size: OperandSize::B1,
+                ..addr.clone()
+            },
+            AL,
+        ));
+
+        translation.insns.push(Insn::new(
+            Opcode::Add,
+            addr.clone(),
+            Reg::Rbx.operand(ctx.address_size),
+        ));
+        let addr = ctx.linear_address(addr, &self.cpu_mode, &mut translation);
+        translation.insns.push(Insn::new(Opcode::Load, AL, addr));
+        translation
+    }
+    /// translates a `mov` to or from a control or debug register. `opcode` is the low nibble of the instruction's second
+    /// opcode byte, which encodes the kind of special register and the direction of the move.
+    fn translate_mov_special_reg(
+        &self,
+        code: &mut ByteReader,
+        opcode: u8,
+        ctx: PostPrefixesCtx,
+    ) -> Result<Translation, TranslateError> {
+        let modrm = extract_modrm(code)?;
+
+        // the mod bits are ignored, and the r/m field always encodes a general purpose register.
+        let special_reg = modrm.effective_reg(ctx.prefixes.rex);
+        let gpr = modrm.effective_rm(ctx.prefixes.rex);
+
+        // the operand size is determined by the cpu mode, and the operand size override prefix is ignored.
+        let size = match self.cpu_mode {
+            X86CpuMode::LongMode => OperandSize::B8,
+            X86CpuMode::RealMode | X86CpuMode::ProtectedMode => OperandSize::B4,
+        };
+
+        let intrinsic = match opcode {
+            0x0 => Intrinsic::ReadControlReg(special_reg),
+            0x1 => Intrinsic::ReadDebugReg(special_reg),
+            0x2 => Intrinsic::WriteControlReg(special_reg),
+            0x3 => Intrinsic::WriteDebugReg(special_reg),
+            _ => unreachable!(),
+        };
+        let mut translation = Translation::new();
+        translation.insns.push(Insn::new(
+            Opcode::Intrinsic(intrinsic),
+            gpr_operand(gpr, size),
+            Operand::zero(OperandSize::B1),
+        ));
+        Ok(translation)
+    }
+    fn translate_intrinsic(&self, intrinsic: Intrinsic) -> Translation {
+        let mut translation = Translation::new();
+        translation.insns.push(Insn::new(
+            Opcode::Intrinsic(intrinsic),
+            Operand::zero(OperandSize::B1),
+            Operand::zero(OperandSize::B1),
+        ));
+        translation
+    }
+
+    /// translates an instruction from the two byte opcode map, after its `0x0f` escape byte was skipped.
+    fn translate_two_byte_opcode(
+        &self,
+        code: &mut ByteReader,
+        ctx: PostPrefixesCtx,
+    ) -> Result<(InsnMnemonic, Translation), TranslateError> {
+        let opcode = code.read_u8()?;
+        Ok(match TWO_BYTE_OPCODE_HANDLERS[opcode as usize] {
+            TwoByteOpcodeHandler::Syscall => (
+                InsnMnemonic::Syscall,
+                self.translate_intrinsic(Intrinsic::Syscall),
+            ),
+            TwoByteOpcodeHandler::Sysret => (
+                InsnMnemonic::Sysret,
+                self.translate_intrinsic(Intrinsic::Sysret),
+            ),
+            TwoByteOpcodeHandler::Sysenter => (
+                InsnMnemonic::Sysenter,
+                self.translate_intrinsic(Intrinsic::Sysenter),
+            ),
+            TwoByteOpcodeHandler::Sysexit => (
+                InsnMnemonic::Sysexit,
+                self.translate_intrinsic(Intrinsic::Sysexit),
+            ),
+            TwoByteOpcodeHandler::Jcc => (
+                InsnMnemonic::Jcc,
+                self.translate_jcc(
+                    code,
+                    ConditionCode::from_opcode_low_nibble(opcode),
+                    self.near_rel_len(&ctx),
+                    ctx,
+                )?,
+            ),
+            TwoByteOpcodeHandler::MovSpecialReg => (
+                InsnMnemonic::Mov,
+                self.translate_mov_special_reg(code, opcode & 0xf, ctx)?,
+            ),
+            TwoByteOpcodeHandler::Cmov => (
+                InsnMnemonic::Cmovcc,
+                self.translate_cmov(code, ConditionCode::from_opcode_low_nibble(opcode), ctx)?,
+            ),
+            TwoByteOpcodeHandler::Setcc => (
+                InsnMnemonic::Setcc,
+                self.translate_setcc(code, ConditionCode::from_opcode_low_nibble(opcode), ctx)?,
+            ),
+            TwoByteOpcodeHandler::Imul => (
+                InsnMnemonic::Imul,
+                self.translate_imul_truncating(code, None, ctx)?,
+            ),
+            TwoByteOpcodeHandler::Xadd => {
+                (InsnMnemonic::Xadd, self.translate_xadd(code, opcode, ctx)?)
+            }
+            TwoByteOpcodeHandler::Movzx => (
+                InsnMnemonic::Movzx,
+                self.translate_mov_extend(code, opcode, ctx)?,
+            ),
+            TwoByteOpcodeHandler::Movsx => (
+                InsnMnemonic::Movsx,
+                self.translate_mov_extend(code, opcode, ctx)?,
+            ),
+            // the cet branch target markers are encoded as `rep` prefixed hint nops with a specific modrm byte.
+            TwoByteOpcodeHandler::HintNop
+                if opcode == 0x1e
+                    && ctx.prefixes.legacy.has_rep()
+                    && matches!(code.peek_u8(), Some(0xfa | 0xfb)) =>
+            {
+                let is_64_bit = code.read_u8()? == 0xfa;
+                if is_64_bit {
+                    (
+                        InsnMnemonic::Endbr64,
+                        self.translate_intrinsic(Intrinsic::Endbr64),
+                    )
+                } else {
+                    (
+                        InsnMnemonic::Endbr32,
+                        self.translate_intrinsic(Intrinsic::Endbr32),
+                    )
+                }
+            }
+            // prefetch hints, reserved hint nops and the multi byte nop. none of them have any architectural effect.
+            TwoByteOpcodeHandler::HintNop => {
+                let mnemonic = match code.peek_u8() {
+                    Some(modrm) if opcode == 0x18 && modrm >> 6 != 0b11 => {
+                        match (modrm >> 3) & 0b111 {
+                            0 => InsnMnemonic::Prefetchnta,
+                            1 => InsnMnemonic::Prefetcht0,
+                            2 => InsnMnemonic::Prefetcht1,
+                            3 => InsnMnemonic::Prefetcht2,
+                            _ => InsnMnemonic::Nop,
+                        }
+                    }
+                    _ => InsnMnemonic::Nop,
+                };
+                skip_modrm(code, ctx.address_size)?;
+                (mnemonic, self.translate_nop(code.position()))
+            }
+            TwoByteOpcodeHandler::Unknown => return Err(TranslateError::UnknownOpcode(opcode)),
+        })
+    }
+
+    /// resolves the operand size of an instruction with the given prefixes.
+    ///
+    /// - in real mode, the default operand size is 16 bits, and the operand size override prefix selects 32 bits.
+    /// - in protected mode, the default operand size is determined by the `D` flag of the code segment, and the operand size
+    ///   override prefix selects the other one of 16 and 32 bits.
+    /// - in long mode, the default operand size is 32 bits, and the operand size override prefix selects 16 bits. the
+    ///   `W` bit of the rex prefix selects 64 bits, and takes precedence over the operand size override prefix.
+    ///
+    /// instructions which default to a 64 bit operand size in long mode, like `push` and `pop`, don't use this.
+    fn resolve_operand_size(&self, prefixes: &InsnPrefixes) -> OperandSize {
         match self.cpu_mode {
             X86CpuMode::RealMode => {
-                if prefixes.legacy.contains(LegacyPrefix::AddressSizeOverride) {
+                if prefixes.legacy.has_operand_size_override() {
                     OperandSize::B4
                 } else {
                     OperandSize::B2
@@ -305,14 +3146,14 @@ impl X86Ctx {
             }
             X86CpuMode::ProtectedMode => match self.code_segment_default_operand_size {
                 X86SegmentDefaultOperandSize::B16 => {
-                    if prefixes.legacy.contains(LegacyPrefix::AddressSizeOverride) {
+                    if prefixes.legacy.has_operand_size_override() {
                         OperandSize::B4
                     } else {
                         OperandSize::B2
                     }
                 }
                 X86SegmentDefaultOperandSize::B32 => {
-                    if prefixes.legacy.contains(LegacyPrefix::AddressSizeOverride) {
+                    if prefixes.legacy.has_operand_size_override() {
                         OperandSize::B2
                     } else {
                         OperandSize::B4
@@ -320,37 +3161,441 @@ impl X86Ctx {
                 }
             },
             X86CpuMode::LongMode => match prefixes.rex {
-                Some(rex_prefix) if rex_prefix.w_bit() => {
-                    if prefixes.legacy.contains(LegacyPrefix::AddressSizeOverride) {
+                Some(rex_prefix) if rex_prefix.w_bit() => OperandSize::B8,
+                _ => {
+                    if prefixes.legacy.has_operand_size_override() {
+                        OperandSize::B2
+                    } else {
+                        OperandSize::B4
+                    }
+                }
+            },
+        }
+    }
+
+    /// resolves the address size of an instruction with the given prefixes.
+    ///
+    /// - in real mode, the default address size is 16 bits, and the address size override prefix selects 32 bits.
+    /// - in protected mode, the default address size is determined by the `D` flag of the code segment, and the address size
+    ///   override prefix selects the other one of 16 and 32 bits.
+    /// - in long mode, the default address size is 64 bits, and the address size override prefix selects 32 bits. the `W`
+    ///   bit of the rex prefix only affects the operand size, and 16 bit addressing can't be encoded.
+    fn resolve_address_size(&self, prefixes: &InsnPrefixes) -> OperandSize {
+        match self.cpu_mode {
+            X86CpuMode::RealMode => {
+                if prefixes.legacy.has_address_size_override() {
+                    OperandSize::B4
+                } else {
+                    OperandSize::B2
+                }
+            }
+            X86CpuMode::ProtectedMode => match self.code_segment_default_operand_size {
+                X86SegmentDefaultOperandSize::B16 => {
+                    if prefixes.legacy.has_address_size_override() {
                         OperandSize::B4
                     } else {
-                        OperandSize::B8
+                        OperandSize::B2
                     }
                 }
-                _ => {
-                    if prefixes.legacy.contains(LegacyPrefix::AddressSizeOverride) {
+                X86SegmentDefaultOperandSize::B32 => {
+                    if prefixes.legacy.has_address_size_override() {
                         OperandSize::B2
                     } else {
                         OperandSize::B4
                     }
                 }
             },
+            X86CpuMode::LongMode => {
+                if prefixes.legacy.has_address_size_override() {
+                    OperandSize::B4
+                } else {
+                    OperandSize::B8
+                }
+            }
         }
     }
-}
-impl ArchCtx for X86Ctx {
-    fn translate(&self, mut code: &[u8]) -> Translation {
-        let prefixes = extract_prefixes(&mut code);
+    /// translates a single instruction, returning its mnemonic, its translation and its length in bytes.
+    pub fn decode_and_lift(
+        &self,
+        code: &[u8],
+    ) -> Result<(InsnMnemonic, Translation, usize), TranslateError> {
+        self.decode(code)
+            .map(|insn| (insn.mnemonic, insn.translation, insn.length))
+    }
+    /// decodes and translates a single instruction, returning everything known about it.
+    pub fn decode(&self, code: &[u8]) -> Result<DecodedInsn, TranslateError> {
+        self.decode_at(code, 0)
+    }
+    /// decodes and translates a single instruction which is located at the given address. the address is used for
+    /// resolving the targets of relative branches.
+    pub fn decode_at(&self, code: &[u8], addr: u64) -> Result<DecodedInsn, TranslateError> {
+        if code.is_empty() {
+            return Err(TranslateError::UnexpectedEof);
+        }
+        // the code is not empty, so reaching its end means that it ends in the middle of the instruction.
+        self.decode_non_empty_at(code, addr)
+            .map_err(|err| match err {
+                TranslateError::UnexpectedEof => TranslateError::TruncatedInstruction,
+                err => err,
+            })
+    }
+    /// decodes and translates a single instruction from the given non empty code, like [`X86Ctx::decode_at`]. reading past
+    /// the end of the code fails with [`TranslateError::UnexpectedEof`].
+    fn decode_non_empty_at(&self, code: &[u8], addr: u64) -> Result<DecodedInsn, TranslateError> {
+        let mut cur = ByteReader::new(code);
+        let prefixes = extract_prefixes(&mut cur, &self.cpu_mode);
+        if cur.position() >= MAX_INSN_LEN {
+            return Err(TranslateError::TooLong);
+        }
 
         let ctx = PostPrefixesCtx {
             operand_size: self.resolve_operand_size(&prefixes),
             address_size: self.resolve_address_size(&prefixes),
             prefixes,
+            insn_addr: addr,
+            tmps: TmpAllocator::new(),
         };
 
-        if code.len() == 1 && (0x50..=0x50 + Reg::MAX_VALUE as u8).contains(&code[0]) {
-            return self.translate_push_reg(Reg::from_bits(code[0] - 0x50), ctx);
+        let opcode = cur.read_u8()?;
+
+        let (mnemonic, mut translation) = match ONE_BYTE_OPCODE_HANDLERS[opcode as usize] {
+            OneByteOpcodeHandler::Alu => self.translate_alu(&mut cur, opcode, ctx)?,
+            // in long mode, these are rex prefixes, and a rex prefix which isn't directly followed by the opcode is invalid.
+            OneByteOpcodeHandler::IncDecReg if !matches!(self.cpu_mode, X86CpuMode::LongMode) => {
+                self.translate_inc_dec_reg(opcode, ctx)
+            }
+            OneByteOpcodeHandler::PushReg => (
+                InsnMnemonic::Push,
+                self.translate_push_reg(ctx.opcode_reg(opcode - 0x50), ctx),
+            ),
+            OneByteOpcodeHandler::PopReg => (
+                InsnMnemonic::Pop,
+                self.translate_pop_reg(ctx.opcode_reg(opcode - 0x58), ctx),
+            ),
+            OneByteOpcodeHandler::PushImm => (
+                InsnMnemonic::Push,
+                self.translate_push_imm(&mut cur, ctx.operand_size.bytes().min(4), ctx)?,
+            ),
+            OneByteOpcodeHandler::ImulImm => (
+                InsnMnemonic::Imul,
+                self.translate_imul_truncating(
+                    &mut cur,
+                    Some(ctx.operand_size.bytes().min(4)),
+                    ctx,
+                )?,
+            ),
+            OneByteOpcodeHandler::ImulImm8 => (
+                InsnMnemonic::Imul,
+                self.translate_imul_truncating(&mut cur, Some(1), ctx)?,
+            ),
+            OneByteOpcodeHandler::PushImm8 => (
+                InsnMnemonic::Push,
+                self.translate_push_imm(&mut cur, 1, ctx)?,
+            ),
+            OneByteOpcodeHandler::Group1 => self.translate_group1(&mut cur, opcode, ctx)?,
+            OneByteOpcodeHandler::MovRmReg => (
+                InsnMnemonic::Mov,
+                self.translate_mov_rm_reg(&mut cur, opcode, ctx)?,
+            ),
+            OneByteOpcodeHandler::TestRmReg => (
+                InsnMnemonic::Test,
+                self.translate_test_rm_reg(&mut cur, opcode, ctx)?,
+            ),
+            OneByteOpcodeHandler::XchgRmReg => (
+                InsnMnemonic::Xchg,
+                self.translate_xchg_rm_reg(&mut cur, opcode, ctx)?,
+            ),
+            OneByteOpcodeHandler::Lea => (InsnMnemonic::Lea, self.translate_lea(&mut cur, ctx)?),
+            OneByteOpcodeHandler::PopRm => {
+                (InsnMnemonic::Pop, self.translate_pop_rm(&mut cur, ctx)?)
+            }
+            OneByteOpcodeHandler::Nop if ctx.prefixes.legacy.has_rep() => (
+                InsnMnemonic::Pause,
+                self.translate_intrinsic(Intrinsic::Pause),
+            ),
+            // this is `xchg` of the accumulator with itself, which has no effect in any operand size. with the `B` bit of the
+            // rex prefix, it is an `xchg` with `r8`.
+            OneByteOpcodeHandler::Nop if !ctx.prefixes.rex.is_some_and(|rex| rex.b_bit()) => {
+                (InsnMnemonic::Nop, self.translate_nop(cur.position()))
+            }
+            OneByteOpcodeHandler::Nop | OneByteOpcodeHandler::XchgAccumulator => (
+                InsnMnemonic::Xchg,
+                self.translate_xchg_accumulator(ctx.opcode_reg(opcode - 0x90), ctx),
+            ),
+            OneByteOpcodeHandler::MovReg8Imm => (
+                InsnMnemonic::Mov,
+                self.translate_mov_reg_imm(
+                    &mut cur,
+                    ctx.opcode_reg(opcode - 0xb0),
+                    OperandSize::B1,
+                    ctx,
+                )?,
+            ),
+            OneByteOpcodeHandler::MovRegImm => (
+                InsnMnemonic::Mov,
+                self.translate_mov_reg_imm(
+                    &mut cur,
+                    ctx.opcode_reg(opcode - 0xb8),
+                    ctx.operand_size,
+                    ctx,
+                )?,
+            ),
+            OneByteOpcodeHandler::MovRmImm => (
+                InsnMnemonic::Mov,
+                self.translate_mov_rm_imm(&mut cur, opcode, ctx)?,
+            ),
+            OneByteOpcodeHandler::StringOp => self.translate_string_op(&cur, opcode, ctx)?,
+            OneByteOpcodeHandler::Xlat => (InsnMnemonic::Xlat, self.translate_xlat(ctx)),
+            OneByteOpcodeHandler::Cmc => {
+                (InsnMnemonic::Cmc, self.translate_complement_carry_flag())
+            }
+            OneByteOpcodeHandler::Clc => {
+                (InsnMnemonic::Clc, self.translate_set_flag(FLAG_CF, false))
+            }
+            OneByteOpcodeHandler::Stc => {
+                (InsnMnemonic::Stc, self.translate_set_flag(FLAG_CF, true))
+            }
+            OneByteOpcodeHandler::Cld => {
+                (InsnMnemonic::Cld, self.translate_set_flag(FLAG_DF, false))
+            }
+            OneByteOpcodeHandler::Std => {
+                (InsnMnemonic::Std, self.translate_set_flag(FLAG_DF, true))
+            }
+            OneByteOpcodeHandler::JccRel8 => (
+                InsnMnemonic::Jcc,
+                self.translate_jcc(
+                    &mut cur,
+                    ConditionCode::from_opcode_low_nibble(opcode),
+                    1,
+                    ctx,
+                )?,
+            ),
+            OneByteOpcodeHandler::Ret => (InsnMnemonic::Ret, self.translate_ret(ctx)),
+            OneByteOpcodeHandler::CallRel => {
+                (InsnMnemonic::Call, self.translate_call_rel(&mut cur, ctx)?)
+            }
+            OneByteOpcodeHandler::JmpRel => (
+                InsnMnemonic::Jmp,
+                self.translate_jmp_rel(&mut cur, self.near_rel_len(&ctx), ctx)?,
+            ),
+            OneByteOpcodeHandler::JmpRel8 => {
+                (InsnMnemonic::Jmp, self.translate_jmp_rel(&mut cur, 1, ctx)?)
+            }
+            OneByteOpcodeHandler::Group2 => self.translate_group2(&mut cur, opcode, ctx)?,
+            OneByteOpcodeHandler::Group3 => self.translate_group3(&mut cur, opcode, ctx)?,
+            OneByteOpcodeHandler::Group4 => self.translate_group4(&mut cur, ctx)?,
+            OneByteOpcodeHandler::Group5 => self.translate_group5(&mut cur, ctx)?,
+            OneByteOpcodeHandler::TwoByteOpcode => self.translate_two_byte_opcode(&mut cur, ctx)?,
+            OneByteOpcodeHandler::IncDecReg | OneByteOpcodeHandler::Unknown => {
+                return Err(TranslateError::UnknownOpcode(opcode))
+            }
+        };
+        let length = cur.position();
+        if length > MAX_INSN_LEN {
+            return Err(TranslateError::TooLong);
+        }
+        if prefixes.legacy.has_lock() {
+            mark_locked_memory_accesses(mnemonic, &mut translation)?;
         }
-        todo!()
+        debug_assert_eq!(translation.validate(), Ok(()));
+        Ok(DecodedInsn {
+            length,
+            mnemonic,
+            prefixes,
+            translation,
+        })
+    }
+    /// translates a single instruction, emitting the resulting ir instructions into the given sink instead of collecting
+    /// them into a [`Translation`]. returns the length of the instruction in bytes.
+    pub fn translate_to_sink(
+        &self,
+        code: &[u8],
+        sink: &mut impl InsnSink,
+    ) -> Result<usize, TranslateError> {
+        let decoded = self.decode(code)?;
+        for insn in decoded.translation.insns {
+            sink.emit(insn);
+        }
+        Ok(decoded.length)
+    }
+    /// translates consecutive instructions from the start of the given code into a single translation, until reaching an
+    /// instruction which ends the block, the end of the code, or an instruction whose translation doesn't fit in the
+    /// [`crate::TRANSLATION_MAX_INSNS`] instructions of a translation. in the last case, that instruction is not translated.
+    #[cfg(feature = "alloc")]
+    pub fn translate_block(&self, code: &[u8]) -> Result<BlockTranslation, TranslateError> {
+        self.translate_block_at(code, 0)
+    }
+    /// translates a block of consecutive instructions like [`X86Ctx::translate_block`], where the block is located at the
+    /// given address.
+    #[cfg(feature = "alloc")]
+    pub fn translate_block_at(
+        &self,
+        code: &[u8],
+        addr: u64,
+    ) -> Result<BlockTranslation, TranslateError> {
+        let block = self.translate_block_with_policy(code, addr, DecodeErrorPolicy::Strict);
+        match block.error {
+            Some(err) => Err(err),
+            None => Ok(block),
+        }
+    }
+    /// translates a block of consecutive instructions which is located at the given address like
+    /// [`X86Ctx::translate_block_at`], handling instructions which can't be decoded according to the given policy instead
+    /// of failing.
+    #[cfg(feature = "alloc")]
+    pub fn translate_block_with_policy(
+        &self,
+        code: &[u8],
+        addr: u64,
+        policy: DecodeErrorPolicy,
+    ) -> BlockTranslation {
+        let mut block = BlockTranslation {
+            translation: Translation::new(),
+            insns: Vec::new(),
+            gaps: Vec::new(),
+            error: None,
+        };
+        let mut offset = 0;
+        while offset < code.len() {
+            let decoded = match self.decode_at(&code[offset..], addr.wrapping_add(offset as u64)) {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    if policy == DecodeErrorPolicy::Strict {
+                        block.error = Some(error);
+                        break;
+                    }
+                    match block.gaps.last_mut() {
+                        Some(gap) if gap.offset + gap.len == offset => gap.len += 1,
+                        _ => block.gaps.push(BlockGap {
+                            offset,
+                            len: 1,
+                            error,
+                        }),
+                    }
+                    offset += 1;
+                    continue;
+                }
+            };
+            if decoded.translation.insns.len() > block.translation.insns.remaining_capacity() {
+                break;
+            }
+            let ends_block = decoded
+                .translation
+                .insns
+                .iter()
+                .any(|insn| insn.opcode.is_block_terminator());
+            let ir_start = block.translation.insns.len();
+            block.translation.insns.extend(decoded.translation.insns);
+            block.insns.push(BlockInsnInfo {
+                offset,
+                bytes_consumed: decoded.length,
+                ir_insns: ir_start..block.translation.insns.len(),
+            });
+            offset += decoded.length;
+            if ends_block {
+                break;
+            }
+        }
+        block
+    }
+    /// lifts the function whose entry point is at the given address, by following the control flow from its entry point, and
+    /// splitting the code into blocks at the targets of branches. the code is assumed to be located at address 0.
+    ///
+    /// calls are assumed to return to the instruction following them, and the called functions are not lifted. control flow
+    /// is not followed through indirect branches, returns, and branches to targets outside of the code.
+    ///
+    /// an instruction which can't be decoded ends its block, and the error is recorded in [`BlockTranslation::error`]. such a
+    /// block has no fallthrough successor, and the rest of the function is still lifted.
+    #[cfg(feature = "alloc")]
+    pub fn lift_function(&self, code: &[u8], entry: u64) -> Function {
+        let in_range = |addr: u64| addr < code.len() as u64;
+        let translate_block = |start: u64, end: usize| {
+            self.translate_block_with_policy(
+                &code[start as usize..end],
+                start,
+                DecodeErrorPolicy::Strict,
+            )
+        };
+
+        // find the start addresses of all reachable blocks.
+        let mut block_starts = BTreeSet::new();
+        let mut worklist = vec![entry];
+        while let Some(start) = worklist.pop() {
+            if !in_range(start) || !block_starts.insert(start) {
+                continue;
+            }
+            let block = translate_block(start, code.len());
+            worklist.extend(block_successors(&block, start));
+        }
+
+        // a block may start in the middle of a previously discovered block, so translate each block only up to the start of
+        // the next one.
+        let mut blocks = BTreeMap::new();
+        for (&start, end) in block_starts.iter().zip(
+            block_starts
+                .iter()
+                .skip(1)
+                .map(|&end| Some(end))
+                .chain([None]),
+        ) {
+            let block = translate_block(start, end.map_or(code.len(), |end| end as usize));
+            let successors = block_successors(&block, start);
+            blocks.insert(start, FunctionBlock { block, successors });
+        }
+        Function { entry, blocks }
+    }
+    /// translates a single instruction which must fit entirely inside of the given code, returning
+    /// [`TranslateError::TruncatedInstruction`] if decoding it requires reading past the end of the code.
+    ///
+    /// this allows safely decoding instructions near the end of a mapped region, by passing only the bytes up to the end of
+    /// the region. the returned [`TranslationResult::bytes_consumed`] tells how much of that budget the instruction used.
+    pub fn decode_one_within(&self, code: &[u8]) -> Result<TranslationResult, TranslateError> {
+        self.translate(code)
+    }
+}
+impl ArchCtx for X86Ctx {
+    fn reg_file(&self) -> &'static RegFile {
+        &REG_FILE
+    }
+
+    fn translate_at(&self, code: &[u8], addr: u64) -> Result<TranslationResult, TranslateError> {
+        self.decode_at(code, addr).map(|insn| TranslationResult {
+            translation: insn.translation,
+            bytes_consumed: insn.length,
+        })
+    }
+}
+
+/// returns the addresses to which control may flow when leaving the given block, which starts at the given address, when
+/// they are known.
+#[cfg(feature = "alloc")]
+fn block_successors(block: &BlockTranslation, start: u64) -> Vec<u64> {
+    let next = start.wrapping_add(block.bytes_consumed() as u64);
+    let const_target = |operand: &Operand| {
+        (operand.addr.space == OperandSpace::Const).then_some(operand.addr.offset)
+    };
+    // control may leave the block in the middle of the translation of its last instruction, for example at the exit
+    // branch of a `rep` prefixed string instruction, so all of the branches are considered, and not just the last one.
+    let mut successors = Vec::new();
+    for insn in &block.translation.insns {
+        let target = match insn.opcode {
+            Opcode::Branch => const_target(&insn.operands[1]),
+            Opcode::Jump => const_target(&insn.operands[0]),
+            _ => None,
+        };
+        successors.extend(target.filter(|target| !successors.contains(target)));
+    }
+    // control can't flow past an instruction which couldn't be decoded.
+    let falls_through = block.error.is_none()
+        && match block.translation.insns.last().map(|insn| insn.opcode) {
+            Some(Opcode::Jump | Opcode::Return) => false,
+            Some(Opcode::Intrinsic(intrinsic)) => {
+                intrinsic == Intrinsic::Syscall || !intrinsic.affects_control_flow()
+            }
+            _ => true,
+        };
+    if falls_through && !successors.contains(&next) {
+        successors.push(next);
     }
+    successors
 }
@@ -0,0 +1,94 @@
+#![cfg(feature = "alloc")]
+
+use pis::{
+    aarch64::*,
+    interp::{Flow, Machine},
+    *,
+};
+
+/// lifts the given instruction at address 0x1000, and executes it on the given machine.
+fn run(machine: &mut Machine, insn: u32) -> Flow {
+    let result = Aarch64Ctx
+        .translate_at(&insn.to_le_bytes(), 0x1000)
+        .unwrap();
+    assert_eq!(result.bytes_consumed, INSN_LEN);
+    machine.step(&result.translation).unwrap()
+}
+
+#[test]
+fn add_immediate() {
+    let mut machine = Machine::new(0);
+    machine.write(&X1, 41).unwrap();
+    // add x0, x1, #1
+    run(&mut machine, 0x91000420);
+    assert_eq!(machine.read(&X0), Ok(42));
+
+    machine.write(&SP, 0x80).unwrap();
+    // add sp, sp, #16
+    run(&mut machine, 0x910043ff);
+    assert_eq!(machine.read(&SP), Ok(0x90));
+    // add x4, sp, #1, lsl #12
+    run(&mut machine, 0x914007e4);
+    assert_eq!(machine.read(&X4), Ok(0x1090));
+}
+
+#[test]
+fn loads_and_stores() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&X1, 0x40).unwrap();
+    machine.ram[0x40..0x48].copy_from_slice(&0x1122334455667788u64.to_le_bytes());
+    // ldr x0, [x1]
+    run(&mut machine, 0xf9400020);
+    assert_eq!(machine.read(&X0), Ok(0x1122334455667788));
+
+    // 32 bit loads zero the upper half of the destination.
+    machine.write(&X2, u64::MAX).unwrap();
+    // ldr w2, [x1]
+    run(&mut machine, 0xb9400022);
+    assert_eq!(machine.read(&X2), Ok(0x55667788));
+
+    // str x0, [x1, #8]
+    run(&mut machine, 0xf9000420);
+    assert_eq!(machine.ram[0x48..0x50], 0x1122334455667788u64.to_le_bytes());
+}
+
+#[test]
+fn register_moves_and_the_zero_register() {
+    let mut machine = Machine::new(0);
+    machine.write(&X2, 0x55667788).unwrap();
+    // mov x3, x2
+    run(&mut machine, 0xaa0203e3);
+    assert_eq!(machine.read(&X3), Ok(0x55667788));
+    // mov x3, xzr
+    run(&mut machine, 0xaa1f03e3);
+    assert_eq!(machine.read(&X3), Ok(0));
+}
+
+#[test]
+fn branches_are_relative_to_the_insn() {
+    let mut machine = Machine::new(0);
+    // b .-4
+    assert_eq!(run(&mut machine, 0x17ffffff), Flow::Jump(0x1000 - 4));
+    // b .+0x40
+    assert_eq!(run(&mut machine, 0x14000010), Flow::Jump(0x1040));
+}
+
+#[test]
+fn invalid_encodings_are_errors() {
+    assert!(Aarch64Ctx.translate(&[0x00, 0x00, 0x00, 0x00]).is_err());
+    assert_eq!(
+        Aarch64Ctx.translate(&[0x20, 0x04]),
+        Err(TranslateError::TruncatedInstruction)
+    );
+}
+
+#[test]
+fn reg_file_describes_the_register_layout() {
+    let reg_file = Aarch64Ctx.reg_file();
+    assert_eq!(reg_file.reg_by_name("sp").unwrap().operand(), SP);
+    assert_eq!(
+        reg_file.reg_by_name("w2").unwrap().operand(),
+        Operand::reg(2 * XREG_STEP, OperandSize::B4)
+    );
+    assert_eq!(reg_file.reg_at(0).unwrap().name, "x0");
+}
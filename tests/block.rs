@@ -0,0 +1,241 @@
+#![cfg(feature = "alloc")]
+
+use pis::{x86::*, *};
+
+fn long_mode() -> X86Ctx {
+    X86Ctx {
+        cpu_mode: X86CpuMode::LongMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    }
+}
+
+#[test]
+fn system_call_ends_block() {
+    // push rbp; add eax, 0x44332211; syscall; push rax
+    let code = [0x55, 0x05, 0x11, 0x22, 0x33, 0x44, 0x0f, 0x05, 0x50];
+    let block = long_mode().translate_block(&code).unwrap();
+    assert_eq!(block.insns.len(), 3);
+    assert_eq!(block.bytes_consumed(), 8);
+    assert_eq!(
+        block.translation.insns.last().unwrap().opcode,
+        Opcode::Intrinsic(Intrinsic::Syscall)
+    );
+}
+
+#[test]
+fn block_reg_summary_tracks_reads_before_writes() {
+    // mov eax, 1; add ecx, eax; add edx, ebx; mov ebx, 2
+    let code = [
+        0xb8, 0x01, 0x00, 0x00, 0x00, 0x01, 0xc1, 0x01, 0xda, 0xbb, 0x02, 0x00, 0x00, 0x00,
+    ];
+    let block = long_mode().translate_block(&code).unwrap();
+    let summary = block.translation.block_reg_summary();
+    let bytes_of = |reg: Reg, size: OperandSize| {
+        let offset = reg.operand(size).addr.offset;
+        offset..offset + size.bytes() as u64
+    };
+
+    // eax is written before it is read by the add.
+    for byte in bytes_of(Reg::Rax, OperandSize::B8) {
+        assert!(!summary.read_before_write.contains(&byte));
+        assert!(summary.written.contains(&byte));
+    }
+    // ecx and edx are read before being written, and ebx is read before the mov writes it.
+    for reg in [Reg::Rcx, Reg::Rdx, Reg::Rbx] {
+        for byte in bytes_of(reg, OperandSize::B4) {
+            assert!(summary.read_before_write.contains(&byte));
+            assert!(summary.written.contains(&byte));
+        }
+    }
+    // the upper half of rbx is zeroed by the 32 bit write, but never read.
+    for byte in bytes_of(Reg::Rbx, OperandSize::B8).skip(4) {
+        assert!(!summary.read_before_write.contains(&byte));
+        assert!(summary.written.contains(&byte));
+    }
+    // rsp is not accessed at all.
+    for byte in bytes_of(Reg::RspAh, OperandSize::B8) {
+        assert!(!summary.read_before_write.contains(&byte));
+        assert!(!summary.written.contains(&byte));
+    }
+}
+
+#[test]
+fn undecodable_bytes_stop_strict_blocks_and_are_skipped_in_best_effort_blocks() {
+    // push rax; (invalid in long mode); (invalid in long mode); push rcx; ret
+    let code = [0x50, 0x06, 0x06, 0x51, 0xc3];
+    let ctx = long_mode();
+
+    let block = ctx.translate_block_with_policy(&code, 0, DecodeErrorPolicy::Strict);
+    assert_eq!(block.insns.len(), 1);
+    assert_eq!(block.bytes_consumed(), 1);
+    assert!(block.gaps.is_empty());
+    assert_eq!(block.error, Some(TranslateError::UnknownOpcode(0x06)));
+    assert_eq!(
+        ctx.translate_block(&code),
+        Err(TranslateError::UnknownOpcode(0x06))
+    );
+
+    let block = ctx.translate_block_with_policy(&code, 0, DecodeErrorPolicy::BestEffort);
+    let offsets: Vec<usize> = block.insns.iter().map(|insn| insn.offset).collect();
+    assert_eq!(offsets, [0, 3, 4]);
+    assert_eq!(
+        block.gaps,
+        [BlockGap {
+            offset: 1,
+            len: 2,
+            error: TranslateError::UnknownOpcode(0x06),
+        }]
+    );
+    assert_eq!(block.bytes_consumed(), code.len());
+    assert_eq!(block.error, None);
+}
+
+#[test]
+fn block_of_function_prologue() {
+    // push rbp; mov rbp, rsp
+    let block = long_mode()
+        .translate_block(&[0x55, 0x48, 0x89, 0xe5])
+        .unwrap();
+    let mut expected = TranslationBuilder::new();
+    expected.sub(RSP, Operand::constant(8, OperandSize::B8));
+    expected.store(RSP, RBP);
+    expected.mov(RBP, RSP);
+    assert_eq!(block.translation, expected.build());
+    assert_eq!(block.bytes_consumed(), 4);
+    assert_eq!(
+        block.insns,
+        [
+            BlockInsnInfo {
+                offset: 0,
+                bytes_consumed: 1,
+                ir_insns: 0..2,
+            },
+            BlockInsnInfo {
+                offset: 1,
+                bytes_consumed: 3,
+                ir_insns: 2..3,
+            },
+        ]
+    );
+}
+
+#[test]
+fn block_ends_at_terminator_or_end_of_code() {
+    // push rbp; ret; push rbp
+    let block = long_mode().translate_block(&[0x55, 0xc3, 0x55]).unwrap();
+    assert_eq!(block.insns.len(), 2);
+    assert_eq!(block.bytes_consumed(), 2);
+
+    let block = long_mode().translate_block(&[]).unwrap();
+    assert!(block.insns.is_empty());
+    assert_eq!(block.translation, Translation::new());
+}
+
+/// returns the start address and the successors of each block of the given function.
+fn cfg_of(function: &Function) -> Vec<(u64, Vec<u64>)> {
+    function
+        .blocks
+        .iter()
+        .map(|(addr, block)| (*addr, block.successors.clone()))
+        .collect()
+}
+
+#[test]
+fn lift_function_splits_blocks_at_branch_targets() {
+    let ctx = long_mode();
+    // 0: add eax, ecx
+    // 2: jz 7
+    // 4: add eax, eax
+    // 6: nop
+    // 7: ret
+    let function = ctx.lift_function(&[0x01, 0xc8, 0x74, 0x03, 0x01, 0xc0, 0x90, 0xc3], 0);
+    assert_eq!(function.entry, 0);
+    assert_eq!(
+        cfg_of(&function),
+        [(0, vec![7, 4]), (4, vec![7]), (7, vec![])]
+    );
+    assert_eq!(function.blocks[&0].block.bytes_consumed(), 4);
+    assert_eq!(function.blocks[&4].block.bytes_consumed(), 3);
+    assert_eq!(function.blocks[&7].block.bytes_consumed(), 1);
+}
+
+#[test]
+fn lift_function_handles_loops_and_external_targets() {
+    let ctx = long_mode();
+    // 0: add eax, ecx
+    // 2: jmp 0
+    let function = ctx.lift_function(&[0x01, 0xc8, 0xeb, 0xfc], 0);
+    assert_eq!(cfg_of(&function), [(0, vec![0])]);
+
+    // a jump into the middle of a block splits it.
+    // 0: nop
+    // 1: nop
+    // 2: jmp 1
+    let function = ctx.lift_function(&[0x90, 0x90, 0xeb, 0xfd], 0);
+    assert_eq!(cfg_of(&function), [(0, vec![1]), (1, vec![1])]);
+
+    // targets outside of the code have no blocks.
+    // 0: call 0x10005
+    // 5: jmp 0x1000a
+    let function = ctx.lift_function(
+        &[0xe8, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x00, 0x00, 0x01, 0x00],
+        0,
+    );
+    assert_eq!(cfg_of(&function), [(0, vec![5]), (5, vec![0x1000a])]);
+}
+
+#[test]
+fn lift_function_keeps_blocks_which_fail_to_decode() {
+    let ctx = long_mode();
+    // 0: jz 3
+    // 2: ret
+    // 3: (invalid in long mode)
+    let function = ctx.lift_function(&[0x74, 0x01, 0xc3, 0x06], 0);
+    assert_eq!(
+        cfg_of(&function),
+        [(0, vec![3, 2]), (2, vec![]), (3, vec![])]
+    );
+    let invalid = &function.blocks[&3].block;
+    assert!(invalid.insns.is_empty());
+    assert_eq!(invalid.error, Some(TranslateError::UnknownOpcode(0x06)));
+    assert_eq!(function.blocks[&0].block.error, None);
+
+    // an undecodable instruction in the middle of a block ends it without a fallthrough.
+    // 0: nop
+    // 1: (invalid in long mode)
+    // 2: ret
+    let function = ctx.lift_function(&[0x90, 0x06, 0xc3], 0);
+    assert_eq!(cfg_of(&function), [(0, vec![])]);
+    assert_eq!(function.blocks[&0].block.bytes_consumed(), 1);
+    assert_eq!(
+        function.blocks[&0].block.error,
+        Some(TranslateError::UnknownOpcode(0x06))
+    );
+}
+
+#[test]
+fn block_translation_advances_over_nop_padding() {
+    // nop word cs:[rax+rax*1+0x0]; nop; ret
+    let block = long_mode()
+        .translate_block(&[
+            0x66, 0x2e, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00, 0x90, 0xc3, 0xcc,
+        ])
+        .unwrap();
+    assert_eq!(block.bytes_consumed(), 12);
+    assert_eq!(
+        block
+            .insns
+            .iter()
+            .map(|insn| insn.offset)
+            .collect::<Vec<_>>(),
+        [0, 10, 11]
+    );
+}
+
+#[test]
+fn rep_prefixed_insn_loops_back_to_itself() {
+    // rep movsb; nop; ret
+    let function = long_mode().lift_function(&[0xf3, 0xa4, 0x90, 0xc3], 0);
+    assert_eq!(cfg_of(&function), [(0, vec![2, 0]), (2, vec![])]);
+}
@@ -0,0 +1,151 @@
+#![cfg(feature = "alloc")]
+
+use pis::{
+    encoding::{DecodeError, ENCODED_INSN_LEN},
+    *,
+};
+
+/// a small deterministic pseudo random generator, so that failures are reproducible.
+struct XorShift(u64);
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn random_intrinsic(rng: &mut XorShift) -> Intrinsic {
+    let index = rng.below(16) as u8;
+    match rng.below(11) {
+        0 => Intrinsic::Syscall,
+        1 => Intrinsic::Sysret,
+        2 => Intrinsic::Sysenter,
+        3 => Intrinsic::Sysexit,
+        4 => Intrinsic::Pause,
+        5 => Intrinsic::Endbr64,
+        6 => Intrinsic::Endbr32,
+        7 => Intrinsic::WriteControlReg(index),
+        8 => Intrinsic::ReadControlReg(index),
+        9 => Intrinsic::WriteDebugReg(index),
+        _ => Intrinsic::ReadDebugReg(index),
+    }
+}
+
+fn random_opcode(rng: &mut XorShift) -> Opcode {
+    let conds = [Cond::Zero, Cond::Negative, Cond::EvenParity];
+    match rng.below(28) {
+        0 => Opcode::Move,
+        1 => Opcode::ZeroExtend,
+        2 => Opcode::SignExtend,
+        3 => Opcode::Add,
+        4 => Opcode::Sub,
+        5 => Opcode::And,
+        6 => Opcode::Or,
+        7 => Opcode::Xor,
+        8 => Opcode::Shl,
+        9 => Opcode::Shr,
+        10 => Opcode::Sar,
+        11 => Opcode::Rol,
+        12 => Opcode::Ror,
+        13 => Opcode::UMul,
+        14 => Opcode::SMul,
+        15 => Opcode::UDiv,
+        16 => Opcode::SDiv,
+        17 => Opcode::Not,
+        18 => Opcode::Neg,
+        19 => Opcode::Store,
+        20 => Opcode::Load,
+        21 => Opcode::SetCond(conds[rng.below(3) as usize]),
+        22 => Opcode::Branch,
+        23 => Opcode::Jump,
+        24 => Opcode::Call,
+        25 => Opcode::Return,
+        26 => Opcode::Intrinsic(random_intrinsic(rng)),
+        _ => Opcode::Nop,
+    }
+}
+
+fn random_operand(rng: &mut XorShift) -> Operand {
+    let sizes = [
+        OperandSize::B1,
+        OperandSize::B2,
+        OperandSize::B4,
+        OperandSize::B8,
+        OperandSize::B16,
+    ];
+    let spaces = [
+        OperandSpace::Ram,
+        OperandSpace::Const,
+        OperandSpace::Regs,
+        OperandSpace::Tmp,
+    ];
+    Operand {
+        addr: OperandAddr {
+            space: spaces[rng.below(4) as usize],
+            offset: rng.next(),
+        },
+        size: sizes[rng.below(5) as usize],
+    }
+}
+
+fn random_translation(rng: &mut XorShift) -> Translation {
+    let mut translation = Translation::new();
+    for _ in 0..=rng.below(TRANSLATION_MAX_INSNS as u64) {
+        let mut insn = Insn::new(random_opcode(rng), random_operand(rng), random_operand(rng));
+        insn.is_atomic = rng.below(2) == 1;
+        translation.insns.push(insn);
+    }
+    translation
+}
+
+#[test]
+fn decode_of_encode_is_identity() {
+    let mut rng = XorShift(0x2545f4914f6cdd1d);
+    for _ in 0..200 {
+        let translation = random_translation(&mut rng);
+        let encoded = translation.encode();
+        assert_eq!(encoded.len(), translation.insns.len() * ENCODED_INSN_LEN);
+        assert_eq!(Translation::decode(&encoded), Ok(translation));
+    }
+    assert_eq!(Translation::decode(&[]), Ok(Translation::new()));
+}
+
+#[test]
+fn decode_of_invalid_encodings_fails() {
+    let mut translation = Translation::new();
+    translation.insns.push(Insn::new(
+        Opcode::Add,
+        Operand::reg(0, OperandSize::B8),
+        Operand::constant(1, OperandSize::B8),
+    ));
+    let encoded = translation.encode();
+
+    assert_eq!(
+        Translation::decode(&encoded[..ENCODED_INSN_LEN - 1]),
+        Err(DecodeError::InvalidLength)
+    );
+    assert_eq!(
+        Translation::decode(&encoded.repeat(TRANSLATION_MAX_INSNS + 1)),
+        Err(DecodeError::TooManyInsns)
+    );
+
+    let mut invalid_flags = encoded.clone();
+    invalid_flags[ENCODED_INSN_LEN - 1] = 2;
+    assert_eq!(
+        Translation::decode(&invalid_flags),
+        Err(DecodeError::InvalidFlags(2))
+    );
+
+    // the size byte of the first operand.
+    let mut invalid_size = encoded;
+    invalid_size[3 + 9] = 3;
+    assert_eq!(
+        Translation::decode(&invalid_size),
+        Err(DecodeError::InvalidOperandSize(3))
+    );
+}
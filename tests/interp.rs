@@ -0,0 +1,1356 @@
+#![cfg(feature = "alloc")]
+
+use pis::{
+    interp::{Flow, InterpError, Machine, MemoryBackend, VecMemory},
+    x86::*,
+    *,
+};
+use strum::IntoEnumIterator;
+
+fn long_mode() -> X86Ctx {
+    X86Ctx {
+        cpu_mode: X86CpuMode::LongMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    }
+}
+
+/// lifts the single instruction in the given code, and executes it on the given machine.
+fn run<M: MemoryBackend>(machine: &mut Machine<M>, code: &[u8]) {
+    let result = long_mode().translate(code).unwrap();
+    assert_eq!(result.bytes_consumed, code.len());
+    assert_eq!(machine.step(&result.translation), Ok(Flow::Next));
+}
+
+#[test]
+fn write_operand_of_sub_register_leaves_rest_intact() {
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, 0x1122334455667788).unwrap();
+    machine.write_operand(&AL, 0x1ff).unwrap();
+    assert_eq!(machine.read(&RAX).unwrap(), 0x11223344556677ff);
+    assert_eq!(machine.read_operand(&AL).unwrap(), 0xff);
+    machine
+        .write_operand(&Reg::Rax.operand(OperandSize::B4), 0xaabbccdd)
+        .unwrap();
+    assert_eq!(machine.read(&RAX).unwrap(), 0x11223344aabbccdd);
+}
+
+#[test]
+fn lifted_32_bit_register_writes_zero_the_upper_half() {
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, 0x1122334455667788).unwrap();
+    machine.write(&RCX, 0xaabbccdd).unwrap();
+    // mov eax, ecx
+    run(&mut machine, &[0x89, 0xc8]);
+    assert_eq!(machine.read(&RAX).unwrap(), 0xaabbccdd);
+
+    // mov al, 0xff
+    machine.write(&RAX, 0x1122334455667788).unwrap();
+    run(&mut machine, &[0xb0, 0xff]);
+    assert_eq!(machine.read(&RAX).unwrap(), 0x11223344556677ff);
+}
+
+#[test]
+fn carry_flag_instructions() {
+    let mut machine = Machine::new(0);
+    // stc
+    run(&mut machine, &[0xf9]);
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // cmc
+    run(&mut machine, &[0xf5]);
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    run(&mut machine, &[0xf5]);
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // clc
+    run(&mut machine, &[0xf8]);
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    run(&mut machine, &[0xf8]);
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+}
+
+#[test]
+fn add_wraps_at_operand_size_with_carry_only_in_flags() {
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, 0x1180).unwrap();
+    // add al, al
+    run(&mut machine, &[0x00, 0xc0]);
+    assert_eq!(machine.read(&RAX), Ok(0x1100));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+
+    // the ir add itself wraps without touching the neighbouring bytes.
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, 0x1180).unwrap();
+    let mut translation = Translation::new();
+    translation.insns.push(Insn::new(Opcode::Add, AL, AL));
+    assert_eq!(machine.step(&translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(0x1100));
+}
+
+#[test]
+fn rip_relative_load_from_memory_mapped_at_a_base() {
+    // mov rax, [rip+0x10], at 0x401000
+    let insn = long_mode()
+        .decode_at(&[0x48, 0x8b, 0x05, 0x10, 0x00, 0x00, 0x00], 0x401000)
+        .unwrap();
+    let mut machine = Machine::with_memory(VecMemory::with_base(0x401000, 0x100));
+    machine.ram[0x17..0x1f].copy_from_slice(&0x1122334455667788u64.to_le_bytes());
+    assert_eq!(machine.step(&insn.translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(0x1122334455667788));
+
+    assert_eq!(
+        machine.read(&Operand::ram(0x400fff, OperandSize::B1)),
+        Err(InterpError::RamOutOfBounds(0x400fff))
+    );
+    assert_eq!(
+        machine.read(&Operand::ram(0x4010ff, OperandSize::B1)),
+        Ok(0)
+    );
+    assert_eq!(
+        machine.read(&Operand::ram(0x4010ff, OperandSize::B2)),
+        Err(InterpError::RamOutOfBounds(0x4010ff))
+    );
+}
+
+#[test]
+fn xlat_looks_up_al_in_table() {
+    let mut machine = Machine::new(0x200);
+    for i in 0..0x100 {
+        machine.ram[0x100 + i] = !(i as u8);
+    }
+    machine.write(&RBX, 0x100).unwrap();
+    machine.write(&RAX, 0x1234_5605).unwrap();
+    // xlat
+    run(&mut machine, &[0xd7]);
+    assert_eq!(machine.read(&RAX), Ok(0x1234_56fa));
+
+    // with a 32 bit address size, only ebx is used as the table base.
+    machine.write(&RBX, 0xffff_ffff_0000_0100).unwrap();
+    // xlat
+    run(&mut machine, &[0x67, 0xd7]);
+    assert_eq!(machine.read(&RAX), Ok(0x1234_5605));
+}
+
+#[test]
+fn add_sets_overflow_at_signed_boundary() {
+    for (value, addend, overflow) in [(0x7f, 1, 1), (0x7f, 0, 0), (0x80, 0xff, 1), (0xff, 1, 0)] {
+        let mut machine = Machine::new(0);
+        machine.write(&RAX, value).unwrap();
+        // add al, imm8
+        run(&mut machine, &[0x04, addend]);
+        assert_eq!(machine.read(&FLAG_OF), Ok(overflow));
+    }
+}
+
+/// the kinds of 8 bit arithmetic instructions checked against a reference model of their results and flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArithKind {
+    Add,
+    Adc,
+    Sub,
+    Sbb,
+    Cmp,
+    Inc,
+    Dec,
+}
+
+/// returns the expected result, carry and overflow of an 8 bit arithmetic instruction.
+fn reference_arith(kind: ArithKind, a: u8, b: u8, carry_in: bool) -> (u8, bool, bool) {
+    let carry = carry_in as u8;
+    let (sa, sb) = (a as i8 as i16, b as i8 as i16);
+    let is_signed_overflow = |result: i16| !(-128..128).contains(&result);
+    match kind {
+        ArithKind::Add => (
+            a.wrapping_add(b),
+            a.checked_add(b).is_none(),
+            is_signed_overflow(sa + sb),
+        ),
+        ArithKind::Adc => (
+            a.wrapping_add(b).wrapping_add(carry),
+            a as u16 + b as u16 + carry as u16 > 0xff,
+            is_signed_overflow(sa + sb + carry as i16),
+        ),
+        ArithKind::Sub | ArithKind::Cmp => (a.wrapping_sub(b), a < b, is_signed_overflow(sa - sb)),
+        ArithKind::Sbb => (
+            a.wrapping_sub(b).wrapping_sub(carry),
+            (a as u16) < b as u16 + carry as u16,
+            is_signed_overflow(sa - sb - carry as i16),
+        ),
+        // inc and dec preserve the carry flag.
+        ArithKind::Inc => (a.wrapping_add(1), carry_in, is_signed_overflow(sa + 1)),
+        ArithKind::Dec => (a.wrapping_sub(1), carry_in, is_signed_overflow(sa - 1)),
+    }
+}
+
+#[test]
+fn arithmetic_flags_match_reference_model() {
+    let insns: [(&[u8], ArithKind); 7] = [
+        // add al, cl
+        (&[0x00, 0xc8], ArithKind::Add),
+        // adc al, cl
+        (&[0x10, 0xc8], ArithKind::Adc),
+        // sub al, cl
+        (&[0x28, 0xc8], ArithKind::Sub),
+        // sbb al, cl
+        (&[0x18, 0xc8], ArithKind::Sbb),
+        // cmp al, cl
+        (&[0x38, 0xc8], ArithKind::Cmp),
+        // inc al
+        (&[0xfe, 0xc0], ArithKind::Inc),
+        // dec al
+        (&[0xfe, 0xc8], ArithKind::Dec),
+    ];
+    let interesting = [0x00, 0x01, 0x7e, 0x7f, 0x80, 0x81, 0xfe, 0xff];
+    let values = || (0..=0xffu8).step_by(7).chain(interesting);
+    for (code, kind) in insns {
+        let translation = long_mode().translate(code).unwrap().translation;
+        for a in values() {
+            for b in values() {
+                for carry_in in [false, true] {
+                    let mut machine = Machine::new(0);
+                    machine.write(&RAX, a as u64).unwrap();
+                    machine.write(&RCX, b as u64).unwrap();
+                    machine.write(&FLAG_CF, carry_in as u64).unwrap();
+                    assert_eq!(machine.step(&translation), Ok(Flow::Next));
+
+                    let (result, carry, overflow) = reference_arith(kind, a, b, carry_in);
+                    let expected_al = if kind == ArithKind::Cmp { a } else { result };
+                    let case = (kind, a, b, carry_in);
+                    assert_eq!(machine.read(&AL), Ok(expected_al as u64), "{:?}", case);
+                    assert_eq!(machine.read(&FLAG_CF), Ok(carry as u64), "{:?}", case);
+                    assert_eq!(machine.read(&FLAG_OF), Ok(overflow as u64), "{:?}", case);
+                    assert_eq!(
+                        machine.read(&FLAG_ZF),
+                        Ok((result == 0) as u64),
+                        "{:?}",
+                        case
+                    );
+                    assert_eq!(
+                        machine.read(&FLAG_SF),
+                        Ok((result >> 7) as u64),
+                        "{:?}",
+                        case
+                    );
+                    assert_eq!(
+                        machine.read(&FLAG_PF),
+                        Ok(result.count_ones().is_multiple_of(2) as u64),
+                        "{:?}",
+                        case
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn cmp_only_writes_flags() {
+    let mut machine = Machine::new(0x10);
+    machine.write(&RAX, u64::MAX).unwrap();
+    // cmp eax, ecx
+    run(&mut machine, &[0x39, 0xc8]);
+    assert_eq!(machine.read(&RAX), Ok(u64::MAX));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(0));
+
+    // cmp qword [rax], 5
+    let translation = long_mode()
+        .translate(&[0x48, 0x83, 0x38, 0x05])
+        .unwrap()
+        .translation;
+    assert!(!translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Store));
+}
+
+#[test]
+fn logic_operations_clear_carry_and_overflow() {
+    let mut machine = Machine::new(0);
+    machine.write(&FLAG_CF, 1).unwrap();
+    machine.write(&FLAG_OF, 1).unwrap();
+    machine.write(&RAX, 0x8000_0000).unwrap();
+    machine.write(&RCX, 0x8000_0000).unwrap();
+    // and eax, ecx
+    run(&mut machine, &[0x21, 0xc8]);
+    assert_eq!(
+        [FLAG_CF, FLAG_OF, FLAG_ZF, FLAG_SF].map(|flag| machine.read(&flag).unwrap()),
+        [0, 0, 0, 1]
+    );
+}
+
+/// executes the given `lea` instruction with the given values of rax and rcx, and returns the computed address.
+fn lea(code: &[u8], rax: u64, rcx: u64) -> u64 {
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, rax).unwrap();
+    machine.write(&RCX, rcx).unwrap();
+    run(&mut machine, code);
+    machine.read(&RDX).unwrap()
+}
+
+#[test]
+fn sib_addresses() {
+    // lea rdx, [rax+rcx*4+0x10]
+    assert_eq!(lea(&[0x48, 0x8d, 0x54, 0x88, 0x10], 0x1000, 3), 0x101c);
+    // lea rdx, [rax+rcx*8-0x10]
+    assert_eq!(lea(&[0x48, 0x8d, 0x54, 0xc8, 0xf0], 0x1000, 3), 0x1008);
+    // lea rdx, [rax+rcx*1+0x12345678]
+    assert_eq!(
+        lea(&[0x48, 0x8d, 0x94, 0x08, 0x78, 0x56, 0x34, 0x12], 0x1000, 3),
+        0x1234667b
+    );
+    // a base of 5 with a mod of 0 means that there is no base, and a 32 bit displacement instead.
+    // lea rdx, [rcx*4+0x12345678]
+    assert_eq!(
+        lea(&[0x48, 0x8d, 0x14, 0x8d, 0x78, 0x56, 0x34, 0x12], 0x1000, 3),
+        0x12345684
+    );
+    // an index of 4 means that there is no index.
+    // lea rdx, [rax+0x10]
+    assert_eq!(lea(&[0x48, 0x8d, 0x54, 0x20, 0x10], 0x1000, 3), 0x1010);
+    // lea rdx, [0x12345678]
+    assert_eq!(
+        lea(&[0x48, 0x8d, 0x14, 0x25, 0x78, 0x56, 0x34, 0x12], 0x1000, 3),
+        0x12345678
+    );
+}
+
+#[test]
+fn lea_with_scaled_index() {
+    let mut machine = Machine::new(0);
+    machine.write(&RBX, 0x1000).unwrap();
+    machine.write(&RCX, 3).unwrap();
+    // lea rax, [rbx+rcx*8+0x20]
+    run(&mut machine, &[0x48, 0x8d, 0x44, 0xcb, 0x20]);
+    assert_eq!(machine.read(&RAX).unwrap(), 0x1038);
+    // lea eax, [rbx+rcx*8-0x2000]
+    machine.write(&RAX, u64::MAX).unwrap();
+    run(&mut machine, &[0x8d, 0x84, 0xcb, 0x00, 0xe0, 0xff, 0xff]);
+    assert_eq!(machine.read(&RAX).unwrap(), 0xfffff018);
+}
+
+#[test]
+fn push_writes_below_the_stack_pointer() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RSP, 0x80).unwrap();
+    machine.write(&RAX, 0x1122334455667788).unwrap();
+    // push rax
+    run(&mut machine, &[0x50]);
+    assert_eq!(machine.read(&RSP), Ok(0x78));
+    assert_eq!(machine.ram[0x78..0x80], 0x1122334455667788u64.to_le_bytes());
+    // the rest of the memory is untouched.
+    assert!(machine.ram[..0x78].iter().all(|&byte| byte == 0));
+    assert!(machine.ram[0x80..].iter().all(|&byte| byte == 0));
+
+    // pop rcx
+    run(&mut machine, &[0x59]);
+    assert_eq!(machine.read(&RSP), Ok(0x80));
+    assert_eq!(machine.read(&RCX), Ok(0x1122334455667788));
+
+    // a push to an unmapped stack fails.
+    machine.write(&RSP, 0x200).unwrap();
+    let translation = long_mode().translate(&[0x50]).unwrap().translation;
+    assert_eq!(
+        machine.step(&translation),
+        Err(InterpError::RamOutOfBounds(0x1f8))
+    );
+}
+
+#[test]
+fn fs_relative_load_adds_fs_base() {
+    // mov rax, fs:[0x0]
+    let decoded = long_mode()
+        .decode(&[0x64, 0x48, 0x8b, 0x04, 0x25, 0x00, 0x00, 0x00, 0x00])
+        .unwrap();
+    assert_eq!(
+        decoded.prefixes.legacy.segment_override(),
+        Some(Segment::Fs)
+    );
+    assert!(decoded
+        .translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Add && insn.operands[1] == FS_BASE));
+
+    let mut machine = Machine::new(0x100);
+    machine.write(&FS_BASE, 0x20).unwrap();
+    machine.ram[0x20..0x28].copy_from_slice(&0x1122334455667788u64.to_le_bytes());
+    assert_eq!(machine.step(&decoded.translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(0x1122334455667788));
+
+    // lea only computes the offset, without the segment base.
+    machine.write(&RBX, 0x30).unwrap();
+    // lea rax, fs:[rbx]
+    run(&mut machine, &[0x64, 0x48, 0x8d, 0x03]);
+    assert_eq!(machine.read(&RAX), Ok(0x30));
+}
+
+#[test]
+fn segment_bases_are_added_to_segment_relative_addresses() {
+    // mov rax, gs:[0x28]
+    let translation = long_mode()
+        .translate(&[0x65, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00])
+        .unwrap()
+        .translation;
+    let mut machine = Machine::new(0x100);
+    machine.write(&GS_BASE, 0x40).unwrap();
+    machine.ram[0x68] = 0x42;
+    assert_eq!(machine.step(&translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(0x42));
+
+    // the es segment base is ignored in long mode.
+    // mov rax, es:[rbx]
+    machine.write(&ES_BASE, 0x1000).unwrap();
+    machine.write(&RBX, 0x68).unwrap();
+    machine.write(&RAX, 0).unwrap();
+    run(&mut machine, &[0x26, 0x48, 0x8b, 0x03]);
+    assert_eq!(machine.read(&RAX), Ok(0x42));
+
+    // outside of long mode, the address wraps at the address size after adding the segment base.
+    let protected_mode = X86Ctx {
+        cpu_mode: X86CpuMode::ProtectedMode,
+        ..long_mode()
+    };
+    // mov eax, gs:[ebx]
+    let translation = protected_mode
+        .translate(&[0x65, 0x8b, 0x03])
+        .unwrap()
+        .translation;
+    machine.write(&RBX, 0xffff_fff8).unwrap();
+    machine.write(&GS_BASE, 0x10).unwrap();
+    machine.ram[0x8] = 0x24;
+    assert_eq!(machine.step(&translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(0x24));
+}
+
+#[test]
+fn movzx_and_movsx() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, u64::MAX).unwrap();
+    machine.write(&RBX, 0x1234_80f0).unwrap();
+    // movzx eax, bl
+    run(&mut machine, &[0x0f, 0xb6, 0xc3]);
+    assert_eq!(machine.read(&RAX), Ok(0xf0));
+    // movsx rax, bx
+    run(&mut machine, &[0x48, 0x0f, 0xbf, 0xc3]);
+    assert_eq!(machine.read(&RAX), Ok(0xffff_ffff_ffff_80f0));
+    // movsx eax, bh
+    run(&mut machine, &[0x0f, 0xbe, 0xc7]);
+    assert_eq!(machine.read(&RAX), Ok(0xffff_ff80));
+    // movzx ecx, bh
+    run(&mut machine, &[0x0f, 0xb6, 0xcf]);
+    assert_eq!(machine.read(&RCX), Ok(0x80));
+
+    machine.ram[0x10] = 0xfe;
+    machine.write(&RSI, 0x10).unwrap();
+    // movsx cx, byte [rsi]
+    run(&mut machine, &[0x66, 0x0f, 0xbe, 0x0e]);
+    assert_eq!(machine.read(&RCX), Ok(0xfffe));
+}
+
+#[test]
+fn mov_immediates() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, u64::MAX).unwrap();
+    // mov al, 0x10
+    run(&mut machine, &[0xb0, 0x10]);
+    assert_eq!(machine.read(&RAX), Ok(0xffff_ffff_ffff_ff10));
+    // movabs rax, 0x1122334455667788
+    run(
+        &mut machine,
+        &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+    );
+    assert_eq!(machine.read(&RAX), Ok(0x1122334455667788));
+    // mov ecx, 0xffffffff
+    machine.write(&RCX, u64::MAX).unwrap();
+    run(&mut machine, &[0xb9, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(machine.read(&RCX), Ok(0xffff_ffff));
+    // mov r10w, 0x1234
+    run(&mut machine, &[0x66, 0x41, 0xba, 0x34, 0x12]);
+    assert_eq!(machine.read(&R10), Ok(0x1234));
+    // mov eax, 1
+    run(&mut machine, &[0xc7, 0xc0, 0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(machine.read(&RAX), Ok(1));
+
+    machine.write(&RBX, 0x20).unwrap();
+    // mov qword [rbx], -1
+    run(&mut machine, &[0x48, 0xc7, 0x03, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(machine.ram[0x20..0x28], [0xff; 8]);
+    // mov dword [rbx], 1
+    run(&mut machine, &[0xc7, 0x03, 0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(
+        machine.ram[0x20..0x28],
+        [1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]
+    );
+
+    // the rip relative address is relative to the end of the instruction, including the immediate.
+    // mov byte [rip+0x10], 5
+    let result = long_mode()
+        .translate_at(&[0xc6, 0x05, 0x10, 0x00, 0x00, 0x00, 0x05], 0x40)
+        .unwrap();
+    assert_eq!(result.bytes_consumed, 7);
+    assert_eq!(machine.step(&result.translation), Ok(Flow::Next));
+    assert_eq!(machine.ram[0x57], 5);
+
+    // only the /0 form of 0xc6 and 0xc7 is a mov.
+    assert!(long_mode().translate(&[0xc6, 0xf8, 0x00]).is_err());
+}
+
+#[test]
+fn alu_with_immediates() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, 0x1_0000_fff0).unwrap();
+    // add rax, 0x10
+    run(&mut machine, &[0x48, 0x83, 0xc0, 0x10]);
+    assert_eq!(machine.read(&RAX), Ok(0x1_0001_0000));
+    // the 8 bit immediate is sign extended.
+    // add rax, -1
+    run(&mut machine, &[0x48, 0x83, 0xc0, 0xff]);
+    assert_eq!(machine.read(&RAX), Ok(0x1_0000_ffff));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // and eax, 0x1234
+    run(&mut machine, &[0x81, 0xe0, 0x34, 0x12, 0x00, 0x00]);
+    assert_eq!(machine.read(&RAX), Ok(0x1234));
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    // the 32 bit immediate is sign extended to 64 bits.
+    // or rax, -0x10000
+    run(&mut machine, &[0x48, 0x81, 0xc8, 0x00, 0x00, 0xff, 0xff]);
+    assert_eq!(machine.read(&RAX), Ok(0xffff_ffff_ffff_1234));
+    assert_eq!(machine.read(&FLAG_SF), Ok(1));
+
+    machine.write(&RBX, 0x10).unwrap();
+    machine.ram[0x10] = 0x0f;
+    // sub byte [rbx], 0x10
+    run(&mut machine, &[0x80, 0x2b, 0x10]);
+    assert_eq!(machine.ram[0x10], 0xff);
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // cmp byte [rbx], 0xff
+    run(&mut machine, &[0x80, 0x3b, 0xff]);
+    assert_eq!(machine.ram[0x10], 0xff);
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+}
+
+#[test]
+fn multi_word_add_and_sub_propagate_carry() {
+    // (rdx:rax, rbx:rcx) pairs of 128 bit values.
+    let cases = [
+        (u64::MAX, 0, 1, 0),
+        (u64::MAX, 5, u64::MAX, 7),
+        (3, 4, 5, 6),
+        (1 << 63, 0, 1 << 63, 0),
+        (u64::MAX, u64::MAX, 1, 0),
+    ];
+    for (lo, hi, other_lo, other_hi) in cases {
+        let mut machine = Machine::new(0);
+        machine.write(&RAX, lo).unwrap();
+        machine.write(&RDX, hi).unwrap();
+        machine.write(&RCX, other_lo).unwrap();
+        machine.write(&RBX, other_hi).unwrap();
+        // stc
+        run(&mut machine, &[0xf9]);
+        // add rax, rcx
+        run(&mut machine, &[0x48, 0x01, 0xc8]);
+        // adc rdx, rbx
+        run(&mut machine, &[0x48, 0x11, 0xda]);
+        let value = (hi as u128) << 64 | lo as u128;
+        let other = (other_hi as u128) << 64 | other_lo as u128;
+        let (sum, carry) = value.overflowing_add(other);
+        assert_eq!(machine.read(&RAX), Ok(sum as u64));
+        assert_eq!(machine.read(&RDX), Ok((sum >> 64) as u64));
+        assert_eq!(machine.read(&FLAG_CF), Ok(carry as u64));
+
+        // sub rax, rcx
+        run(&mut machine, &[0x48, 0x29, 0xc8]);
+        // sbb rdx, rbx
+        run(&mut machine, &[0x48, 0x19, 0xda]);
+        assert_eq!(machine.read(&RAX), Ok(lo));
+        assert_eq!(machine.read(&RDX), Ok(hi));
+        assert_eq!(machine.read(&FLAG_CF), Ok(carry as u64));
+    }
+}
+
+#[test]
+fn adc_and_sbb_immediate_forms() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&AL, 0x80).unwrap();
+    machine.write(&FLAG_CF, 1).unwrap();
+    // adc al, al
+    run(&mut machine, &[0x10, 0xc0]);
+    assert_eq!(machine.read(&AL), Ok(1));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // adc al, 0xfe
+    run(&mut machine, &[0x14, 0xfe]);
+    assert_eq!(machine.read(&AL), Ok(0));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    // sbb eax, 1
+    run(&mut machine, &[0x83, 0xd8, 0x01]);
+    assert_eq!(machine.read(&RAX), Ok(0xffff_fffe));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+
+    machine.write(&RBX, 0x10).unwrap();
+    machine.ram[0x10] = 0xff;
+    // adc byte [rbx], 0
+    run(&mut machine, &[0x80, 0x13, 0x00]);
+    assert_eq!(machine.ram[0x10], 0);
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+}
+
+#[test]
+fn xchg_swaps_operands() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, 1).unwrap();
+    machine.write(&RCX, 2).unwrap();
+    // xchg rax, rcx
+    run(&mut machine, &[0x48, 0x91]);
+    assert_eq!(machine.read(&RAX), Ok(2));
+    assert_eq!(machine.read(&RCX), Ok(1));
+
+    machine.write(&RDX, 0x1234).unwrap();
+    // xchg cl, dl
+    run(&mut machine, &[0x86, 0xca]);
+    assert_eq!(machine.read(&RCX), Ok(0x34));
+    assert_eq!(machine.read(&RDX), Ok(0x1201));
+
+    let dword = Operand::ram(0x10, OperandSize::B4);
+    machine.write(&RAX, 0x10).unwrap();
+    machine.write(&RCX, 0xffff_ffff_0000_0005).unwrap();
+    machine.write(&dword, 7).unwrap();
+    // xchg [rax], ecx
+    run(&mut machine, &[0x87, 0x08]);
+    assert_eq!(machine.read(&RCX), Ok(7));
+    assert_eq!(machine.read(&dword), Ok(5));
+}
+
+#[test]
+fn xadd_exchanges_and_adds() {
+    let qword = Operand::ram(0x10, OperandSize::B8);
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, 0x10).unwrap();
+    machine.write(&RBX, 3).unwrap();
+    machine.write(&qword, 5).unwrap();
+    // lock xadd [rax], rbx
+    run(&mut machine, &[0xf0, 0x48, 0x0f, 0xc1, 0x18]);
+    assert_eq!(machine.read(&RBX), Ok(5));
+    assert_eq!(machine.read(&qword), Ok(8));
+
+    // when both operands are the same register, the sum is written last.
+    machine.write(&RAX, 4).unwrap();
+    // xadd rax, rax
+    run(&mut machine, &[0x48, 0x0f, 0xc1, 0xc0]);
+    assert_eq!(machine.read(&RAX), Ok(8));
+}
+
+#[test]
+fn inc_dec_and_push_of_group_5() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, u64::MAX).unwrap();
+    machine.write(&FLAG_CF, 1).unwrap();
+    // inc rax
+    run(&mut machine, &[0x48, 0xff, 0xc0]);
+    assert_eq!(machine.read(&RAX), Ok(0));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+    // inc and dec preserve the carry flag.
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+
+    let dword = Operand::ram(0x10, OperandSize::B4);
+    machine.write(&RBX, 0x10).unwrap();
+    machine
+        .write(&Operand::ram(0x10, OperandSize::B8), 0x1_0000_0000)
+        .unwrap();
+    // dec dword [rbx]
+    run(&mut machine, &[0xff, 0x0b]);
+    assert_eq!(machine.read(&dword), Ok(0xffff_ffff));
+    assert_eq!(machine.read(&FLAG_SF), Ok(1));
+    // the upper dword is untouched.
+    assert_eq!(machine.ram[0x14], 1);
+
+    machine.write(&RSP, 0x80).unwrap();
+    // push qword [rbx]
+    run(&mut machine, &[0xff, 0x33]);
+    assert_eq!(machine.read(&RSP), Ok(0x78));
+    assert_eq!(
+        machine.read(&Operand::ram(0x78, OperandSize::B8)),
+        Ok(0x1_ffff_ffff)
+    );
+}
+
+#[test]
+fn push_and_pop_of_immediates_and_memory() {
+    let stack_qword = |addr| Operand::ram(addr, OperandSize::B8);
+    let mut machine = Machine::new(0x100);
+    machine.write(&RSP, 0x80).unwrap();
+    // push 0x10
+    run(&mut machine, &[0x6a, 0x10]);
+    assert_eq!(machine.read(&stack_qword(0x78)), Ok(0x10));
+    // the immediates are sign extended.
+    // push -0x10
+    run(&mut machine, &[0x6a, 0xf0]);
+    assert_eq!(machine.read(&stack_qword(0x70)), Ok(-0x10i64 as u64));
+    // push -0x80000000
+    run(&mut machine, &[0x68, 0x00, 0x00, 0x00, 0x80]);
+    assert_eq!(machine.read(&stack_qword(0x68)), Ok(0xffff_ffff_8000_0000));
+
+    machine.write(&RAX, 0x10).unwrap();
+    machine.write(&stack_qword(0x10), 0xabcd).unwrap();
+    // push qword [rax]
+    run(&mut machine, &[0xff, 0x30]);
+    assert_eq!(machine.read(&stack_qword(0x60)), Ok(0xabcd));
+
+    machine.write(&RBX, 0x20).unwrap();
+    // pop qword [rbx]
+    run(&mut machine, &[0x8f, 0x03]);
+    assert_eq!(machine.read(&stack_qword(0x20)), Ok(0xabcd));
+    assert_eq!(machine.read(&RSP), Ok(0x68));
+
+    // the address of the destination of a pop is computed after incrementing rsp.
+    // pop qword [rsp]
+    run(&mut machine, &[0x8f, 0x04, 0x24]);
+    assert_eq!(machine.read(&RSP), Ok(0x70));
+    assert_eq!(machine.read(&stack_qword(0x70)), Ok(0xffff_ffff_8000_0000));
+
+    // a push of rsp pushes its value from before the push.
+    // push rsp
+    run(&mut machine, &[0xff, 0xf4]);
+    assert_eq!(machine.read(&stack_qword(0x68)), Ok(0x70));
+    // pop rcx
+    run(&mut machine, &[0x8f, 0xc1]);
+    assert_eq!(machine.read(&RCX), Ok(0x70));
+    assert_eq!(machine.read(&RSP), Ok(0x70));
+}
+
+/// a sparse memory which records all of the accesses made to it.
+#[derive(Default)]
+struct LoggingMemory {
+    bytes: std::collections::BTreeMap<u64, u8>,
+    accesses: std::cell::RefCell<Vec<MemoryAccess>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MemoryAccess {
+    Read { addr: u64, len: usize },
+    Write { addr: u64, len: usize },
+}
+
+impl MemoryBackend for LoggingMemory {
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), InterpError> {
+        self.accesses.borrow_mut().push(MemoryAccess::Read {
+            addr,
+            len: buf.len(),
+        });
+        for (byte_addr, byte) in (addr..).zip(buf.iter_mut()) {
+            *byte = self.bytes.get(&byte_addr).copied().unwrap_or(0);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), InterpError> {
+        self.accesses.borrow_mut().push(MemoryAccess::Write {
+            addr,
+            len: data.len(),
+        });
+        self.bytes.extend((addr..).zip(data.iter().copied()));
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_memory_backend_sees_all_accesses() {
+    let mut machine = Machine::with_memory(LoggingMemory::default());
+    machine.write(&RSP, 0x7fff_0000_0000).unwrap();
+    machine.write(&RAX, 0x55).unwrap();
+    // push rax
+    run(&mut machine, &[0x50]);
+    // pop rcx
+    run(&mut machine, &[0x59]);
+    assert_eq!(machine.read(&RCX), Ok(0x55));
+    assert_eq!(
+        *machine.ram.accesses.borrow(),
+        [
+            MemoryAccess::Write {
+                addr: 0x7fff_0000_0000 - 8,
+                len: 8
+            },
+            MemoryAccess::Read {
+                addr: 0x7fff_0000_0000 - 8,
+                len: 8
+            },
+        ]
+    );
+}
+
+#[test]
+fn vec_memory_rejects_out_of_bounds_accesses() {
+    let mut machine = Machine::new(0x10);
+    assert_eq!(
+        machine.write(&Operand::ram(0xc, OperandSize::B8), 1),
+        Err(InterpError::RamOutOfBounds(0xc))
+    );
+    assert_eq!(
+        machine.read(&Operand::ram(u64::MAX, OperandSize::B8)),
+        Err(InterpError::RamOutOfBounds(u64::MAX))
+    );
+    assert_eq!(
+        machine.write(&Operand::ram(0x8, OperandSize::B8), 1),
+        Ok(())
+    );
+}
+
+#[test]
+fn step_traced_reports_each_insn_before_executing_it() {
+    let mut translation = Translation::new();
+    translation.insns.extend([
+        Insn::new(Opcode::Move, RAX, Operand::constant(5, OperandSize::B8)),
+        Insn::new(Opcode::Add, RAX, Operand::constant(1, OperandSize::B8)),
+        Insn::new(Opcode::Jump, RAX, Operand::zero(OperandSize::B1)),
+        // not reached.
+        Insn::new(
+            Opcode::Nop,
+            Operand::constant(1, OperandSize::B1),
+            Operand::zero(OperandSize::B1),
+        ),
+    ]);
+    let mut machine = Machine::new(0);
+    let mut trace = Vec::new();
+    let flow = machine.step_traced(&translation, |insn, machine| {
+        trace.push((insn.opcode, machine.read(&RAX).unwrap()))
+    });
+    assert_eq!(flow, Ok(Flow::Jump(6)));
+    assert_eq!(
+        trace,
+        [(Opcode::Move, 0), (Opcode::Add, 5), (Opcode::Jump, 6)]
+    );
+}
+
+/// lifts the given string instruction at address 0x1000, and executes it until it leaves the instruction, returning the
+/// number of times that it jumped back to its start.
+fn run_string_op(machine: &mut Machine, code: &[u8]) -> usize {
+    let translation = long_mode().decode_at(code, 0x1000).unwrap().translation;
+    let mut iterations = 0;
+    loop {
+        match machine.step(&translation) {
+            Ok(Flow::Jump(0x1000)) => iterations += 1,
+            Ok(Flow::Jump(addr)) => {
+                assert_eq!(addr, 0x1000 + code.len() as u64);
+                return iterations;
+            }
+            Ok(Flow::Next) => return iterations,
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+}
+
+#[test]
+fn rep_movsb_copies_buffer() {
+    // rep movsb
+    let translation = long_mode()
+        .decode_at(&[0xf3, 0xa4], 0x1000)
+        .unwrap()
+        .translation;
+    assert!(translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Branch));
+
+    let mut machine = Machine::new(0x100);
+    for i in 0..8 {
+        machine.ram[0x10 + i] = i as u8 + 1;
+    }
+    machine.write(&RSI, 0x10).unwrap();
+    machine.write(&RDI, 0x40).unwrap();
+    machine.write(&RCX, 8).unwrap();
+    // each iteration copies a single byte and jumps back to the instruction, which leaves once the count reaches zero.
+    assert_eq!(run_string_op(&mut machine, &[0xf3, 0xa4]), 8);
+    assert_eq!(machine.ram[0x40..0x48], [1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(machine.ram[0x48], 0);
+    assert_eq!(machine.read(&RCX), Ok(0));
+    assert_eq!(machine.read(&RSI), Ok(0x18));
+    assert_eq!(machine.read(&RDI), Ok(0x48));
+
+    // with a zero count nothing is copied.
+    machine.write(&RDI, 0x80).unwrap();
+    assert_eq!(run_string_op(&mut machine, &[0xf3, 0xa4]), 0);
+    assert_eq!(machine.ram[0x80], 0);
+    assert_eq!(machine.read(&RDI), Ok(0x80));
+}
+
+#[test]
+fn other_string_ops() {
+    let mut machine = Machine::new(0x100);
+    machine.ram[0x40..0x48].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+    // rep stosq, backwards
+    machine.write(&FLAG_DF, 1).unwrap();
+    machine.write(&RAX, 0x0101010101010101).unwrap();
+    machine.write(&RCX, 2).unwrap();
+    machine.write(&RDI, 0x88).unwrap();
+    run_string_op(&mut machine, &[0xf3, 0x48, 0xab]);
+    assert_eq!(machine.ram[0x80..0x90], [1; 16]);
+    assert_eq!(machine.read(&RDI), Ok(0x78));
+    machine.write(&FLAG_DF, 0).unwrap();
+
+    // lodsd
+    machine.write(&RSI, 0x40).unwrap();
+    machine.write(&RAX, u64::MAX).unwrap();
+    run_string_op(&mut machine, &[0xad]);
+    assert_eq!(machine.read(&RAX), Ok(0x04030201));
+    assert_eq!(machine.read(&RSI), Ok(0x44));
+
+    // repne scasb, which stops after the matching byte.
+    machine.write(&RDI, 0x40).unwrap();
+    machine.write(&RAX, 5).unwrap();
+    machine.write(&RCX, 100).unwrap();
+    run_string_op(&mut machine, &[0xf2, 0xae]);
+    assert_eq!(machine.read(&RDI), Ok(0x45));
+    assert_eq!(machine.read(&RCX), Ok(95));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+}
+
+#[test]
+fn scas_sets_flags_like_cmp() {
+    let mut machine = Machine::new(0x100);
+    machine.ram[0x40] = 0x90;
+    machine.write(&FLAG_CF, 0).unwrap();
+    machine.write(&RDI, 0x40).unwrap();
+    machine.write(&RAX, 0x10).unwrap();
+    // scasb
+    run_string_op(&mut machine, &[0xae]);
+    // setb cl
+    run(&mut machine, &[0x0f, 0x92, 0xc1]);
+    // 0x10 - 0x90 borrows, and is 0x80, which is negative and has odd parity, and overflows.
+    assert_eq!(machine.read(&RCX), Ok(1));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(0));
+    assert_eq!(machine.read(&FLAG_SF), Ok(1));
+    assert_eq!(machine.read(&FLAG_OF), Ok(1));
+    assert_eq!(machine.read(&FLAG_PF), Ok(0));
+    assert_eq!(machine.read(&RDI), Ok(0x41));
+
+    machine.ram[0x41] = 0x01;
+    machine.write(&RAX, 0x80).unwrap();
+    // scasb
+    run_string_op(&mut machine, &[0xae]);
+    // 0x80 - 0x01 is 0x7f, which overflows from negative to positive and has odd parity.
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    assert_eq!(machine.read(&FLAG_OF), Ok(1));
+    assert_eq!(machine.read(&FLAG_SF), Ok(0));
+    assert_eq!(machine.read(&FLAG_PF), Ok(0));
+    // setb cl
+    run(&mut machine, &[0x0f, 0x92, 0xc1]);
+    assert_eq!(machine.read(&RCX), Ok(0));
+}
+
+#[test]
+fn string_ops_honor_segment_override_and_address_size() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&FS_BASE, 0x10).unwrap();
+    machine.write(&RSI, 0x20).unwrap();
+    machine.write(&RDI, 0x40).unwrap();
+    machine.ram[0x30] = 0xab;
+    // movsb byte [rdi], fs:[rsi]
+    run_string_op(&mut machine, &[0x64, 0xa4]);
+    assert_eq!(machine.ram[0x40], 0xab);
+    // the segment base is only added to the address, not to rsi itself.
+    assert_eq!(machine.read(&RSI), Ok(0x21));
+
+    // with a 32 bit address size, ecx is the counter and esi and edi are the index registers.
+    machine.ram[0x10..0x12].copy_from_slice(&[0xcd, 0xef]);
+    machine.write(&RCX, 0xffff_ffff_0000_0002).unwrap();
+    machine.write(&RSI, 0xffff_ffff_0000_0010).unwrap();
+    machine.write(&RDI, 0x50).unwrap();
+    // rep movsb byte [edi], [esi]
+    run_string_op(&mut machine, &[0x67, 0xf3, 0xa4]);
+    assert_eq!(machine.ram[0x50..0x52], [0xcd, 0xef]);
+    assert_eq!(machine.read(&RCX), Ok(0));
+    assert_eq!(machine.read(&RSI), Ok(0x12));
+}
+
+#[test]
+fn direction_flag_selects_string_op_direction() {
+    let ctx = long_mode();
+    let std_insn = ctx.decode(&[0xfd]).unwrap();
+    assert_eq!(std_insn.mnemonic, InsnMnemonic::Std);
+    assert_eq!(
+        std_insn.translation.insns.as_slice(),
+        [Insn::new(
+            Opcode::Move,
+            FLAG_DF,
+            Operand::constant(1, OperandSize::B1)
+        )]
+    );
+
+    let mut machine = Machine::new(0x100);
+    // std
+    run(&mut machine, &[0xfd]);
+    assert_eq!(machine.read(&FLAG_DF), Ok(1));
+    machine.ram[0x10] = 7;
+    machine.write(&RSI, 0x10).unwrap();
+    machine.write(&RDI, 0x20).unwrap();
+    // movsb
+    run(&mut machine, &[0xa4]);
+    assert_eq!(machine.ram[0x20], 7);
+    assert_eq!(machine.read(&RSI), Ok(0xf));
+    assert_eq!(machine.read(&RDI), Ok(0x1f));
+
+    // cld
+    run(&mut machine, &[0xfc]);
+    assert_eq!(machine.read(&FLAG_DF), Ok(0));
+    // movsb
+    run(&mut machine, &[0xa4]);
+    assert_eq!(machine.read(&RSI), Ok(0x10));
+    assert_eq!(machine.read(&RDI), Ok(0x20));
+}
+
+#[test]
+fn not_and_neg() {
+    let ctx = long_mode();
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, 0xf0).unwrap();
+    machine.write(&FLAG_CF, 1).unwrap();
+    // not rax
+    let insn = ctx.decode(&[0x48, 0xf7, 0xd0]).unwrap();
+    assert_eq!(insn.mnemonic, InsnMnemonic::Not);
+    assert_eq!(machine.step(&insn.translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&RAX), Ok(!0xf0));
+    // not doesn't change the flags.
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+
+    // neg dword [rbx]
+    let insn = ctx.decode(&[0xf7, 0x1b]).unwrap();
+    assert_eq!(insn.mnemonic, InsnMnemonic::Neg);
+    let qword = Operand::ram(0x10, OperandSize::B8);
+    machine.write(&RBX, 0x10).unwrap();
+    machine.write(&qword, 0x1_0000_0005).unwrap();
+    assert_eq!(machine.step(&insn.translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&qword), Ok(0x1_ffff_fffb));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    assert_eq!(machine.read(&FLAG_SF), Ok(1));
+    // the carry flag is only cleared when negating zero.
+    machine
+        .write(&Operand::ram(0x10, OperandSize::B4), 0)
+        .unwrap();
+    assert_eq!(machine.step(&insn.translation), Ok(Flow::Next));
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+    // negating the most negative value overflows.
+    machine
+        .write(&Operand::ram(0x10, OperandSize::B4), 0x8000_0000)
+        .unwrap();
+    assert_eq!(machine.step(&insn.translation), Ok(Flow::Next));
+    assert_eq!(
+        machine.read(&Operand::ram(0x10, OperandSize::B4)),
+        Ok(0x8000_0000)
+    );
+    assert_eq!(machine.read(&FLAG_OF), Ok(1));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+}
+
+#[test]
+fn mul_and_div() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, u64::MAX).unwrap();
+    machine.write(&RCX, 3).unwrap();
+    // mul rcx
+    run(&mut machine, &[0x48, 0xf7, 0xe1]);
+    assert_eq!(machine.read(&RDX), Ok(2));
+    assert_eq!(machine.read(&RAX), Ok(u64::MAX - 2));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    assert_eq!(machine.read(&FLAG_OF), Ok(1));
+
+    machine.write(&RDX, 1).unwrap();
+    machine.write(&RAX, 5).unwrap();
+    machine.write(&RCX, 7).unwrap();
+    // div rcx
+    run(&mut machine, &[0x48, 0xf7, 0xf1]);
+    let dividend = (1u128 << 64) + 5;
+    assert_eq!(machine.read(&RAX), Ok((dividend / 7) as u64));
+    assert_eq!(machine.read(&RDX), Ok((dividend % 7) as u64));
+
+    machine.write(&RDX, 0xffff_ffff).unwrap();
+    machine.write(&RAX, -7i32 as u32 as u64).unwrap();
+    machine.write(&RCX, 2).unwrap();
+    // idiv ecx
+    run(&mut machine, &[0xf7, 0xf9]);
+    assert_eq!(machine.read(&RAX), Ok(-3i32 as u32 as u64));
+    assert_eq!(machine.read(&RDX), Ok(-1i32 as u32 as u64));
+
+    // the byte forms use ax as the double width value.
+    let ax = Reg::Rax.operand(OperandSize::B2);
+    machine.write(&RAX, 0x80).unwrap();
+    machine.write(&RCX, 4).unwrap();
+    // mul cl
+    run(&mut machine, &[0xf6, 0xe1]);
+    assert_eq!(machine.read(&ax), Ok(0x200));
+    machine.write(&RAX, 0xff).unwrap();
+    // imul cl
+    run(&mut machine, &[0xf6, 0xe9]);
+    assert_eq!(machine.read(&ax), Ok(0xfffc));
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+    machine.write(&RAX, 103).unwrap();
+    machine.write(&RCX, 10).unwrap();
+    // div cl
+    run(&mut machine, &[0xf6, 0xf1]);
+    assert_eq!(machine.read(&ax), Ok(0x030a));
+
+    machine.write(&RBX, 0x10).unwrap();
+    machine
+        .write(&Operand::ram(0x10, OperandSize::B8), -2i64 as u64)
+        .unwrap();
+    machine.write(&RAX, 5).unwrap();
+    // imul qword [rbx]
+    run(&mut machine, &[0x48, 0xf7, 0x2b]);
+    assert_eq!(machine.read(&RDX), Ok(u64::MAX));
+    assert_eq!(machine.read(&RAX), Ok(-10i64 as u64));
+}
+
+#[test]
+fn div_faults_on_zero_divisor_and_quotient_overflow() {
+    // div rcx
+    let translation = long_mode()
+        .translate(&[0x48, 0xf7, 0xf1])
+        .unwrap()
+        .translation;
+    let mut machine = Machine::new(0);
+    machine.write(&RAX, 5).unwrap();
+    assert_eq!(machine.step(&translation), Err(InterpError::DivideError));
+    // the quotient doesn't fit in 64 bits.
+    machine.write(&RDX, 8).unwrap();
+    machine.write(&RCX, 7).unwrap();
+    assert_eq!(machine.step(&translation), Err(InterpError::DivideError));
+}
+
+#[test]
+fn test_sets_flags_without_writing_its_operands() {
+    assert_eq!(
+        long_mode().decode(&[0x48, 0x85, 0xc0]).unwrap().mnemonic,
+        InsnMnemonic::Test
+    );
+
+    let mut machine = Machine::new(0x100);
+    machine.write(&FLAG_CF, 1).unwrap();
+    // test rax, rax
+    run(&mut machine, &[0x48, 0x85, 0xc0]);
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+    assert_eq!(machine.read(&FLAG_SF), Ok(0));
+    assert_eq!(machine.read(&FLAG_PF), Ok(1));
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+
+    machine.write(&RAX, 0x8000_0000_0000_0001).unwrap();
+    run(&mut machine, &[0x48, 0x85, 0xc0]);
+    assert_eq!(machine.read(&FLAG_ZF), Ok(0));
+    assert_eq!(machine.read(&FLAG_SF), Ok(1));
+    assert_eq!(machine.read(&FLAG_PF), Ok(0));
+    assert_eq!(machine.read(&RAX), Ok(0x8000_0000_0000_0001));
+
+    machine.write(&RBX, 0x10).unwrap();
+    machine.ram[0x10] = 1;
+    // test byte [rbx], 0x02
+    run(&mut machine, &[0xf6, 0x03, 0x02]);
+    assert_eq!(machine.read(&FLAG_ZF), Ok(1));
+
+    machine.write(&RAX, 0x100).unwrap();
+    // test eax, 0x100
+    run(&mut machine, &[0xf7, 0xc0, 0x00, 0x01, 0x00, 0x00]);
+    assert_eq!(machine.read(&FLAG_ZF), Ok(0));
+    assert_eq!(machine.read(&RAX), Ok(0x100));
+
+    // test dword [rip], 1
+    run(&mut machine, &[0xf7, 0x05, 0, 0, 0, 0, 1, 0, 0, 0]);
+}
+
+#[test]
+fn cmovcc_moves_only_when_condition_holds() {
+    assert_eq!(
+        long_mode()
+            .decode(&[0x48, 0x0f, 0x44, 0xc3])
+            .unwrap()
+            .mnemonic,
+        InsnMnemonic::Cmovcc
+    );
+    for zf in [0, 1] {
+        let mut machine = Machine::new(0);
+        machine.write(&RAX, 0x1111).unwrap();
+        machine.write(&RBX, 0x2222).unwrap();
+        machine.write(&FLAG_ZF, zf).unwrap();
+        // cmovz rax, rbx
+        run(&mut machine, &[0x48, 0x0f, 0x44, 0xc3]);
+        assert_eq!(
+            machine.read(&RAX),
+            Ok(if zf == 1 { 0x2222 } else { 0x1111 })
+        );
+        assert_eq!(machine.read(&RBX), Ok(0x2222));
+    }
+
+    // a 32 bit cmov zero extends its destination even if the condition doesn't hold.
+    let mut machine = Machine::new(0x100);
+    machine.write(&RAX, 0xffff_ffff_0000_0001).unwrap();
+    machine.write(&RBX, 0x10).unwrap();
+    machine.write(&FLAG_ZF, 1).unwrap();
+    // cmovne eax, [rbx]
+    run(&mut machine, &[0x0f, 0x45, 0x03]);
+    assert_eq!(machine.read(&RAX), Ok(1));
+    machine.write(&FLAG_ZF, 0).unwrap();
+    machine.ram[0x10] = 9;
+    run(&mut machine, &[0x0f, 0x45, 0x03]);
+    assert_eq!(machine.read(&RAX), Ok(9));
+}
+
+#[test]
+fn setcc_writes_condition_byte() {
+    assert_eq!(
+        long_mode().decode(&[0x0f, 0x94, 0xc0]).unwrap().mnemonic,
+        InsnMnemonic::Setcc
+    );
+    for zf in [0, 1] {
+        let mut machine = Machine::new(0x100);
+        machine.write(&RAX, 0x1234_5600).unwrap();
+        machine.write(&FLAG_ZF, zf).unwrap();
+        // sete al
+        run(&mut machine, &[0x0f, 0x94, 0xc0]);
+        assert_eq!(machine.read(&RAX), Ok(0x1234_5600 | zf));
+
+        machine.write(&RAX, 0x10).unwrap();
+        machine.ram[0x10] = 0xff;
+        // setne byte [rax]
+        run(&mut machine, &[0x0f, 0x95, 0x00]);
+        assert_eq!(machine.ram[0x10] as u64, 1 - zf);
+    }
+
+    let mut machine = Machine::new(0);
+    // setg ah
+    run(&mut machine, &[0x0f, 0x9f, 0xc4]);
+    assert_eq!(machine.read(&RAX), Ok(0x100));
+}
+
+/// the reference definition of each condition code in terms of the flags.
+fn reference_condition(
+    cc: ConditionCode,
+    cf: bool,
+    zf: bool,
+    sf: bool,
+    of: bool,
+    pf: bool,
+) -> bool {
+    match cc {
+        ConditionCode::O => of,
+        ConditionCode::No => !of,
+        ConditionCode::B => cf,
+        ConditionCode::Ae => !cf,
+        ConditionCode::E => zf,
+        ConditionCode::Ne => !zf,
+        ConditionCode::Be => cf || zf,
+        ConditionCode::A => !cf && !zf,
+        ConditionCode::S => sf,
+        ConditionCode::Ns => !sf,
+        ConditionCode::P => pf,
+        ConditionCode::Np => !pf,
+        ConditionCode::L => sf != of,
+        ConditionCode::Ge => sf == of,
+        ConditionCode::Le => zf || sf != of,
+        ConditionCode::G => !zf && sf == of,
+    }
+}
+
+#[test]
+fn condition_codes_match_reference_for_all_flag_combinations() {
+    let flags = [Flag::Cf, Flag::Zf, Flag::Sf, Flag::Of, Flag::Pf];
+    for (i, cc) in ConditionCode::iter().enumerate() {
+        assert_eq!(ConditionCode::from_opcode_low_nibble(0x90 + i as u8), cc);
+        for bits in 0..32u32 {
+            let [cf, zf, sf, of, pf] = [0, 1, 2, 3, 4].map(|bit| bits >> bit & 1 != 0);
+            let flag_value = |f: Flag| bits >> flags.iter().position(|x| *x == f).unwrap() & 1 != 0;
+            let expected = reference_condition(cc, cf, zf, sf, of, pf);
+            assert_eq!(cc.evaluate(flag_value), expected, "{cc:?}");
+
+            let mut machine = Machine::new(0);
+            for f in flags {
+                machine.write(&flag(f), flag_value(f) as u64).unwrap();
+            }
+            // setcc al
+            run(&mut machine, &[0x0f, 0x90 + i as u8, 0xc0]);
+            assert_eq!(machine.read(&RAX), Ok(expected as u64), "{cc:?}");
+        }
+    }
+}
+
+#[test]
+fn imul_two_and_three_operand_forms() {
+    assert_eq!(
+        long_mode()
+            .decode(&[0x48, 0x0f, 0xaf, 0xc3])
+            .unwrap()
+            .mnemonic,
+        InsnMnemonic::Imul
+    );
+    let mut machine = Machine::new(0x10);
+    machine.write(&RAX, -3i64 as u64).unwrap();
+    machine.write(&RBX, 7).unwrap();
+    // imul rax, rbx
+    run(&mut machine, &[0x48, 0x0f, 0xaf, 0xc3]);
+    assert_eq!(machine.read(&RAX), Ok(-21i64 as u64));
+    assert_eq!(machine.read(&RBX), Ok(7));
+    assert_eq!(machine.read(&FLAG_CF), Ok(0));
+
+    // the truncated result doesn't fit.
+    machine.write(&RAX, 1 << 62).unwrap();
+    machine.write(&RBX, 4).unwrap();
+    run(&mut machine, &[0x48, 0x0f, 0xaf, 0xc3]);
+    assert_eq!(machine.read(&RAX), Ok(0));
+    assert_eq!(machine.read(&FLAG_CF), Ok(1));
+    assert_eq!(machine.read(&FLAG_OF), Ok(1));
+
+    machine.write(&RAX, u64::MAX).unwrap();
+    machine.write(&RCX, 0xffff_ffff_0000_0003).unwrap();
+    // imul eax, ecx, 0x10
+    run(&mut machine, &[0x6b, 0xc1, 0x10]);
+    assert_eq!(machine.read(&RAX), Ok(0x30));
+    assert_eq!(machine.read(&FLAG_OF), Ok(0));
+    machine.write(&RAX, 0).unwrap();
+    // imul eax, ecx, 0x10 with an imm32
+    run(&mut machine, &[0x69, 0xc1, 0x10, 0, 0, 0]);
+    assert_eq!(machine.read(&RAX), Ok(0x30));
+
+    machine.write(&RAX, 0).unwrap();
+    machine.ram[8] = 5;
+    // imul ax, word [rip], -2
+    run(&mut machine, &[0x66, 0x6b, 0x05, 0, 0, 0, 0, 0xfe]);
+    assert_eq!(machine.read(&RAX), Ok(0xfff6));
+}
+
+#[test]
+fn shifts_and_rotates_mask_their_count() {
+    assert_eq!(
+        long_mode()
+            .decode(&[0x48, 0xc1, 0xc0, 13])
+            .unwrap()
+            .mnemonic,
+        InsnMnemonic::Rol
+    );
+    let mut machine = Machine::new(0x10);
+    let value = 0x8123_4567_89ab_cdefu64;
+    machine.write(&RAX, value).unwrap();
+    // rol rax, 13
+    run(&mut machine, &[0x48, 0xc1, 0xc0, 13]);
+    assert_eq!(machine.read(&RAX), Ok(value.rotate_left(13)));
+
+    machine.write(&RAX, 0x101).unwrap();
+    // ror al, 1
+    run(&mut machine, &[0xd0, 0xc8]);
+    assert_eq!(machine.read(&RAX), Ok(0x180));
+    machine.write(&RCX, 9).unwrap();
+    // rol al, cl
+    run(&mut machine, &[0xd2, 0xc0]);
+    assert_eq!(machine.read(&RAX), Ok(0x101));
+
+    // the count is 0x24 & 0x1f = 4.
+    machine.write(&RCX, 0xffff_ffff_8000_0024).unwrap();
+    // shr ecx, cl
+    run(&mut machine, &[0xd3, 0xe9]);
+    assert_eq!(machine.read(&RCX), Ok(0x0800_0002));
+
+    // the count is 33 & 0x1f = 1.
+    machine.ram[7..11].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+    // sar dword [rip], 33
+    run(&mut machine, &[0xc1, 0x3d, 0, 0, 0, 0, 33]);
+    assert_eq!(machine.ram[7..11], 0xc000_0000u32.to_le_bytes());
+
+    // rotates through the carry flag aren't lifted.
+    assert!(long_mode().decode(&[0xd1, 0xd0]).is_err());
+}
@@ -0,0 +1,571 @@
+use pis::*;
+
+fn translation_of(insns: &[Insn]) -> Translation {
+    let mut translation = Translation::new();
+    translation.insns.extend(insns.iter().cloned());
+    translation
+}
+
+fn coalesced(insns: &[Insn]) -> Translation {
+    let mut translation = translation_of(insns);
+    translation.coalesce_mem_access();
+    translation
+}
+
+fn addr(addr: u64) -> Operand {
+    Operand::constant(addr, OperandSize::B4)
+}
+
+#[test]
+fn coalesce_merges_stores_of_contiguous_halves() {
+    let low = Operand::reg(0x10, OperandSize::B4);
+    let high = Operand::reg(0x14, OperandSize::B4);
+    let merged = translation_of(&[Insn::new(
+        Opcode::Store,
+        addr(0x1000),
+        Operand::reg(0x10, OperandSize::B8),
+    )]);
+    assert_eq!(
+        coalesced(&[
+            Insn::new(Opcode::Store, addr(0x1000), low.clone()),
+            Insn::new(Opcode::Store, addr(0x1004), high.clone()),
+        ]),
+        merged.clone()
+    );
+    assert_eq!(
+        coalesced(&[
+            Insn::new(Opcode::Store, addr(0x1004), high),
+            Insn::new(Opcode::Store, addr(0x1000), low),
+        ]),
+        merged
+    );
+}
+
+#[test]
+fn coalesce_merges_stores_of_constant_halves() {
+    assert_eq!(
+        coalesced(&[
+            Insn::new(
+                Opcode::Store,
+                addr(0x1000),
+                Operand::constant(0x5678, OperandSize::B2)
+            ),
+            Insn::new(
+                Opcode::Store,
+                addr(0x1002),
+                Operand::constant(0x1234, OperandSize::B2)
+            ),
+        ]),
+        translation_of(&[Insn::new(
+            Opcode::Store,
+            addr(0x1000),
+            Operand::constant(0x12345678, OperandSize::B4)
+        )])
+    );
+}
+
+#[test]
+fn coalesce_merges_loads_into_contiguous_halves() {
+    let low = Operand::tmp(0, OperandSize::B4);
+    let high = Operand::tmp(4, OperandSize::B4);
+    let merged = translation_of(&[Insn::new(
+        Opcode::Load,
+        Operand::tmp(0, OperandSize::B8),
+        addr(0x1000),
+    )]);
+    assert_eq!(
+        coalesced(&[
+            Insn::new(Opcode::Load, low.clone(), addr(0x1000)),
+            Insn::new(Opcode::Load, high.clone(), addr(0x1004)),
+        ]),
+        merged.clone()
+    );
+    assert_eq!(
+        coalesced(&[
+            Insn::new(Opcode::Load, high, addr(0x1004)),
+            Insn::new(Opcode::Load, low, addr(0x1000)),
+        ]),
+        merged
+    );
+}
+
+#[test]
+fn coalesce_keeps_non_contiguous_accesses() {
+    let low = Operand::tmp(0, OperandSize::B4);
+    let high = Operand::tmp(4, OperandSize::B4);
+    let cases = [
+        // the addresses are not contiguous.
+        [
+            Insn::new(Opcode::Store, addr(0x1000), low.clone()),
+            Insn::new(Opcode::Store, addr(0x1008), high.clone()),
+        ],
+        [
+            Insn::new(Opcode::Load, low.clone(), addr(0x1000)),
+            Insn::new(Opcode::Load, high.clone(), addr(0x1008)),
+        ],
+        // the values are not the contiguous halves of a single value.
+        [
+            Insn::new(Opcode::Store, addr(0x1000), high.clone()),
+            Insn::new(Opcode::Store, addr(0x1004), low.clone()),
+        ],
+        [
+            Insn::new(Opcode::Load, high.clone(), addr(0x1000)),
+            Insn::new(Opcode::Load, low.clone(), addr(0x1004)),
+        ],
+        // the accesses overlap the same memory.
+        [
+            Insn::new(Opcode::Store, addr(0x1000), low.clone()),
+            Insn::new(Opcode::Store, addr(0x1000), high.clone()),
+        ],
+        // a load and a store are never merged.
+        [
+            Insn::new(Opcode::Load, low.clone(), addr(0x1000)),
+            Insn::new(Opcode::Store, addr(0x1004), high.clone()),
+        ],
+    ];
+    for insns in cases {
+        assert_eq!(coalesced(&insns), translation_of(&insns));
+    }
+}
+
+#[test]
+fn coalesce_only_merges_adjacent_accesses() {
+    let insns = [
+        Insn::new(
+            Opcode::Store,
+            addr(0x1000),
+            Operand::tmp(0, OperandSize::B4),
+        ),
+        Insn::new(Opcode::Load, Operand::tmp(8, OperandSize::B4), addr(0x1000)),
+        Insn::new(
+            Opcode::Store,
+            addr(0x1004),
+            Operand::tmp(4, OperandSize::B4),
+        ),
+    ];
+    assert_eq!(coalesced(&insns), translation_of(&insns));
+}
+
+#[test]
+fn operand_size_conversions() {
+    for size in [
+        OperandSize::B1,
+        OperandSize::B2,
+        OperandSize::B4,
+        OperandSize::B8,
+        OperandSize::B16,
+    ] {
+        assert_eq!(OperandSize::from_bytes(size.bytes()), Some(size));
+        assert_eq!(OperandSize::from_bits(size.bits()), Some(size));
+    }
+    for bytes in [0, 3, 5, 32] {
+        assert_eq!(OperandSize::from_bytes(bytes), None);
+    }
+    for bits in [0, 7, 12, 24] {
+        assert_eq!(OperandSize::from_bits(bits), None);
+    }
+    assert_eq!(OperandSize::of_storage::<u8>(), OperandSize::B1);
+    assert_eq!(OperandSize::of_storage::<u16>(), OperandSize::B2);
+    assert_eq!(OperandSize::of_storage::<u32>(), OperandSize::B4);
+    assert_eq!(OperandSize::of_storage::<u64>(), OperandSize::B8);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn dot_output_has_a_node_per_insn() {
+    let translation = translation_of(&[
+        Insn::new(
+            Opcode::Move,
+            Operand::tmp(0, OperandSize::B1),
+            Operand::constant(1, OperandSize::B1),
+        ),
+        Insn::new(
+            Opcode::Branch,
+            Operand::tmp(0, OperandSize::B1),
+            Operand::constant(0x1000, OperandSize::B8),
+        ),
+        Insn::new(
+            Opcode::Jump,
+            Operand::constant(0x2000, OperandSize::B8),
+            Operand::zero(OperandSize::B1),
+        ),
+    ]);
+    let dot = translation.to_dot();
+    assert!(dot.starts_with("digraph translation {\n"));
+    for (i, insn) in translation.insns.iter().enumerate() {
+        assert!(dot.contains(&format!(
+            "    insn{} [shape=box, label=\"{}: {}\"];",
+            i, i, insn
+        )));
+    }
+    assert!(!dot.contains("insn3"));
+    for edge in ["entry -> insn0", "insn0 -> insn1", "insn2 -> exit"] {
+        assert!(dot.contains(edge));
+    }
+
+    assert!(Translation::new().to_dot().contains("entry -> exit"));
+}
+
+#[test]
+fn constants_display_truncated_to_their_size() {
+    assert_eq!(
+        Operand::constant(0x1ff, OperandSize::B1).to_string(),
+        "Const[0xff]:1"
+    );
+    let unmasked = Operand {
+        addr: OperandAddr {
+            space: OperandSpace::Const,
+            offset: 0x1ff,
+        },
+        size: OperandSize::B1,
+    };
+    assert_eq!(unmasked.to_string(), "Const[0xff]:1");
+    assert_eq!(
+        Operand::negative_constant(8, OperandSize::B8).to_string(),
+        "Const[0xfffffffffffffff8]:8"
+    );
+    assert_eq!(
+        Operand::negative_constant(8, OperandSize::B2).to_string(),
+        "Const[0xfff8]:2"
+    );
+}
+
+#[test]
+fn bitwise_and_shift_insns_display() {
+    let reg = Operand::reg(0, OperandSize::B8);
+    for (opcode, name) in [
+        (Opcode::And, "And"),
+        (Opcode::Or, "Or"),
+        (Opcode::Xor, "Xor"),
+    ] {
+        assert_eq!(
+            Insn::new(opcode, reg.clone(), Operand::reg(8, OperandSize::B8)).to_string(),
+            format!("{} Regs[0x0]:8, Regs[0x8]:8", name)
+        );
+    }
+    for (opcode, name) in [
+        (Opcode::Shl, "Shl"),
+        (Opcode::Shr, "Shr"),
+        (Opcode::Sar, "Sar"),
+    ] {
+        assert_eq!(
+            Insn::new(opcode, reg.clone(), Operand::constant(1, OperandSize::B1)).to_string(),
+            format!("{} Regs[0x0]:8, Const[0x1]:1", name)
+        );
+    }
+}
+
+#[test]
+fn set_cond_insns_display() {
+    let flag = Operand::reg(0x83, OperandSize::B1);
+    let value = Operand::reg(0, OperandSize::B8);
+    for (cond, name) in [
+        (Cond::Zero, "Zero"),
+        (Cond::Negative, "Negative"),
+        (Cond::EvenParity, "EvenParity"),
+    ] {
+        let insn = Insn::new(Opcode::SetCond(cond), flag.clone(), value.clone());
+        assert_eq!(
+            insn.to_string(),
+            format!("SetCond({}) Regs[0x83]:1, Regs[0x0]:8", name)
+        );
+    }
+}
+
+#[test]
+fn tmp_allocations_are_disjoint_and_aligned() {
+    let sizes = [
+        OperandSize::B1,
+        OperandSize::B4,
+        OperandSize::B2,
+        OperandSize::B8,
+        OperandSize::B1,
+        OperandSize::B16,
+        OperandSize::B2,
+    ];
+    let mut allocator = TmpAllocator::new();
+    let tmps: Vec<Operand> = sizes.iter().map(|&size| allocator.alloc(size)).collect();
+    for (i, tmp) in tmps.iter().enumerate() {
+        assert_eq!(tmp.addr.space, OperandSpace::Tmp);
+        assert_eq!(tmp.size, sizes[i]);
+        assert_eq!(tmp.addr.offset % tmp.size.bytes() as u64, 0);
+        let (start, end) = (tmp.addr.offset, tmp.addr.offset + tmp.size.bytes() as u64);
+        for other in &tmps[i + 1..] {
+            let (other_start, other_end) = (
+                other.addr.offset,
+                other.addr.offset + other.size.bytes() as u64,
+            );
+            assert!(end <= other_start || other_end <= start);
+        }
+    }
+
+    allocator.reset();
+    assert_eq!(
+        allocator.alloc(OperandSize::B8),
+        Operand::tmp(0, OperandSize::B8)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn translation_json_round_trip() {
+    let mut atomic_add = Insn::new(
+        Opcode::Add,
+        Operand::tmp(0, OperandSize::B4),
+        Operand::constant(1, OperandSize::B4),
+    );
+    atomic_add.is_atomic = true;
+    let translation = translation_of(&[
+        Insn::new(
+            Opcode::Load,
+            Operand::tmp(0, OperandSize::B4),
+            Operand::reg(0x10, OperandSize::B8),
+        ),
+        atomic_add,
+        Insn::new(
+            Opcode::SetCond(Cond::Zero),
+            Operand::reg(0x83, OperandSize::B1),
+            Operand::tmp(0, OperandSize::B4),
+        ),
+        Insn::new(
+            Opcode::Intrinsic(Intrinsic::ReadControlReg(3)),
+            Operand::reg(0, OperandSize::B8),
+            Operand::zero(OperandSize::B1),
+        ),
+        Insn::new(
+            Opcode::Store,
+            Operand::ram(0x1000, OperandSize::B8),
+            Operand::tmp(0, OperandSize::B4),
+        ),
+    ]);
+    let json = serde_json::to_string(&translation).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Translation>(&json).unwrap(),
+        translation
+    );
+}
+
+#[test]
+fn validate_accepts_well_formed_translations() {
+    let rax = Operand::reg(0, OperandSize::B8);
+    let translation = translation_of(&[
+        Insn::new(
+            Opcode::Add,
+            rax.clone(),
+            Operand::reg(0x18, OperandSize::B8),
+        ),
+        Insn::new(Opcode::Store, rax.clone(), Operand::reg(0, OperandSize::B1)),
+        Insn::new(Opcode::UMul, Operand::tmp(0, OperandSize::B16), rax.clone()),
+        Insn::new(
+            Opcode::Shl,
+            rax.clone(),
+            Operand::constant(3, OperandSize::B1),
+        ),
+        Insn::new(Opcode::Jump, rax, Operand::zero(OperandSize::B1)),
+    ]);
+    assert_eq!(translation.validate(), Ok(()));
+}
+
+#[test]
+fn validate_reports_index_of_malformed_insn() {
+    let rax = Operand::reg(0, OperandSize::B8);
+    let al = Operand::reg(0, OperandSize::B1);
+    let cases = [
+        (
+            Insn::new(Opcode::Add, rax.clone(), al.clone()),
+            ValidationError::SizeMismatch(1),
+        ),
+        (
+            Insn::new(Opcode::ZeroExtend, al.clone(), rax.clone()),
+            ValidationError::SizeMismatch(1),
+        ),
+        (
+            Insn::new(Opcode::Move, Operand::zero(OperandSize::B8), rax.clone()),
+            ValidationError::WriteToConst(1),
+        ),
+        (
+            Insn::new(
+                Opcode::Load,
+                rax.clone(),
+                Operand::ram(0x1000, OperandSize::B8),
+            ),
+            ValidationError::RamAddress(1),
+        ),
+        (
+            Insn::new(Opcode::Branch, rax.clone(), rax.clone()),
+            ValidationError::InvalidConditionSize(1),
+        ),
+        (
+            Insn::new(Opcode::Jump, rax.clone(), rax.clone()),
+            ValidationError::UnusedOperandNotZero(1),
+        ),
+    ];
+    for (insn, error) in cases {
+        let translation =
+            translation_of(&[Insn::new(Opcode::Move, rax.clone(), rax.clone()), insn]);
+        assert_eq!(translation.validate(), Err(error));
+    }
+}
+
+#[test]
+fn constant_values_as_signed_and_unsigned() {
+    let minus_eight = Operand::negative_constant(8, OperandSize::B8);
+    assert_eq!(minus_eight.as_signed(), Some(-8));
+    assert_eq!(minus_eight.as_unsigned(), Some(-8i64 as u64));
+
+    let minus_one = Operand::negative_constant(1, OperandSize::B1);
+    assert_eq!(minus_one.as_signed(), Some(-1));
+    assert_eq!(minus_one.as_unsigned(), Some(0xff));
+
+    let max_positive = Operand::constant(0x7fff, OperandSize::B2);
+    assert_eq!(max_positive.as_signed(), Some(0x7fff));
+    assert_eq!(
+        Operand::constant(0x8000, OperandSize::B2).as_signed(),
+        Some(-0x8000)
+    );
+
+    for operand in [
+        Operand::reg(0, OperandSize::B8),
+        Operand::tmp(0, OperandSize::B8),
+        Operand::ram(0x10, OperandSize::B8),
+    ] {
+        assert_eq!(operand.as_signed(), None);
+        assert_eq!(operand.as_unsigned(), None);
+    }
+}
+
+#[test]
+fn numbered_display_pads_indices() {
+    let mut translation = translation_of(&[
+        Insn::new(
+            Opcode::Add,
+            Operand::reg(0x20, OperandSize::B8),
+            Operand::constant(8, OperandSize::B8),
+        ),
+        Insn::new(
+            Opcode::Move,
+            Operand::reg(0, OperandSize::B8),
+            Operand::reg(8, OperandSize::B8),
+        ),
+    ]);
+    assert_eq!(
+        translation.display_numbered().to_string(),
+        "  0: Add Regs[0x20]:8, Const[0x8]:8\n  1: Move Regs[0x0]:8, Regs[0x8]:8\n"
+    );
+
+    // with more than 10 instructions, the indices are padded to 2 digits.
+    let nop = Insn::new(
+        Opcode::Nop,
+        Operand::constant(1, OperandSize::B1),
+        Operand::zero(OperandSize::B1),
+    );
+    translation.insns.extend(core::iter::repeat_n(nop, 9));
+    let displayed = translation.display_numbered().to_string();
+    let lines: Vec<&str> = displayed.lines().collect();
+    assert_eq!(lines.len(), 11);
+    assert_eq!(lines[0], "  00: Add Regs[0x20]:8, Const[0x8]:8");
+    assert_eq!(lines[10], "  10: Nop Const[0x1]:1, Const[0x0]:1");
+
+    assert_eq!(Translation::new().display_numbered().to_string(), "");
+}
+
+#[test]
+fn read_and_written_operands_of_each_opcode() {
+    let both: &[usize] = &[0, 1];
+    let first: &[usize] = &[0];
+    let second: &[usize] = &[1];
+    let none: &[usize] = &[];
+    // (opcode, indices of the read operands, indices of the written operands)
+    let cases = [
+        (Opcode::Move, second, first),
+        (Opcode::ZeroExtend, second, first),
+        (Opcode::SignExtend, second, first),
+        (Opcode::Add, both, first),
+        (Opcode::Sub, both, first),
+        (Opcode::And, both, first),
+        (Opcode::Or, both, first),
+        (Opcode::Xor, both, first),
+        (Opcode::Shl, both, first),
+        (Opcode::Shr, both, first),
+        (Opcode::Sar, both, first),
+        (Opcode::Rol, both, first),
+        (Opcode::Ror, both, first),
+        (Opcode::UMul, both, first),
+        (Opcode::SMul, both, first),
+        (Opcode::UDiv, both, first),
+        (Opcode::SDiv, both, first),
+        (Opcode::Not, first, first),
+        (Opcode::Neg, first, first),
+        (Opcode::Store, both, none),
+        (Opcode::Load, second, first),
+        (Opcode::SetCond(Cond::Zero), second, first),
+        (Opcode::Branch, both, none),
+        (Opcode::Jump, first, none),
+        (Opcode::Call, first, none),
+        (Opcode::Return, first, none),
+        (Opcode::Intrinsic(Intrinsic::Syscall), none, none),
+        (
+            Opcode::Intrinsic(Intrinsic::WriteControlReg(3)),
+            first,
+            none,
+        ),
+        (Opcode::Intrinsic(Intrinsic::ReadControlReg(3)), none, first),
+        (Opcode::Intrinsic(Intrinsic::WriteDebugReg(7)), first, none),
+        (Opcode::Intrinsic(Intrinsic::ReadDebugReg(7)), none, first),
+        (Opcode::Nop, none, none),
+    ];
+    for (opcode, read, written) in cases {
+        let insn = Insn::new(
+            opcode,
+            Operand::reg(0, OperandSize::B8),
+            Operand::reg(8, OperandSize::B8),
+        );
+        let operands_at = |indices: &[usize]| -> Vec<Operand> {
+            indices.iter().map(|&i| insn.operands[i].clone()).collect()
+        };
+        assert_eq!(insn.read_operands(), operands_at(read), "{:?}", opcode);
+        assert_eq!(
+            insn.written_operands(),
+            operands_at(written),
+            "{:?}",
+            opcode
+        );
+    }
+}
+
+#[test]
+fn coalesce_keeps_accesses_whose_merged_size_is_too_wide() {
+    let low = Operand::tmp(0, OperandSize::B8);
+    let high = Operand::tmp(8, OperandSize::B8);
+    let cases = [
+        [
+            Insn::new(Opcode::Store, addr(0x1000), low.clone()),
+            Insn::new(Opcode::Store, addr(0x1008), high.clone()),
+        ],
+        [
+            Insn::new(
+                Opcode::Store,
+                addr(0x1000),
+                Operand::constant(0x5678, OperandSize::B8),
+            ),
+            Insn::new(
+                Opcode::Store,
+                addr(0x1008),
+                Operand::constant(0x1234, OperandSize::B8),
+            ),
+        ],
+        [
+            Insn::new(Opcode::Load, low.clone(), addr(0x1000)),
+            Insn::new(Opcode::Load, high.clone(), addr(0x1008)),
+        ],
+        [
+            Insn::new(Opcode::Load, high, addr(0x1008)),
+            Insn::new(Opcode::Load, low, addr(0x1000)),
+        ],
+    ];
+    for insns in cases {
+        let translation = coalesced(&insns);
+        assert_eq!(translation, translation_of(&insns));
+        assert_eq!(translation.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,186 @@
+use pis::{
+    opt::{eliminate_dead_stores, fold_constants, simplify_idioms},
+    x86::*,
+    *,
+};
+
+fn translation_of(insns: &[Insn]) -> Translation {
+    let mut translation = Translation::new();
+    translation.insns.extend(insns.iter().cloned());
+    translation
+}
+
+#[test]
+fn simplify_idioms_zeroes_xor_and_sub_of_self() {
+    for opcode in [Opcode::Xor, Opcode::Sub] {
+        let mut translation = translation_of(&[Insn::new(opcode, RAX, RAX)]);
+        simplify_idioms(&mut translation);
+        assert_eq!(
+            translation,
+            translation_of(&[Insn::new(Opcode::Move, RAX, Operand::zero(OperandSize::B8))])
+        );
+    }
+}
+
+#[test]
+fn simplify_idioms_removes_and_or_of_self() {
+    let mut translation = translation_of(&[
+        Insn::new(Opcode::And, RAX, RAX),
+        Insn::new(Opcode::Or, RCX, RCX),
+        Insn::new(Opcode::Add, RAX, RCX),
+    ]);
+    simplify_idioms(&mut translation);
+    assert_eq!(
+        translation,
+        translation_of(&[Insn::new(Opcode::Add, RAX, RCX)])
+    );
+}
+
+#[test]
+fn simplify_idioms_keeps_different_operands() {
+    let insns = [
+        Insn::new(Opcode::Xor, RAX, RCX),
+        Insn::new(Opcode::Sub, RAX, RCX),
+        Insn::new(Opcode::And, RAX, RCX),
+        Insn::new(Opcode::Or, RAX, RCX),
+        Insn::new(Opcode::Add, RAX, RAX),
+    ];
+    let mut translation = translation_of(&insns);
+    simplify_idioms(&mut translation);
+    assert_eq!(translation, translation_of(&insns));
+}
+
+#[test]
+fn fold_constants_folds_chain_of_adds_into_move() {
+    let tmp = Operand::tmp(0, OperandSize::B8);
+    let constant = |value| Operand::constant(value, OperandSize::B8);
+    let mut translation = translation_of(&[
+        Insn::new(Opcode::Move, tmp.clone(), constant(0)),
+        Insn::new(Opcode::Add, tmp.clone(), constant(5)),
+        Insn::new(Opcode::Add, tmp.clone(), constant(3)),
+    ]);
+    fold_constants(&mut translation);
+    assert_eq!(
+        translation,
+        translation_of(&[Insn::new(Opcode::Move, tmp, constant(8))])
+    );
+}
+
+#[test]
+fn fold_constants_wraps_at_operand_size() {
+    let tmp = Operand::tmp(0, OperandSize::B1);
+    let mut translation = translation_of(&[
+        Insn::new(
+            Opcode::Move,
+            tmp.clone(),
+            Operand::constant(0xff, OperandSize::B1),
+        ),
+        Insn::new(
+            Opcode::Add,
+            tmp.clone(),
+            Operand::constant(2, OperandSize::B1),
+        ),
+    ]);
+    fold_constants(&mut translation);
+    assert_eq!(
+        translation,
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            tmp,
+            Operand::constant(1, OperandSize::B1)
+        )])
+    );
+}
+
+#[test]
+fn fold_constants_removes_identity_operations() {
+    let constant = |value| Operand::constant(value, OperandSize::B8);
+    let mut translation = translation_of(&[
+        Insn::new(Opcode::Add, RAX, constant(5)),
+        Insn::new(Opcode::Sub, RAX, constant(5)),
+        Insn::new(Opcode::Or, RAX, constant(0)),
+        Insn::new(
+            Opcode::And,
+            Reg::Rax.operand(OperandSize::B4),
+            Operand::constant(0xffffffff, OperandSize::B4),
+        ),
+        Insn::new(Opcode::Add, RAX, RBX),
+    ]);
+    fold_constants(&mut translation);
+    assert_eq!(
+        translation,
+        translation_of(&[Insn::new(Opcode::Add, RAX, RBX)])
+    );
+
+    // a constant of a different size is not folded.
+    let unfolded = translation_of(&[Insn::new(Opcode::Add, RAX, Operand::zero(OperandSize::B4))]);
+    let mut translation = unfolded.clone();
+    fold_constants(&mut translation);
+    assert_eq!(translation, unfolded);
+}
+
+#[test]
+fn eliminate_dead_stores_removes_unread_tmp_writes() {
+    let addr = Operand::tmp(0, OperandSize::B8);
+    let unused = Operand::tmp(8, OperandSize::B8);
+    let constant = |value| Operand::constant(value, OperandSize::B8);
+    let mut translation = translation_of(&[
+        // overwritten before being read.
+        Insn::new(Opcode::Move, addr.clone(), RBX),
+        // never read.
+        Insn::new(Opcode::Move, unused.clone(), RCX),
+        Insn::new(Opcode::Add, unused, constant(4)),
+        Insn::new(Opcode::Move, addr.clone(), RAX),
+        Insn::new(Opcode::Add, addr.clone(), constant(8)),
+        Insn::new(Opcode::Load, RDX, addr.clone()),
+        // not read by the rest of the translation.
+        Insn::new(Opcode::Move, addr.clone(), constant(1)),
+    ]);
+    eliminate_dead_stores(&mut translation);
+    assert_eq!(
+        translation,
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), RAX),
+            Insn::new(Opcode::Add, addr.clone(), constant(8)),
+            Insn::new(Opcode::Load, RDX, addr),
+        ])
+    );
+}
+
+#[test]
+fn eliminate_dead_stores_keeps_partially_overwritten_tmps() {
+    let tmp = Operand::tmp(0, OperandSize::B8);
+    let expected = translation_of(&[
+        Insn::new(Opcode::Move, tmp.clone(), RBX),
+        Insn::new(
+            Opcode::Move,
+            Operand::tmp(4, OperandSize::B4),
+            Operand::zero(OperandSize::B4),
+        ),
+        Insn::new(Opcode::Store, RAX, tmp),
+    ]);
+    let mut translation = expected.clone();
+    eliminate_dead_stores(&mut translation);
+    assert_eq!(translation, expected);
+}
+
+#[test]
+fn eliminate_dead_stores_keeps_lifted_insns_intact() {
+    let ctx = X86Ctx {
+        cpu_mode: X86CpuMode::LongMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    };
+    // mov rax, [rax+rcx*4+0x10]; push rax; ret; xlat
+    for code in [
+        &[0x48, 0x8b, 0x44, 0x88, 0x10][..],
+        &[0x50],
+        &[0xc3],
+        &[0xd7],
+    ] {
+        let expected = ctx.translate(code).unwrap().translation;
+        let mut translation = expected.clone();
+        eliminate_dead_stores(&mut translation);
+        assert_eq!(translation, expected);
+    }
+}
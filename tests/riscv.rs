@@ -0,0 +1,90 @@
+#![cfg(feature = "alloc")]
+
+use pis::{
+    interp::{Flow, Machine},
+    riscv::*,
+    *,
+};
+
+/// lifts the given instruction at address 0x1000, and executes it on the given machine.
+fn run(machine: &mut Machine, insn: u32) -> Flow {
+    let result = RiscvCtx.translate_at(&insn.to_le_bytes(), 0x1000).unwrap();
+    assert_eq!(result.bytes_consumed, INSN_LEN);
+    machine.step(&result.translation).unwrap()
+}
+
+#[test]
+fn addi_adds_sign_extended_immediate() {
+    // addi x1, x2, 5
+    assert_eq!(
+        RiscvCtx.translate(&0x00510093u32.to_le_bytes()),
+        Ok(TranslationResult {
+            translation: {
+                let mut builder = TranslationBuilder::new();
+                builder.mov(X1, X2);
+                builder.add(X1, Operand::constant(5, OperandSize::B8));
+                builder.build()
+            },
+            bytes_consumed: 4,
+        })
+    );
+
+    let mut machine = Machine::new(0);
+    machine.write(&X2, 10).unwrap();
+    run(&mut machine, 0x00510093);
+    assert_eq!(machine.read(&X1), Ok(15));
+    // addi x1, x1, -1
+    run(&mut machine, 0xfff08093);
+    assert_eq!(machine.read(&X1), Ok(14));
+    // addi x0, x0, 5
+    run(&mut machine, 0x00500013);
+    assert_eq!(machine.read(&X0), Ok(0));
+}
+
+#[test]
+fn register_arithmetic_and_memory_access() {
+    let mut machine = Machine::new(0x100);
+    machine.write(&X1, 14).unwrap();
+    machine.write(&X3, 100).unwrap();
+    // add x3, x1, x3
+    run(&mut machine, 0x003081b3);
+    assert_eq!(machine.read(&X3), Ok(114));
+
+    machine.write(&X2, 0x20).unwrap();
+    // sd x3, -8(x2)
+    run(&mut machine, 0xfe313c23);
+    assert_eq!(machine.ram[0x18..0x20], 114u64.to_le_bytes());
+    // ld x5, -8(x2)
+    run(&mut machine, 0xff813283);
+    assert_eq!(machine.read(&X5), Ok(114));
+}
+
+#[test]
+fn jal_links_and_jumps_relative_to_the_insn() {
+    let mut machine = Machine::new(0);
+    // jal x1, -16
+    assert_eq!(run(&mut machine, 0xff1ff0ef), Flow::Jump(0x1000 - 16));
+    assert_eq!(machine.read(&X1), Ok(0x1004));
+    // jal x0, 2048
+    assert_eq!(run(&mut machine, 0x0010006f), Flow::Jump(0x1000 + 2048));
+    assert_eq!(machine.read(&X0), Ok(0));
+}
+
+#[test]
+fn invalid_encodings_are_errors() {
+    assert_eq!(
+        RiscvCtx.translate(&[0x13, 0x00]),
+        Err(TranslateError::TruncatedInstruction)
+    );
+    assert_eq!(
+        RiscvCtx.translate(&[0x7f, 0x00, 0x00, 0x00]),
+        Err(TranslateError::UnknownOpcode(0x7f))
+    );
+}
+
+#[test]
+fn reg_file_describes_the_register_layout() {
+    let reg_file = RiscvCtx.reg_file();
+    assert_eq!(reg_file.reg_at(8).unwrap().operand(), X1);
+    assert_eq!(reg_file.reg_by_name("x31").unwrap().offset, 31 * XREG_STEP);
+}
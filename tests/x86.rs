@@ -0,0 +1,1553 @@
+use bitpiece::BitPiece;
+use pis::{x86::*, *};
+use strum::IntoEnumIterator;
+
+fn long_mode() -> X86Ctx {
+    X86Ctx {
+        cpu_mode: X86CpuMode::LongMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    }
+}
+
+fn protected_mode() -> X86Ctx {
+    X86Ctx {
+        cpu_mode: X86CpuMode::ProtectedMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B32,
+        preserve_nops: false,
+    }
+}
+
+fn real_mode() -> X86Ctx {
+    X86Ctx {
+        cpu_mode: X86CpuMode::RealMode,
+        code_segment_default_operand_size: X86SegmentDefaultOperandSize::B16,
+        preserve_nops: false,
+    }
+}
+
+fn translation_of(insns: &[Insn]) -> Translation {
+    let mut translation = Translation::new();
+    translation.insns.extend(insns.iter().cloned());
+    translation
+}
+
+fn lift(ctx: &X86Ctx, code: &[u8]) -> Translation {
+    let result = ctx.translate(code).unwrap();
+    assert_eq!(result.bytes_consumed, code.len());
+    result.translation
+}
+
+#[test]
+fn push_reg_matches_hand_built_translation() {
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(8, OperandSize::B8));
+    builder.store(RSP, RCX);
+    assert_eq!(lift(&long_mode(), &[0x51]), builder.build());
+
+    let mut builder = TranslationBuilder::new();
+    let old_rsp = builder.alloc_tmp(OperandSize::B8);
+    builder.mov(old_rsp.clone(), RSP);
+    builder.sub(RSP, Operand::constant(8, OperandSize::B8));
+    builder.store(RSP, old_rsp);
+    assert_eq!(lift(&long_mode(), &[0x54]), builder.build());
+}
+
+#[test]
+fn push_and_pop_of_segment_relative_memory_use_distinct_tmps() {
+    let addr = Operand::tmp(0, OperandSize::B8);
+    let value = Operand::tmp(8, OperandSize::B8);
+    let eight = Operand::constant(8, OperandSize::B8);
+
+    // push qword [fs:rax]
+    assert_eq!(
+        lift(&long_mode(), &[0x64, 0xff, 0x30]),
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), RAX),
+            Insn::new(Opcode::Add, addr.clone(), FS_BASE),
+            Insn::new(Opcode::Load, value.clone(), addr.clone()),
+            Insn::new(Opcode::Sub, RSP, eight.clone()),
+            Insn::new(Opcode::Store, RSP, value.clone()),
+        ])
+    );
+
+    // pop qword [fs:rax]
+    assert_eq!(
+        lift(&long_mode(), &[0x64, 0x8f, 0x00]),
+        translation_of(&[
+            Insn::new(Opcode::Load, value.clone(), RSP),
+            Insn::new(Opcode::Add, RSP, eight),
+            Insn::new(Opcode::Move, addr.clone(), RAX),
+            Insn::new(Opcode::Add, addr.clone(), FS_BASE),
+            Insn::new(Opcode::Store, addr, value),
+        ])
+    );
+}
+
+#[test]
+fn checked_operand_rejects_byte_access_to_rex_only_registers() {
+    for reg in [Reg::Rax, Reg::Rcx, Reg::Rdx, Reg::Rbx] {
+        for has_rex_prefix in [false, true] {
+            assert_eq!(
+                reg.checked_operand(OperandSize::B1, has_rex_prefix),
+                Ok(reg.operand(OperandSize::B1))
+            );
+        }
+    }
+    for reg in [Reg::RspAh, Reg::RbpCh, Reg::RsiDh, Reg::RdiBh] {
+        assert_eq!(
+            reg.checked_operand(OperandSize::B1, false),
+            Err(TranslateError::InvalidRegisterSize)
+        );
+        assert_eq!(
+            reg.checked_operand(OperandSize::B1, true),
+            Ok(reg.operand(OperandSize::B1))
+        );
+        for size in [OperandSize::B2, OperandSize::B4, OperandSize::B8] {
+            for has_rex_prefix in [false, true] {
+                assert_eq!(
+                    reg.checked_operand(size, has_rex_prefix),
+                    Ok(reg.operand(size))
+                );
+            }
+        }
+    }
+    for reg in [Reg::R8, Reg::R15] {
+        assert_eq!(
+            reg.checked_operand(OperandSize::B8, false),
+            Err(TranslateError::InvalidRegisterSize)
+        );
+        assert_eq!(
+            reg.checked_operand(OperandSize::B1, true),
+            Ok(reg.operand(OperandSize::B1))
+        );
+    }
+    assert_eq!(
+        Reg::Rax.checked_operand(OperandSize::B16, true),
+        Err(TranslateError::InvalidRegisterSize)
+    );
+}
+
+#[test]
+fn high_byte_operand_exists_only_for_the_first_4_registers_without_rex() {
+    assert_eq!(
+        Reg::Rax.high_byte_operand(false),
+        Ok(Operand::reg(1, OperandSize::B1))
+    );
+    assert_eq!(
+        Reg::Rbx.high_byte_operand(false),
+        Ok(Operand::reg(3 * GPR_STEP + 1, OperandSize::B1))
+    );
+    assert_eq!(
+        Reg::Rax.high_byte_operand(true),
+        Err(TranslateError::InvalidRegisterSize)
+    );
+    for reg in [Reg::RspAh, Reg::RdiBh, Reg::R8, Reg::R12] {
+        assert_eq!(
+            reg.high_byte_operand(false),
+            Err(TranslateError::InvalidRegisterSize)
+        );
+    }
+}
+
+#[test]
+fn byte_register_encodings_lift_to_checked_operands() {
+    let expected = |index: u8, has_rex_prefix: bool| {
+        let reg = Reg::from_bits(index);
+        if (4..8).contains(&index) && !has_rex_prefix {
+            Reg::from_bits(index - 4).high_byte_operand(has_rex_prefix)
+        } else {
+            reg.checked_operand(OperandSize::B1, has_rex_prefix)
+        }
+        .unwrap()
+    };
+    for rex in [None, Some(0x40), Some(0x45)] {
+        let extension = if rex == Some(0x45) { 8 } else { 0 };
+        for modrm_reg in 0..8u8 {
+            for modrm_rm in 0..8u8 {
+                let mut code: Vec<u8> = rex.into_iter().collect();
+                // mov r/m8, r8
+                code.extend([0x88, 0xc0 | modrm_reg << 3 | modrm_rm]);
+                let insns = lift(&long_mode(), &code).insns;
+                assert_eq!(insns.len(), 1, "{code:x?}");
+                assert_eq!(
+                    insns[0].operands,
+                    [
+                        expected(modrm_rm | extension, rex.is_some()),
+                        expected(modrm_reg | extension, rex.is_some()),
+                    ],
+                    "{code:x?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn byte_register_in_opcode_depends_on_rex_prefix() {
+    let one = Operand::constant(1, OperandSize::B1);
+    // mov ah, 1
+    assert_eq!(
+        lift(&long_mode(), &[0xb4, 0x01]),
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            Operand::reg(1, OperandSize::B1),
+            one.clone()
+        )])
+    );
+    // mov spl, 1
+    assert_eq!(
+        lift(&long_mode(), &[0x40, 0xb4, 0x01]),
+        translation_of(&[Insn::new(Opcode::Move, SPL, one)])
+    );
+}
+
+#[test]
+fn rex_prefix_extends_opcode_register_in_long_mode() {
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(8, OperandSize::B8));
+    builder.store(RSP, R15);
+    assert_eq!(lift(&long_mode(), &[0x41, 0x57]), builder.build());
+}
+
+#[test]
+fn rex_bytes_are_inc_dec_outside_of_long_mode() {
+    let ctx = protected_mode();
+    for (code, mnemonic, reg) in [
+        (0x40, InsnMnemonic::Inc, Reg::Rax),
+        (0x41, InsnMnemonic::Inc, Reg::Rcx),
+        (0x47, InsnMnemonic::Inc, Reg::RdiBh),
+        (0x48, InsnMnemonic::Dec, Reg::Rax),
+        (0x4c, InsnMnemonic::Dec, Reg::RspAh),
+        (0x4f, InsnMnemonic::Dec, Reg::RdiBh),
+    ] {
+        let insn = ctx.decode(&[code, 0x51]).unwrap();
+        assert_eq!((insn.mnemonic, insn.length), (mnemonic, 1));
+        let [dst, _] = &insn.translation.insns.last().unwrap().operands;
+        assert_eq!(*dst, reg.operand(OperandSize::B4));
+    }
+    // with an operand size override, the 16 bit register is incremented.
+    let insn = ctx.decode(&[0x66, 0x41]).unwrap();
+    assert_eq!((insn.mnemonic, insn.length), (InsnMnemonic::Inc, 2));
+    let [dst, _] = &insn.translation.insns.last().unwrap().operands;
+    assert_eq!(*dst, Reg::Rcx.operand(OperandSize::B2));
+}
+
+#[test]
+fn push_and_pop_reg_use_operand_size() {
+    let sp = Reg::RspAh.operand(OperandSize::B2);
+    let esp = Reg::RspAh.operand(OperandSize::B4);
+    let ax = Reg::Rax.operand(OperandSize::B2);
+    let eax = Reg::Rax.operand(OperandSize::B4);
+    let cases = [
+        // push ax, in real mode.
+        (real_mode(), &[0x50][..], sp.clone(), ax.clone()),
+        // push eax, in real mode.
+        (real_mode(), &[0x66, 0x50], sp, eax.clone()),
+        // push eax, in protected mode.
+        (protected_mode(), &[0x50], esp.clone(), eax),
+        // push ax, in protected mode.
+        (protected_mode(), &[0x66, 0x50], esp, ax.clone()),
+        // push rax, in long mode.
+        (long_mode(), &[0x50], RSP, RAX),
+        // push ax, in long mode.
+        (long_mode(), &[0x66, 0x50], RSP, ax),
+    ];
+    for (ctx, push_code, sp, value) in cases {
+        let len = Operand::constant(value.size.bytes() as u64, sp.size);
+
+        let mut builder = TranslationBuilder::new();
+        builder.sub(sp.clone(), len.clone());
+        builder.store(sp.clone(), value.clone());
+        assert_eq!(lift(&ctx, push_code), builder.build());
+
+        // the matching pop has the same prefixes, with the opcode of `pop rax`.
+        let mut pop_code = push_code.to_vec();
+        *pop_code.last_mut().unwrap() = 0x58;
+        let mut builder = TranslationBuilder::new();
+        builder.load(value, sp.clone());
+        builder.add(sp, len);
+        assert_eq!(lift(&ctx, &pop_code), builder.build());
+    }
+}
+
+#[test]
+fn push_imm_uses_operand_size() {
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(2, OperandSize::B8));
+    builder.store(RSP, Operand::constant(0x1234, OperandSize::B2));
+    assert_eq!(
+        lift(&long_mode(), &[0x66, 0x68, 0x34, 0x12]),
+        builder.build()
+    );
+
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(8, OperandSize::B8));
+    builder.store(RSP, Operand::negative_constant(1, OperandSize::B8));
+    assert_eq!(lift(&long_mode(), &[0x6a, 0xff]), builder.build());
+}
+
+#[test]
+fn system_call_instructions_lift_to_intrinsics() {
+    for (code, mnemonic, intrinsic) in [
+        ([0x0f, 0x05], InsnMnemonic::Syscall, Intrinsic::Syscall),
+        ([0x0f, 0x07], InsnMnemonic::Sysret, Intrinsic::Sysret),
+        ([0x0f, 0x34], InsnMnemonic::Sysenter, Intrinsic::Sysenter),
+        ([0x0f, 0x35], InsnMnemonic::Sysexit, Intrinsic::Sysexit),
+    ] {
+        let insn = long_mode().decode(&code).unwrap();
+        assert_eq!((insn.mnemonic, insn.length), (mnemonic, 2));
+        let opcodes: Vec<Opcode> = insn
+            .translation
+            .insns
+            .iter()
+            .map(|insn| insn.opcode)
+            .collect();
+        assert_eq!(opcodes, [Opcode::Intrinsic(intrinsic)]);
+        assert!(intrinsic.affects_control_flow());
+    }
+}
+
+#[test]
+fn opcode_0x82_is_an_alias_of_0x80_outside_of_long_mode() {
+    // add al, 0xff
+    for ctx in [real_mode(), protected_mode()] {
+        let alias = ctx.decode(&[0x82, 0xc0, 0xff]).unwrap();
+        let original = ctx.decode(&[0x80, 0xc0, 0xff]).unwrap();
+        assert_eq!(alias.length, 3);
+        assert_eq!(alias.mnemonic, original.mnemonic);
+        assert_eq!(alias.translation, original.translation);
+    }
+    assert_eq!(
+        long_mode().decode(&[0x82, 0xc0, 0xff]),
+        Err(TranslateError::UnsupportedInMode)
+    );
+}
+
+#[test]
+fn nops_are_preserved_only_when_requested() {
+    let preserving = X86Ctx {
+        preserve_nops: true,
+        ..long_mode()
+    };
+    // nop; nop dword [rax+rax*1+0x0]
+    for code in [&[0x90][..], &[0x0f, 0x1f, 0x44, 0x00, 0x00]] {
+        assert_eq!(lift(&long_mode(), code), Translation::new());
+        assert_eq!(
+            lift(&preserving, code),
+            translation_of(&[Insn::new(
+                Opcode::Nop,
+                Operand::constant(code.len() as u64, OperandSize::B1),
+                Operand::zero(OperandSize::B1)
+            )])
+        );
+    }
+}
+
+#[test]
+fn decode_one_within_respects_the_end_of_the_code() {
+    let ctx = long_mode();
+    for code in [
+        // add eax, 0x44332211
+        &[0x05, 0x11, 0x22, 0x33, 0x44][..],
+        // mov eax, [rax+rcx*4+0x10]
+        &[0x8b, 0x44, 0x88, 0x10],
+        // mov rax, [rip+0x10]
+        &[0x48, 0x8b, 0x05, 0x10, 0x00, 0x00, 0x00],
+        // mov word [rbx], 0x1234
+        &[0x66, 0xc7, 0x03, 0x34, 0x12],
+    ] {
+        assert_eq!(
+            ctx.decode_one_within(code)
+                .map(|result| result.bytes_consumed),
+            Ok(code.len())
+        );
+        // only the bytes of the instruction are consumed from a larger budget.
+        let mut budget = code.to_vec();
+        budget.extend([0x90; 4]);
+        assert_eq!(ctx.decode_one_within(&budget), ctx.translate(code));
+        for len in 1..code.len() {
+            assert_eq!(
+                ctx.decode_one_within(&code[..len]),
+                Err(TranslateError::TruncatedInstruction)
+            );
+        }
+        assert_eq!(
+            ctx.decode_one_within(&[]),
+            Err(TranslateError::UnexpectedEof)
+        );
+    }
+}
+
+#[test]
+fn reg_operands_agree_with_named_register_operands() {
+    let qwords = [
+        RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI, R8, R9, R10, R11, R12, R13, R14, R15,
+    ];
+    let bytes = [
+        AL, CL, DL, BL, SPL, BPL, SIL, DIL, R8B, R9B, R10B, R11B, R12B, R13B, R14B, R15B,
+    ];
+    for index in 0..16u8 {
+        let reg = Reg::from_bits(index);
+        assert_eq!(reg.to_bits(), index);
+        assert_eq!(reg.operand(OperandSize::B8), qwords[index as usize]);
+        assert_eq!(reg.operand(OperandSize::B1), bytes[index as usize]);
+        assert_eq!(
+            reg.operand(OperandSize::B4).addr.offset,
+            index as u64 * GPR_STEP
+        );
+    }
+}
+
+#[test]
+fn hint_space_instruction_lengths() {
+    let ctx = long_mode();
+    for second_opcode in 0x18..=0x1f {
+        for (modrm_and_rest, len) in [
+            (&[0x00][..], 3),
+            (&[0x40, 0x00], 4),
+            (&[0x44, 0x00, 0x00], 5),
+            (&[0x80, 0x00, 0x00, 0x00, 0x00], 7),
+            (&[0x84, 0x00, 0x00, 0x00, 0x00, 0x00], 8),
+            (&[0x04, 0x25, 0x00, 0x00, 0x00, 0x00], 8),
+            (&[0xc0], 3),
+        ] {
+            let mut code = vec![0x0f, second_opcode];
+            code.extend_from_slice(modrm_and_rest);
+            assert_eq!(ctx.decode(&code).unwrap().length, len);
+            assert_eq!(
+                ctx.decode(&code[..code.len() - 1]),
+                Err(TranslateError::TruncatedInstruction)
+            );
+        }
+    }
+    // nop word [rax+rax*1+0x0]
+    assert_eq!(
+        ctx.decode(&[0x66, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .unwrap()
+            .length,
+        9
+    );
+    assert_eq!(
+        ctx.decode(&[0x0f, 0x18, 0x08]).unwrap().mnemonic,
+        InsnMnemonic::Prefetcht0
+    );
+}
+
+#[test]
+fn decode_and_lift_returns_mnemonic_translation_and_length() {
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(8, OperandSize::B8));
+    builder.store(RSP, RAX);
+    assert_eq!(
+        long_mode().decode_and_lift(&[0x50, 0x51]),
+        Ok((InsnMnemonic::Push, builder.build(), 1))
+    );
+    assert_eq!(
+        long_mode().decode_and_lift(&[0x0f, 0x05]).unwrap().0,
+        InsnMnemonic::Syscall
+    );
+}
+
+#[test]
+fn legacy_prefixes_round_trip_through_accessors() {
+    for prefix in LegacyPrefix::iter() {
+        let mut prefixes = InsnLegacyPrefixes {
+            by_group: [None; LegacyPrefixGroup::GROUPS_AMOUNT],
+        };
+        prefixes.add(prefix);
+        assert!(prefixes.contains(prefix));
+        for other in LegacyPrefix::iter().filter(|other| *other != prefix) {
+            assert!(!prefixes.contains(other));
+        }
+
+        // add [rax], ecx
+        let insn = long_mode().decode(&[prefix as u8, 0x01, 0x08]).unwrap();
+        assert_eq!(insn.prefixes.legacy, prefixes);
+    }
+
+    let mut prefixes = InsnLegacyPrefixes {
+        by_group: [None; LegacyPrefixGroup::GROUPS_AMOUNT],
+    };
+    prefixes.add(LegacyPrefix::Lock);
+    prefixes.add(LegacyPrefix::FsSegmentOverride);
+    prefixes.add(LegacyPrefix::OperandSizeOverride);
+    assert!(prefixes.has_lock());
+    assert!(!prefixes.has_rep());
+    assert!(!prefixes.has_repnz());
+    assert!(prefixes.has_operand_size_override());
+    assert!(!prefixes.has_address_size_override());
+    assert_eq!(prefixes.segment_override(), Some(Segment::Fs));
+
+    // the last prefix of each group wins.
+    prefixes.add(LegacyPrefix::GsSegmentOverride);
+    assert_eq!(prefixes.segment_override(), Some(Segment::Gs));
+    assert!(!prefixes.contains(LegacyPrefix::FsSegmentOverride));
+}
+
+/// a sink which counts the instructions emitted into it, and collects them.
+#[derive(Default)]
+struct CountingSink {
+    count: usize,
+    insns: Vec<Insn>,
+}
+impl InsnSink for CountingSink {
+    fn emit(&mut self, insn: Insn) {
+        self.count += 1;
+        self.insns.push(insn);
+    }
+}
+
+#[test]
+fn translate_to_sink_emits_the_translated_insns() {
+    // xlat; push rax; mov rax, [rip+0x10]
+    for code in [
+        &[0xd7][..],
+        &[0x50],
+        &[0x48, 0x8b, 0x05, 0x10, 0x00, 0x00, 0x00],
+    ] {
+        let expected = long_mode().translate(code).unwrap();
+        let mut sink = CountingSink::default();
+        assert_eq!(
+            long_mode().translate_to_sink(code, &mut sink),
+            Ok(expected.bytes_consumed)
+        );
+        assert_eq!(sink.count, expected.translation.insns.len());
+        assert_eq!(sink.insns.as_slice(), expected.translation.insns.as_slice());
+
+        let mut translation = Translation::new();
+        long_mode()
+            .translate_to_sink(code, &mut translation)
+            .unwrap();
+        assert_eq!(translation, expected.translation);
+    }
+}
+
+#[test]
+fn rep_prefixed_instructions_are_recognized() {
+    for (code, mnemonic, intrinsic) in [
+        (&[0xf3, 0x90][..], InsnMnemonic::Pause, Intrinsic::Pause),
+        (
+            &[0xf3, 0x0f, 0x1e, 0xfa],
+            InsnMnemonic::Endbr64,
+            Intrinsic::Endbr64,
+        ),
+        (
+            &[0xf3, 0x0f, 0x1e, 0xfb],
+            InsnMnemonic::Endbr32,
+            Intrinsic::Endbr32,
+        ),
+    ] {
+        let (decoded_mnemonic, translation, len) = long_mode().decode_and_lift(code).unwrap();
+        assert_eq!((decoded_mnemonic, len), (mnemonic, code.len()));
+        assert_eq!(translation.insns.len(), 1);
+        assert_eq!(translation.insns[0].opcode, Opcode::Intrinsic(intrinsic));
+    }
+    // without the rep prefix, these are nops.
+    for code in [&[0x90][..], &[0x0f, 0x1e, 0xfa]] {
+        assert_eq!(
+            long_mode().decode_and_lift(code),
+            Ok((InsnMnemonic::Nop, Translation::new(), code.len()))
+        );
+    }
+}
+
+/// returns the range of register space offsets covered by the given register operand.
+fn reg_bytes(operand: &Operand) -> core::ops::Range<u64> {
+    assert_eq!(operand.addr.space, OperandSpace::Regs);
+    operand.addr.offset..operand.addr.offset + operand.size.bytes() as u64
+}
+
+fn overlaps(a: &Operand, b: &Operand) -> bool {
+    let (a, b) = (reg_bytes(a), reg_bytes(b));
+    a.start < b.end && b.start < a.end
+}
+
+#[test]
+fn flag_operands_overlap_neither_each_other_nor_gprs() {
+    let flags = [
+        FLAG_CF, FLAG_PF, FLAG_AF, FLAG_ZF, FLAG_SF, FLAG_OF, FLAG_DF,
+    ];
+    for (i, flag_operand) in flags.iter().enumerate() {
+        assert_eq!(flag_operand.size, OperandSize::B1);
+        for other in &flags[i + 1..] {
+            assert!(!overlaps(flag_operand, other));
+        }
+        for index in 0..16u8 {
+            assert!(!overlaps(
+                flag_operand,
+                &Reg::from_bits(index).operand(OperandSize::B8)
+            ));
+        }
+    }
+    for flag_kind in Flag::iter() {
+        assert_eq!(flag(flag_kind), flags[flag_kind as usize]);
+    }
+}
+
+#[test]
+fn mov_to_and_from_special_registers_lifts_to_intrinsics() {
+    let zero = Operand::zero(OperandSize::B1);
+    for (code, intrinsic, gpr) in [
+        // mov cr3, rax
+        (&[0x0f, 0x22, 0xd8][..], Intrinsic::WriteControlReg(3), RAX),
+        // mov rax, cr2
+        (&[0x0f, 0x20, 0xd0], Intrinsic::ReadControlReg(2), RAX),
+        // mov cr8, rcx
+        (
+            &[0x44, 0x0f, 0x22, 0xc1],
+            Intrinsic::WriteControlReg(8),
+            RCX,
+        ),
+        // mov r9, dr7
+        (&[0x41, 0x0f, 0x21, 0xf9], Intrinsic::ReadDebugReg(7), R9),
+        // mov dr0, rdx
+        (&[0x0f, 0x23, 0xc2], Intrinsic::WriteDebugReg(0), RDX),
+    ] {
+        assert_eq!(
+            lift(&long_mode(), code),
+            translation_of(&[Insn::new(Opcode::Intrinsic(intrinsic), gpr, zero.clone())])
+        );
+    }
+    // mov eax, cr0, outside of long mode.
+    assert_eq!(
+        lift(&protected_mode(), &[0x0f, 0x20, 0xc0]),
+        translation_of(&[Insn::new(
+            Opcode::Intrinsic(Intrinsic::ReadControlReg(0)),
+            Reg::Rax.operand(OperandSize::B4),
+            zero
+        )])
+    );
+}
+
+#[test]
+fn decoded_insn_fields_of_push_r9() {
+    let insn = long_mode().decode(&[0x66, 0x41, 0x51]).unwrap();
+    assert_eq!(insn.length, 3);
+    assert_eq!(insn.mnemonic, InsnMnemonic::Push);
+    assert!(insn.prefixes.legacy.has_operand_size_override());
+    let rex = insn.prefixes.rex.unwrap();
+    assert!(rex.b_bit() && !rex.w_bit() && !rex.r_bit() && !rex.x_bit());
+
+    let mut builder = TranslationBuilder::new();
+    builder.sub(RSP, Operand::constant(2, OperandSize::B8));
+    builder.store(RSP, Reg::R9.operand(OperandSize::B2));
+    assert_eq!(insn.translation, builder.build());
+}
+
+#[test]
+fn modrm_fields_of_mov_rax_rbx() {
+    // mov rax, rbx
+    let code = [0x48, 0x89, 0xd8];
+    let insn = long_mode().decode(&code).unwrap();
+    let rex = insn.prefixes.rex.unwrap();
+    assert!(rex.w_bit());
+
+    let modrm = ModRm::from_bits(code[2]);
+    assert!(modrm.is_rm_reg());
+    assert_eq!(modrm.mod_bits().0, 0b11);
+    assert_eq!(modrm.effective_reg(Some(rex)), Reg::Rbx as u8);
+    assert_eq!(modrm.effective_rm(Some(rex)), Reg::Rax as u8);
+    // the r and b bits of the rex prefix extend the fields to r8-r15.
+    let extending_rex = RexPrefix::from_bits(0b0101);
+    assert_eq!(modrm.effective_reg(Some(extending_rex)), Reg::R11 as u8);
+    assert_eq!(modrm.effective_rm(Some(extending_rex)), Reg::R8 as u8);
+
+    assert_eq!(
+        insn.translation,
+        translation_of(&[Insn::new(Opcode::Move, RAX, RBX)])
+    );
+}
+
+#[test]
+fn immediates_after_opcode() {
+    // add eax, 0x11223344
+    let insn = long_mode()
+        .decode(&[0x05, 0x44, 0x33, 0x22, 0x11, 0xcc])
+        .unwrap();
+    assert_eq!(insn.length, 5);
+    assert!(insn
+        .translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Add
+            && insn.operands[1] == Operand::constant(0x11223344, OperandSize::B4)));
+
+    // mov al, 0x7f
+    assert_eq!(
+        lift(&long_mode(), &[0xb0, 0x7f]),
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            AL,
+            Operand::constant(0x7f, OperandSize::B1)
+        )])
+    );
+
+    // add rax, -1, where the immediate is sign extended to 64 bits.
+    let insn = long_mode()
+        .decode(&[0x48, 0x05, 0xff, 0xff, 0xff, 0xff])
+        .unwrap();
+    assert_eq!(insn.length, 6);
+    assert!(insn
+        .translation
+        .insns
+        .iter()
+        .any(|insn| insn.opcode == Opcode::Add
+            && insn.operands[1] == Operand::constant(u64::MAX, OperandSize::B8)));
+
+    assert_eq!(
+        long_mode().decode(&[0x05, 0x44, 0x33]),
+        Err(TranslateError::TruncatedInstruction)
+    );
+}
+
+#[test]
+fn translate_returns_errors_instead_of_panicking() {
+    let ctx = long_mode();
+    assert_eq!(ctx.translate(&[]), Err(TranslateError::UnexpectedEof));
+    assert_eq!(ctx.decode(&[]), Err(TranslateError::UnexpectedEof));
+    assert_eq!(
+        ctx.translate(&[0x66]),
+        Err(TranslateError::TruncatedInstruction)
+    );
+    assert_eq!(
+        ctx.translate(&[0x0f]),
+        Err(TranslateError::TruncatedInstruction)
+    );
+    assert_eq!(
+        ctx.translate(&[0xd6]),
+        Err(TranslateError::UnknownOpcode(0xd6))
+    );
+    assert_eq!(
+        ctx.translate(&[0x0f, 0xff]),
+        Err(TranslateError::UnknownOpcode(0xff))
+    );
+}
+
+#[test]
+fn bytes_consumed_of_consecutive_insns() {
+    // add ax, 0x2211; mov r8b, 1
+    let code = [0x66, 0x05, 0x11, 0x22, 0x41, 0xb0, 0x01];
+    let first = long_mode().translate(&code).unwrap();
+    assert_eq!(first.bytes_consumed, 4);
+    let second = long_mode()
+        .translate(&code[first.bytes_consumed..])
+        .unwrap();
+    assert_eq!(second.bytes_consumed, 3);
+    assert_eq!(
+        second.translation,
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            R8B,
+            Operand::constant(1, OperandSize::B1)
+        )])
+    );
+}
+
+#[test]
+fn push_decrements_stack_pointer_with_sub() {
+    for ctx in [real_mode(), protected_mode(), long_mode()] {
+        let translation = lift(&ctx, &[0x50]);
+        let opcodes: Vec<Opcode> = translation.insns.iter().map(|insn| insn.opcode).collect();
+        assert_eq!(opcodes, [Opcode::Sub, Opcode::Store]);
+    }
+}
+
+#[test]
+fn mov_reg_from_memory_lifts_to_load() {
+    // mov rcx, [rdx]
+    assert_eq!(
+        lift(&long_mode(), &[0x48, 0x8b, 0x0a]),
+        translation_of(&[
+            Insn::new(Opcode::Move, Operand::tmp(0, OperandSize::B8), RDX),
+            Insn::new(Opcode::Load, RCX, Operand::tmp(0, OperandSize::B8)),
+        ])
+    );
+    // mov eax, [rdx+rcx*4+0x10]
+    let translation = lift(&long_mode(), &[0x8b, 0x44, 0x8a, 0x10]);
+    let load = &translation.insns[translation.insns.len() - 2];
+    assert_eq!(load.opcode, Opcode::Load);
+    assert_eq!(load.operands[0], Reg::Rax.operand(OperandSize::B4));
+    assert_eq!(load.operands[1].size, OperandSize::B8);
+    // the upper half of rax is zeroed.
+    assert_eq!(
+        translation.insns.last().unwrap(),
+        &Insn::new(
+            Opcode::Move,
+            Operand::reg(4, OperandSize::B4),
+            Operand::zero(OperandSize::B4)
+        )
+    );
+}
+
+#[test]
+fn pop_reg_loads_then_increments_stack_pointer() {
+    assert_eq!(
+        lift(&long_mode(), &[0x58]),
+        translation_of(&[
+            Insn::new(Opcode::Load, RAX, RSP),
+            Insn::new(Opcode::Add, RSP, Operand::constant(8, OperandSize::B8)),
+        ])
+    );
+    assert_eq!(
+        lift(&long_mode(), &[0x41, 0x59]),
+        translation_of(&[
+            Insn::new(Opcode::Load, R9, RSP),
+            Insn::new(Opcode::Add, RSP, Operand::constant(8, OperandSize::B8)),
+        ])
+    );
+    // pop rsp only loads the new stack pointer.
+    assert_eq!(
+        lift(&long_mode(), &[0x5c]),
+        translation_of(&[Insn::new(Opcode::Load, RSP, RSP)])
+    );
+}
+
+#[test]
+fn two_byte_opcodes_are_dispatched() {
+    let ctx = long_mode();
+    for code in [&[0x0f, 0x1f, 0x00][..], &[0x0f, 0x1f, 0xc0]] {
+        let decoded = ctx.decode(code).unwrap();
+        assert_eq!(decoded.length, code.len());
+        assert_eq!(decoded.translation, Translation::new());
+    }
+    // the escape byte alone is not an instruction.
+    assert!(ctx.decode(&[0x0f]).is_err());
+    assert!(ctx.decode(&[0x0f, 0xff]).is_err());
+}
+
+#[test]
+fn mov_between_registers_and_memory() {
+    let ctx = long_mode();
+    let addr = Operand::tmp(0, OperandSize::B8);
+    // mov rbx, rax
+    assert_eq!(
+        lift(&ctx, &[0x48, 0x89, 0xc3]),
+        translation_of(&[Insn::new(Opcode::Move, RBX, RAX)])
+    );
+    // mov [rax], rbx
+    assert_eq!(
+        lift(&ctx, &[0x48, 0x89, 0x18]),
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), RAX),
+            Insn::new(Opcode::Store, addr.clone(), RBX),
+        ])
+    );
+    // mov rcx, [rdx]
+    assert_eq!(
+        lift(&ctx, &[0x48, 0x8b, 0x0a]),
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), RDX),
+            Insn::new(Opcode::Load, RCX, addr.clone()),
+        ])
+    );
+    // mov ah, al
+    assert_eq!(
+        lift(&ctx, &[0x88, 0xc4]),
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            Operand::reg(1, OperandSize::B1),
+            AL
+        )])
+    );
+    // mov cl, [rdx]
+    assert_eq!(
+        lift(&ctx, &[0x8a, 0x0a]),
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), RDX),
+            Insn::new(Opcode::Load, CL, addr),
+        ])
+    );
+}
+
+#[test]
+fn alu_rm_reg_operations() {
+    let ctx = long_mode();
+    let eax = Reg::Rax.operand(OperandSize::B4);
+    let ecx = Reg::Rcx.operand(OperandSize::B4);
+    let result = Operand::tmp(0, OperandSize::B4);
+    // add eax, ecx
+    let translation = lift(&ctx, &[0x01, 0xc8]);
+    assert_eq!(
+        translation.insns[..2],
+        [
+            Insn::new(Opcode::Move, result.clone(), eax.clone()),
+            Insn::new(Opcode::Add, result.clone(), ecx),
+        ]
+    );
+    assert_eq!(
+        translation.insns[translation.insns.len() - 2..],
+        [
+            Insn::new(Opcode::Move, eax, result),
+            Insn::new(
+                Opcode::Move,
+                Operand::reg(4, OperandSize::B4),
+                Operand::zero(OperandSize::B4)
+            ),
+        ]
+    );
+    // xor rax, rax
+    let translation = lift(&ctx, &[0x48, 0x31, 0xc0]);
+    assert_eq!(translation.insns[0], Insn::new(Opcode::Xor, RAX, RAX));
+    assert!(translation.insns[1..]
+        .iter()
+        .all(|insn| insn.operands[0].addr.offset >= FLAGS_OFFSET));
+}
+
+#[test]
+fn flags_region_lies_between_gprs_and_segment_bases() {
+    let gprs_end = reg_bytes(&R15).end;
+    for flag_kind in Flag::iter() {
+        let flag_bytes = reg_bytes(&flag(flag_kind));
+        assert_eq!(flag_bytes.start, FLAGS_OFFSET + flag_kind as u64);
+        assert!(flag_bytes.start >= gprs_end);
+        assert!(flag_bytes.end <= SEGMENT_BASES_OFFSET);
+    }
+}
+
+#[test]
+fn conditional_jumps_branch_relative_to_the_next_insn() {
+    let ctx = long_mode();
+    let zf_clear = Operand::tmp(0, OperandSize::B1);
+    // jz +5
+    let decoded = ctx.decode_at(&[0x74, 0x05], 0x1000).unwrap();
+    assert_eq!(decoded.length, 2);
+    assert_eq!(
+        decoded.translation,
+        translation_of(&[Insn::new(
+            Opcode::Branch,
+            FLAG_ZF,
+            Operand::constant(0x1007, OperandSize::B8)
+        )])
+    );
+    // jne -6
+    let decoded = ctx
+        .decode_at(&[0x0f, 0x85, 0xfa, 0xff, 0xff, 0xff], 0x1000)
+        .unwrap();
+    assert_eq!(decoded.length, 6);
+    assert_eq!(
+        decoded.translation,
+        translation_of(&[
+            Insn::new(Opcode::Move, zf_clear.clone(), FLAG_ZF),
+            Insn::new(
+                Opcode::Xor,
+                zf_clear.clone(),
+                Operand::constant(1, OperandSize::B1)
+            ),
+            Insn::new(
+                Opcode::Branch,
+                zf_clear,
+                Operand::constant(0x1000, OperandSize::B8)
+            ),
+        ])
+    );
+}
+
+#[test]
+fn translate_at_resolves_relative_jump_targets() {
+    let ctx = long_mode();
+    // jmp $+0x12
+    let result = ctx.translate_at(&[0xeb, 0x10], 0x1000).unwrap();
+    assert_eq!(result.bytes_consumed, 2);
+    assert_eq!(
+        result.translation,
+        translation_of(&[Insn::new(
+            Opcode::Jump,
+            Operand::constant(0x1012, OperandSize::B8),
+            Operand::zero(OperandSize::B1)
+        )])
+    );
+    // translating without an address is the same as translating at address 0.
+    assert_eq!(
+        ctx.translate(&[0xeb, 0x10]).unwrap().translation.insns[0].operands[0],
+        Operand::constant(0x12, OperandSize::B8)
+    );
+}
+
+#[test]
+fn rip_relative_addresses_are_resolved_to_constants() {
+    let addr = Operand::tmp(0, OperandSize::B8);
+    // mov rax, [rip+0x10]
+    let decoded = long_mode()
+        .decode_at(&[0x48, 0x8b, 0x05, 0x10, 0x00, 0x00, 0x00], 0x1000)
+        .unwrap();
+    assert_eq!(
+        decoded.translation,
+        translation_of(&[
+            Insn::new(
+                Opcode::Move,
+                addr.clone(),
+                Operand::constant(0x1017, OperandSize::B8)
+            ),
+            Insn::new(Opcode::Load, RAX, addr),
+        ])
+    );
+    // outside of long mode, the same encoding is an absolute address.
+    let decoded = protected_mode()
+        .decode_at(&[0x8b, 0x05, 0x10, 0x00, 0x00, 0x00], 0x1000)
+        .unwrap();
+    let addr = Operand::tmp(0, OperandSize::B4);
+    assert_eq!(
+        decoded.translation,
+        translation_of(&[
+            Insn::new(Opcode::Move, addr.clone(), Operand::zero(OperandSize::B4)),
+            Insn::new(
+                Opcode::Add,
+                addr.clone(),
+                Operand::constant(0x10, OperandSize::B4)
+            ),
+            Insn::new(Opcode::Load, Reg::Rax.operand(OperandSize::B4), addr),
+        ])
+    );
+}
+
+#[test]
+fn lea_computes_the_address_without_accessing_memory() {
+    let ctx = long_mode();
+    // lea rax, [rbx+rcx*8+0x20]
+    let translation = lift(&ctx, &[0x48, 0x8d, 0x44, 0xcb, 0x20]);
+    assert!(translation
+        .insns
+        .iter()
+        .all(|insn| !matches!(insn.opcode, Opcode::Load | Opcode::Store)));
+    assert_eq!(
+        translation.insns.last().unwrap(),
+        &Insn::new(Opcode::Move, RAX, Operand::tmp(0, OperandSize::B8))
+    );
+    // lea eax, [rbx]
+    assert_eq!(
+        lift(&ctx, &[0x8d, 0x03]),
+        translation_of(&[
+            Insn::new(Opcode::Move, Operand::tmp(0, OperandSize::B8), RBX),
+            Insn::new(
+                Opcode::Move,
+                Reg::Rax.operand(OperandSize::B4),
+                Operand::tmp(0, OperandSize::B4)
+            ),
+            Insn::new(
+                Opcode::Move,
+                Operand::reg(4, OperandSize::B4),
+                Operand::zero(OperandSize::B4)
+            ),
+        ])
+    );
+}
+
+#[test]
+fn unconditional_jumps() {
+    let ctx = long_mode();
+    let unused = Operand::zero(OperandSize::B1);
+    // jmp $
+    assert_eq!(
+        ctx.decode_at(&[0xeb, 0xfe], 0x1000).unwrap().translation,
+        translation_of(&[Insn::new(
+            Opcode::Jump,
+            Operand::constant(0x1000, OperandSize::B8),
+            unused.clone()
+        )])
+    );
+    // jmp $+0x15
+    assert_eq!(
+        ctx.decode_at(&[0xe9, 0x10, 0x00, 0x00, 0x00], 0x1000)
+            .unwrap()
+            .translation,
+        translation_of(&[Insn::new(
+            Opcode::Jump,
+            Operand::constant(0x1015, OperandSize::B8),
+            unused.clone()
+        )])
+    );
+    // jmp rax
+    assert_eq!(
+        lift(&ctx, &[0xff, 0xe0]),
+        translation_of(&[Insn::new(Opcode::Jump, RAX, unused.clone())])
+    );
+    // jmp qword [rax]
+    assert_eq!(
+        lift(&ctx, &[0xff, 0x20]),
+        translation_of(&[
+            Insn::new(Opcode::Move, Operand::tmp(0, OperandSize::B8), RAX),
+            Insn::new(
+                Opcode::Load,
+                Operand::tmp(8, OperandSize::B8),
+                Operand::tmp(0, OperandSize::B8)
+            ),
+            Insn::new(Opcode::Jump, Operand::tmp(8, OperandSize::B8), unused),
+        ])
+    );
+}
+
+#[test]
+fn call_pushes_return_address_and_ret_pops_it() {
+    let ctx = long_mode();
+    let unused = Operand::zero(OperandSize::B1);
+    // call $+0x15
+    assert_eq!(
+        ctx.decode_at(&[0xe8, 0x10, 0x00, 0x00, 0x00], 0x1000)
+            .unwrap()
+            .translation,
+        translation_of(&[
+            Insn::new(Opcode::Sub, RSP, Operand::constant(8, OperandSize::B8)),
+            Insn::new(
+                Opcode::Store,
+                RSP,
+                Operand::constant(0x1005, OperandSize::B8)
+            ),
+            Insn::new(
+                Opcode::Call,
+                Operand::constant(0x1015, OperandSize::B8),
+                unused.clone()
+            ),
+        ])
+    );
+    // ret
+    let return_addr = Operand::tmp(0, OperandSize::B8);
+    assert_eq!(
+        lift(&ctx, &[0xc3]),
+        translation_of(&[
+            Insn::new(Opcode::Load, return_addr.clone(), RSP),
+            Insn::new(Opcode::Add, RSP, Operand::constant(8, OperandSize::B8)),
+            Insn::new(Opcode::Return, return_addr, unused),
+        ])
+    );
+}
+
+#[test]
+fn repeated_prefixes_of_a_group_resolve_to_the_last_one() {
+    let ctx = long_mode();
+    // add gs:[rax], eax with redundant prefixes
+    let decoded = ctx.decode(&[0x66, 0x66, 0x64, 0x65, 0x01, 0xc0]).unwrap();
+    assert_eq!(decoded.length, 6);
+    assert!(decoded.prefixes.legacy.has_operand_size_override());
+    assert_eq!(
+        decoded.prefixes.legacy.segment_override(),
+        Some(Segment::Gs)
+    );
+    // add ax, ax
+    assert_eq!(
+        lift(&ctx, &[0x66, 0x66, 0x01, 0xc0]),
+        lift(&ctx, &[0x66, 0x01, 0xc0])
+    );
+
+    let mut prefixes = InsnLegacyPrefixes {
+        by_group: [None; LegacyPrefixGroup::GROUPS_AMOUNT],
+    };
+    prefixes.add(LegacyPrefix::Repnz);
+    prefixes.add(LegacyPrefix::RepOrRepz);
+    assert!(prefixes.has_rep());
+    assert!(!prefixes.has_repnz());
+}
+
+#[test]
+fn insns_longer_than_15_bytes_are_rejected() {
+    let ctx = long_mode();
+    let mut code = [0x66; 17];
+    code[16] = 0x90;
+    assert_eq!(ctx.translate(&code), Err(TranslateError::TooLong));
+    assert_eq!(ctx.translate(&code[..16]), Err(TranslateError::TooLong));
+    // 14 prefixes and a nop are exactly 15 bytes.
+    assert_eq!(ctx.translate(&code[2..]).unwrap().bytes_consumed, 15);
+
+    // all parts of the instruction count towards the limit.
+    // lea rax, [rax+rcx*4+0x04030201] with 11 redundant cs prefixes, which is 19 bytes.
+    let mut code = [0x2e; 19];
+    code[11..].copy_from_slice(&[0x48, 0x8d, 0x84, 0x88, 0x01, 0x02, 0x03, 0x04]);
+    assert_eq!(ctx.translate(&code), Err(TranslateError::TooLong));
+    assert_eq!(ctx.translate(&code[4..]).unwrap().bytes_consumed, 15);
+}
+
+fn ctx_of(cpu_mode: X86CpuMode, default_size: X86SegmentDefaultOperandSize) -> X86Ctx {
+    X86Ctx {
+        cpu_mode,
+        code_segment_default_operand_size: default_size,
+        preserve_nops: false,
+    }
+}
+
+/// encodes the given instruction with an optional size override prefix and an optional rex prefix with the `W` bit set.
+fn with_prefixes(override_prefix: Option<u8>, rex_w: bool, insn: &[u8]) -> Vec<u8> {
+    let mut code: Vec<u8> = override_prefix.into_iter().collect();
+    if rex_w {
+        code.push(0x48);
+    }
+    code.extend_from_slice(insn);
+    code
+}
+
+#[test]
+fn resolved_operand_sizes() {
+    use OperandSize::*;
+    use X86CpuMode::*;
+    use X86SegmentDefaultOperandSize as D;
+    // (cpu mode, D flag of the code segment, operand size override, rex.w, operand size)
+    let cases = [
+        (RealMode, D::B16, false, false, B2),
+        (RealMode, D::B16, true, false, B4),
+        (RealMode, D::B32, false, false, B2),
+        (RealMode, D::B32, true, false, B4),
+        (ProtectedMode, D::B16, false, false, B2),
+        (ProtectedMode, D::B16, true, false, B4),
+        (ProtectedMode, D::B32, false, false, B4),
+        (ProtectedMode, D::B32, true, false, B2),
+        (LongMode, D::B16, false, false, B4),
+        (LongMode, D::B16, true, false, B2),
+        (LongMode, D::B16, false, true, B8),
+        (LongMode, D::B16, true, true, B8),
+        (LongMode, D::B32, false, false, B4),
+        (LongMode, D::B32, true, false, B2),
+        (LongMode, D::B32, false, true, B8),
+        (LongMode, D::B32, true, true, B8),
+    ];
+    for (cpu_mode, default_size, operand_size_override, rex_w, expected) in cases {
+        // mov eax, ecx
+        let code = with_prefixes(operand_size_override.then_some(0x66), rex_w, &[0x89, 0xc8]);
+        let translation = lift(&ctx_of(cpu_mode, default_size), &code);
+        assert_eq!(
+            translation.insns[0],
+            Insn::new(
+                Opcode::Move,
+                Reg::Rax.operand(expected),
+                Reg::Rcx.operand(expected)
+            ),
+            "{:02x?}",
+            code
+        );
+    }
+}
+
+#[test]
+fn resolved_address_sizes() {
+    use OperandSize::*;
+    use X86CpuMode::*;
+    use X86SegmentDefaultOperandSize as D;
+    // (cpu mode, D flag of the code segment, address size override, rex.w, address size)
+    let cases = [
+        (RealMode, D::B16, false, false, B2),
+        (RealMode, D::B16, true, false, B4),
+        (RealMode, D::B32, false, false, B2),
+        (RealMode, D::B32, true, false, B4),
+        (ProtectedMode, D::B16, false, false, B2),
+        (ProtectedMode, D::B16, true, false, B4),
+        (ProtectedMode, D::B32, false, false, B4),
+        (ProtectedMode, D::B32, true, false, B2),
+        (LongMode, D::B16, false, false, B8),
+        (LongMode, D::B16, true, false, B4),
+        (LongMode, D::B16, false, true, B8),
+        (LongMode, D::B16, true, true, B4),
+        (LongMode, D::B32, false, false, B8),
+        (LongMode, D::B32, true, false, B4),
+        (LongMode, D::B32, false, true, B8),
+        (LongMode, D::B32, true, true, B4),
+    ];
+    for (cpu_mode, default_size, address_size_override, rex_w, expected) in cases {
+        // lea eax, [rsi+0x10], which is lea ax, [bp+0x10] with 16 bit addressing.
+        let code = with_prefixes(
+            address_size_override.then_some(0x67),
+            rex_w,
+            &[0x8d, 0x46, 0x10],
+        );
+        let translation = lift(&ctx_of(cpu_mode, default_size), &code);
+        let addr = &translation.insns[0].operands[0];
+        assert_eq!(addr.addr.space, OperandSpace::Tmp, "{:02x?}", code);
+        assert_eq!(addr.size, expected, "{:02x?}", code);
+    }
+}
+
+#[test]
+fn decoded_insn_metadata() {
+    let ctx = long_mode();
+    let insn = ctx.decode_at(&[0x50], 0x1000).unwrap();
+    assert_eq!(insn.length, 1);
+    assert_eq!(insn.mnemonic.name(), "push");
+    assert_eq!(insn.prefixes.rex, None);
+
+    // nop dword [rax+rax*1+0x0]
+    let insn = ctx.decode(&[0x0f, 0x1f, 0x44, 0x00, 0x00]).unwrap();
+    assert_eq!(insn.length, 5);
+    assert_eq!(insn.mnemonic.name(), "nop");
+
+    for (code, name) in [
+        (&[0xc3][..], "ret"),
+        (&[0xf3, 0x0f, 0x1e, 0xfa], "endbr64"),
+        (&[0x0f, 0x18, 0x00], "prefetchnta"),
+    ] {
+        assert_eq!(ctx.decode(code).unwrap().mnemonic.name(), name);
+    }
+}
+
+#[test]
+fn lifted_translations_are_valid() {
+    for ctx in [real_mode(), protected_mode(), long_mode()] {
+        for prefix in [
+            None,
+            Some(0x66),
+            Some(0x67),
+            Some(0x48),
+            Some(0x64),
+            Some(0x0f),
+        ] {
+            for opcode in 0..=u8::MAX {
+                for modrm in [0x00, 0x04, 0x05, 0x44, 0x84, 0xc1] {
+                    let mut code: Vec<u8> = prefix.into_iter().collect();
+                    code.extend_from_slice(&[opcode, modrm, 0x24, 0x11, 0x22, 0x33, 0x44, 0x55]);
+                    if let Ok(result) = ctx.translate(&code) {
+                        assert_eq!(result.translation.validate(), Ok(()), "{:02x?}", code);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn canonical_nop_encodings() {
+    let ctx = long_mode();
+    let nops: [&[u8]; 11] = [
+        &[0x90],
+        &[0x66, 0x90],
+        &[0x0f, 0x1f, 0x00],
+        &[0x0f, 0x1f, 0x40, 0x00],
+        &[0x0f, 0x1f, 0x44, 0x00, 0x00],
+        &[0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00],
+        &[0x0f, 0x1f, 0x80, 0x00, 0x00, 0x00, 0x00],
+        &[0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+        &[0x66, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+        &[0x66, 0x2e, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0x90],
+    ];
+    for nop in nops {
+        // followed by a ret, to check that only the nop is consumed.
+        let mut code = nop.to_vec();
+        code.push(0xc3);
+        let insn = ctx.decode(&code).unwrap();
+        assert_eq!(insn.length, nop.len(), "{:02x?}", nop);
+        assert_eq!(insn.mnemonic, InsnMnemonic::Nop, "{:02x?}", nop);
+        assert_eq!(insn.translation, Translation::new(), "{:02x?}", nop);
+    }
+    // xchg r8, rax is not a nop.
+    assert_ne!(
+        ctx.decode(&[0x41, 0x90]).unwrap().mnemonic,
+        InsnMnemonic::Nop
+    );
+}
+
+#[test]
+fn operand_constructors() {
+    let rcx = Reg::Rcx.operand(OperandSize::B8);
+    assert_eq!(rcx.addr.offset, 8);
+    assert_eq!(rcx, RCX);
+    assert_eq!(Operand::reg(8, OperandSize::B8), RCX);
+    assert_eq!(Operand::x86_reg(Reg::Rcx, OperandSize::B8).addr.offset, 8);
+    assert_eq!(Operand::x86_reg(Reg::Rcx, OperandSize::B8), RCX);
+    assert_eq!(
+        Reg::R9.operand(OperandSize::B2),
+        Operand::reg(9 * GPR_STEP, OperandSize::B2)
+    );
+
+    let ram = Operand::ram(0x10, OperandSize::B2);
+    assert_eq!(ram.addr.space, OperandSpace::Ram);
+    assert_eq!(ram.addr.offset, 0x10);
+    assert_eq!(ram.size, OperandSize::B2);
+    assert_eq!(
+        Operand::tmp(4, OperandSize::B4).addr.space,
+        OperandSpace::Tmp
+    );
+    assert_eq!(
+        Operand::zero(OperandSize::B1),
+        Operand::constant(0, OperandSize::B1)
+    );
+}
+
+#[test]
+fn annotated_display_names_registers_and_signed_constants() {
+    let reg_file = Some(&REG_FILE);
+    assert_eq!(RSP.display_annotated(reg_file).to_string(), "rsp");
+    assert_eq!(
+        Reg::R9
+            .operand(OperandSize::B4)
+            .display_annotated(reg_file)
+            .to_string(),
+        "r9d"
+    );
+    assert_eq!(FLAG_DF.display_annotated(reg_file).to_string(), "df");
+    assert_eq!(GS_BASE.display_annotated(reg_file).to_string(), "gs_base");
+    // the raw format is kept when there is no register file, or no register matches the operand exactly.
+    assert_eq!(RSP.display_annotated(None).to_string(), "Regs[0x20]:8");
+    assert_eq!(
+        Operand::reg(0x21, OperandSize::B1)
+            .display_annotated(reg_file)
+            .to_string(),
+        "Regs[0x21]:1"
+    );
+
+    let minus_eight = Operand::negative_constant(8, OperandSize::B8);
+    assert_eq!(minus_eight.display_annotated(reg_file).to_string(), "-8:8");
+    assert_eq!(minus_eight.to_string(), "Const[0xfffffffffffffff8]:8");
+    assert_eq!(
+        Operand::constant(0x10, OperandSize::B4)
+            .display_annotated(None)
+            .to_string(),
+        "0x10:4"
+    );
+}
+
+#[test]
+fn reg_file_describes_the_register_layout() {
+    let reg_file = long_mode().reg_file();
+    let rsp = reg_file.reg_at(0x20).unwrap();
+    assert_eq!(rsp.name, "rsp");
+    assert_eq!(rsp.operand(), RSP);
+    assert_eq!(
+        reg_file.reg_by_name("eax").unwrap().operand(),
+        Reg::Rax.operand(OperandSize::B4)
+    );
+    assert_eq!(reg_file.reg_by_name("zf").unwrap().operand(), FLAG_ZF);
+    assert_eq!(reg_file.reg_by_name("fs_base").unwrap().operand(), FS_BASE);
+    assert_eq!(reg_file.reg_by_name("xmm0"), None);
+    // the high byte registers don't start a register at their offset.
+    assert_eq!(reg_file.reg_at(1), None);
+}
+
+#[test]
+fn xchg_with_accumulator_mnemonics() {
+    let ctx = long_mode();
+    assert_eq!(
+        ctx.decode(&[0x48, 0x91]).unwrap().mnemonic,
+        InsnMnemonic::Xchg
+    );
+    // xchg r8, rax
+    assert_eq!(
+        ctx.decode(&[0x49, 0x90]).unwrap().mnemonic,
+        InsnMnemonic::Xchg
+    );
+    assert_eq!(ctx.decode(&[0x90]).unwrap().mnemonic, InsnMnemonic::Nop);
+}
+
+#[test]
+fn lock_prefix_marks_memory_accesses_atomic() {
+    let ctx = long_mode();
+    // lock xadd [rax], rbx
+    let insn = ctx.decode(&[0xf0, 0x48, 0x0f, 0xc1, 0x18]).unwrap();
+    assert_eq!(insn.mnemonic, InsnMnemonic::Xadd);
+    let is_mem_access = |insn: &&Insn| matches!(insn.opcode, Opcode::Load | Opcode::Store);
+    assert_eq!(
+        insn.translation.insns.iter().filter(is_mem_access).count(),
+        2
+    );
+    for ir_insn in &insn.translation.insns {
+        assert_eq!(ir_insn.is_atomic, is_mem_access(&ir_insn), "{}", ir_insn);
+    }
+
+    // without the prefix, nothing is atomic.
+    // xadd [rax], rbx
+    let insn = ctx.decode(&[0x48, 0x0f, 0xc1, 0x18]).unwrap();
+    assert!(insn.translation.insns.iter().all(|insn| !insn.is_atomic));
+
+    // xchg with memory is atomic.
+    // lock xchg [rax], ecx
+    let insn = ctx.decode(&[0xf0, 0x87, 0x08]).unwrap();
+    assert!(insn.translation.insns.iter().any(|insn| insn.is_atomic));
+}
+
+#[test]
+fn lock_prefix_requires_a_memory_destination() {
+    let ctx = long_mode();
+    // lock mov rax, rbx
+    assert_eq!(
+        ctx.decode(&[0xf0, 0x48, 0x89, 0xd8]),
+        Err(TranslateError::InvalidLock)
+    );
+    // lock add rax, rbx
+    assert_eq!(
+        ctx.decode(&[0xf0, 0x48, 0x01, 0xd8]),
+        Err(TranslateError::InvalidLock)
+    );
+    // lock mov [rax], rbx
+    assert_eq!(
+        ctx.decode(&[0xf0, 0x48, 0x89, 0x18]),
+        Err(TranslateError::InvalidLock)
+    );
+}
+
+#[test]
+fn group_5_mnemonics() {
+    let ctx = long_mode();
+    for (code, mnemonic) in [
+        (&[0x48, 0xff, 0xc0][..], InsnMnemonic::Inc),
+        (&[0xff, 0x0b], InsnMnemonic::Dec),
+        (&[0xff, 0x33], InsnMnemonic::Push),
+    ] {
+        let insn = ctx.decode(code).unwrap();
+        assert_eq!(insn.length, code.len());
+        assert_eq!(insn.mnemonic, mnemonic);
+    }
+    // /7 is undefined.
+    assert!(ctx.decode(&[0xff, 0x38]).is_err());
+}
+
+#[test]
+fn decoded_insn_reports_its_prefixes() {
+    let ctx = long_mode();
+    // mov ax, cx
+    let insn = ctx.decode(&[0x66, 0x89, 0xc8]).unwrap();
+    assert!(insn
+        .prefixes
+        .legacy
+        .contains(LegacyPrefix::OperandSizeOverride));
+    assert_eq!(insn.prefixes.rex, None);
+    assert_eq!(
+        insn.translation,
+        translation_of(&[Insn::new(
+            Opcode::Move,
+            Reg::Rax.operand(OperandSize::B2),
+            Reg::Rcx.operand(OperandSize::B2)
+        )])
+    );
+
+    // mov eax, ecx
+    let insn = ctx.decode(&[0x89, 0xc8]).unwrap();
+    assert!(LegacyPrefix::iter().all(|prefix| !insn.prefixes.legacy.contains(prefix)));
+}
+
+#[test]
+fn byte_reader_reads_past_the_end_are_unexpected_eof() {
+    let mut reader = ByteReader::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    assert_eq!(reader.read_u8(), Ok(1));
+    assert_eq!(reader.read_u16(), Ok(0x0302));
+    assert_eq!(reader.position(), 3);
+    // a failed read doesn't advance the reader.
+    assert_eq!(reader.read_u64(), Err(TranslateError::UnexpectedEof));
+    assert_eq!(reader.position(), 3);
+    assert_eq!(reader.read_u32(), Ok(0x07060504));
+    assert_eq!(reader.peek_u8(), Some(8));
+    assert_eq!(reader.read_u32(), Err(TranslateError::UnexpectedEof));
+    reader.skip(2).unwrap();
+    assert_eq!(reader.peek_u8(), None);
+    assert_eq!(reader.read_u8(), Err(TranslateError::UnexpectedEof));
+}
+
+#[test]
+fn truncated_instructions_are_errors() {
+    let ctx = long_mode();
+    for code in [
+        &[0x66][..],
+        &[0x48, 0x8b],
+        &[0x48, 0x8b, 0x04],
+        &[0xe8, 0, 0, 0],
+        &[0x0f],
+        &[0x0f, 0x1f, 0x84, 0, 0, 0],
+    ] {
+        assert_eq!(
+            ctx.decode(code).map(|insn| insn.length),
+            Err(TranslateError::TruncatedInstruction),
+            "{code:x?}"
+        );
+    }
+    assert_eq!(ctx.decode(&[0xe8, 0, 0, 0, 0]).unwrap().length, 5);
+    assert_eq!(
+        ctx.decode(&[0x0f, 0x1f, 0x84, 0, 0, 0, 0, 0])
+            .unwrap()
+            .length,
+        8
+    );
+}